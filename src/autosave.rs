@@ -0,0 +1,50 @@
+use crate::prefs::Prefs;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+const AUTOSAVE_DIR: &str = "logs/autosave";
+
+// decides when to write a backup of the session, and which of the backup files to overwrite
+pub struct Autosave {
+    every_changes: usize,
+    interval: Duration,
+    count: usize,
+    saved_changes: usize,
+    saved_at: Instant,
+}
+
+impl Autosave {
+    pub fn new(prefs: &Prefs) -> Self {
+        Autosave {
+            every_changes: prefs.autosave_moves,
+            interval: Duration::from_secs(prefs.autosave_seconds),
+            count: prefs.autosave_count,
+            saved_changes: 0,
+            saved_at: Instant::now(),
+        }
+    }
+
+    // whether a backup should be written now, given the number of changes made to the session.
+    // nothing is written if nothing changed.
+    pub fn due(&self, changes: usize) -> bool {
+        if self.count == 0 || changes == self.saved_changes {
+            return false;
+        }
+        (self.every_changes > 0 && changes - self.saved_changes >= self.every_changes)
+            || (!self.interval.is_zero() && self.saved_at.elapsed() >= self.interval)
+    }
+
+    pub fn record(&mut self, changes: usize) {
+        self.saved_changes = changes;
+        self.saved_at = Instant::now();
+    }
+
+    // the backup file that is missing or was written longest ago, so the rotation carries on
+    // between sessions
+    pub fn next_path(&self) -> PathBuf {
+        (0..self.count)
+            .map(|i| PathBuf::from(format!("{AUTOSAVE_DIR}/backup-{i}.log")))
+            .min_by_key(|path| std::fs::metadata(path).and_then(|m| m.modified()).ok())
+            .expect("count is positive")
+    }
+}
@@ -0,0 +1,158 @@
+use std::path::PathBuf;
+
+// commands typed after the command key, for things that don't need a key of their own. a name
+// can be shortened to any start of it that no other name has, so q is quit.
+pub enum Command {
+    // save to the current file, or to a new one that later saves use
+    Save(Option<PathBuf>),
+    // scramble with this many moves, or the usual number
+    Scramble(Option<usize>),
+    // scramble only the pieces the current filter shows
+    Partial,
+    // show the moves that make the scramble
+    Sequence,
+    // use a filter without adding it to the list, or go back to the list with nothing
+    Filter(String),
+    // undo or redo until this many moves are made, or None to redo everything
+    Seek(Option<usize>),
+    // the comment saved in the log, or none
+    Comment(String),
+    // write the scramble and moves as notation, next to the log if there's no file
+    Export(Option<PathBuf>),
+    // make the moves in a file of notation
+    Apply(PathBuf),
+    // end the trial, keeping the moves made in it
+    Keep,
+    // start memorizing for a blindfolded solve, then hide the colors, then show them again
+    Blindfold,
+    // pick out the next cycle of pieces that aren't solved
+    Cycles,
+    // pick out where the piece with the colors of these sides is and where it belongs
+    Locate(String),
+    // how many pieces of each type are solved
+    Progress,
+    // undo every move one at a time, back to the scramble
+    Rewind,
+    // only let these sides turn, or all of them with nothing
+    Only(String),
+    // how many times these moves, or the last ones typed or made with an alg key, are made
+    // before the puzzle is back where it started
+    Order(String),
+    // keep the puzzle as it is to compare with, and highlight the stickers that change from it
+    Keyframe,
+    // highlight the stickers that changed since the keyframe, or stop
+    Diff,
+    // draw the solved puzzle or the scramble next to this one, or nothing with None. asking for
+    // the one already drawn stops drawing it.
+    Reference(Option<Reference>),
+    // with force, quit even with unsaved changes
+    Quit { force: bool },
+}
+
+// the puzzles the reference command can draw
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Reference {
+    Solved,
+    Scramble,
+}
+
+const NAMES: &[&str] = &[
+    "save",
+    "scramble",
+    "partial",
+    "sequence",
+    "filter",
+    "seek",
+    "comment",
+    "export",
+    "apply",
+    "keep",
+    "blindfold",
+    "cycles",
+    "locate",
+    "progress",
+    "rewind",
+    "order",
+    "only",
+    "reference",
+    "keyframe",
+    "diff",
+    "quit",
+];
+
+impl Command {
+    pub fn parse(st: &str) -> Result<Self, String> {
+        let st = st.trim();
+        let (word, arg) = match st.split_once(char::is_whitespace) {
+            Some((word, arg)) => (word, arg.trim()),
+            None => (st, ""),
+        };
+        let (word, force) = match word.strip_suffix('!') {
+            Some(word) => (word, true),
+            None => (word, false),
+        };
+        if word.is_empty() {
+            return Err(format!("type one of {}", NAMES.join(", ")));
+        }
+
+        let found: Vec<&str> = NAMES
+            .iter()
+            .copied()
+            .filter(|name| name.starts_with(word))
+            .collect();
+        let name = match found[..] {
+            [] => return Err(format!("no command named {word}")),
+            [name] => name,
+            _ => return Err(format!("{word} could be {}", found.join(" or "))),
+        };
+        if force && name != "quit" {
+            return Err(format!("{name} can't end with !"));
+        }
+
+        let number = |what: &str| {
+            arg.parse::<usize>()
+                .map_err(|_| format!("{name} takes {what} instead of {arg}"))
+        };
+        match name {
+            "save" => Ok(Command::Save((!arg.is_empty()).then(|| PathBuf::from(arg)))),
+            "scramble" if arg.is_empty() => Ok(Command::Scramble(None)),
+            "scramble" => Ok(Command::Scramble(Some(number("a number of moves")?))),
+            "filter" => Ok(Command::Filter(arg.to_string())),
+            "seek" if arg.is_empty() => Err("seek takes a move number, start, or end".to_string()),
+            "seek" if arg == "start" => Ok(Command::Seek(Some(0))),
+            "seek" if arg == "end" => Ok(Command::Seek(None)),
+            "seek" => Ok(Command::Seek(Some(number("a move number, start, or end")?))),
+            "comment" => Ok(Command::Comment(arg.to_string())),
+            "apply" if arg.is_empty() => Err("apply takes a file of moves".to_string()),
+            "apply" => Ok(Command::Apply(PathBuf::from(arg))),
+            "export" => Ok(Command::Export(
+                (!arg.is_empty()).then(|| PathBuf::from(arg)),
+            )),
+            "locate" if arg.is_empty() => {
+                Err("locate takes the sides of a piece, like UFR".to_string())
+            }
+            "locate" => Ok(Command::Locate(arg.to_string())),
+            "order" => Ok(Command::Order(arg.to_string())),
+            "only" => Ok(Command::Only(arg.to_string())),
+            "reference" => match arg {
+                "" | "solved" => Ok(Command::Reference(Some(Reference::Solved))),
+                "scramble" => Ok(Command::Reference(Some(Reference::Scramble))),
+                "off" => Ok(Command::Reference(None)),
+                _ => Err(format!(
+                    "reference takes solved, scramble, or off instead of {arg}"
+                )),
+            },
+            _ if !arg.is_empty() => Err(format!("{name} doesn't take {arg}")),
+            "keep" => Ok(Command::Keep),
+            "partial" => Ok(Command::Partial),
+            "sequence" => Ok(Command::Sequence),
+            "blindfold" => Ok(Command::Blindfold),
+            "cycles" => Ok(Command::Cycles),
+            "progress" => Ok(Command::Progress),
+            "rewind" => Ok(Command::Rewind),
+            "keyframe" => Ok(Command::Keyframe),
+            "diff" => Ok(Command::Diff),
+            _ => Ok(Command::Quit { force }),
+        }
+    }
+}
@@ -0,0 +1,137 @@
+use crate::puzzle::{Custom, Puzzle, Turn};
+use crate::{AppLog, Blindfold, Group, LogMetadata, Marker, ScrambleSource, Split};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::time::Duration;
+
+// logs ending in .clog keep the scramble as how it was made and the moves as notation, instead
+// of every sticker and every turn, which makes them much smaller. only the moves to where the log
+// was saved are kept, not the branches that were undone.
+pub fn is_compact(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "clog")
+}
+
+#[derive(Serialize, Deserialize)]
+struct CompactLog {
+    n: i16,
+    d: u16,
+    // the layers along each axis, only kept for hypercuboids
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    shape: Option<Vec<i16>>,
+    void: bool,
+    #[serde(rename = "super")]
+    is_super: bool,
+    #[serde(default, skip_serializing_if = "Custom::is_empty")]
+    custom: Custom,
+    scramble_source: ScrambleSource,
+    // only kept when the scramble can't be made again from scramble_source
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    scramble: Option<Puzzle>,
+    // in notation with a, b, c, ... for the positive sides and A, B, C, ... for the negative
+    // ones, so that it doesn't depend on the names in the prefs
+    moves: String,
+    #[serde(default)]
+    splits: Vec<Split>,
+    #[serde(default)]
+    markers: Vec<Marker>,
+    #[serde(default)]
+    groups: Vec<Group>,
+    #[serde(default)]
+    time: Option<Duration>,
+    #[serde(default)]
+    blindfold: Option<Blindfold>,
+    #[serde(default)]
+    metadata: Option<LogMetadata>,
+}
+
+const MAX_DIM: u16 = 26;
+
+fn side_name(side: i16) -> char {
+    if side >= 0 {
+        (b'a' + side as u8) as char
+    } else {
+        (b'A' + !side as u8) as char
+    }
+}
+
+fn parse_side(ch: char) -> Option<i16> {
+    match ch {
+        'a'..='z' => Some(ch as i16 - 'a' as i16),
+        'A'..='Z' => Some(!(ch as i16 - 'A' as i16)),
+        _ => None,
+    }
+}
+
+pub fn write(app_log: &AppLog, writer: impl Write) -> Result<(), Box<dyn Error>> {
+    let scramble = &app_log.scramble;
+    if scramble.d > MAX_DIM {
+        return Err(format!("compact logs can be at most {MAX_DIM} dimensions").into());
+    }
+    let remade = app_log.scramble_source.remake(scramble.make_solved_like());
+    let compact = CompactLog {
+        n: scramble.n,
+        d: scramble.d,
+        shape: (!scramble.is_cube()).then(|| scramble.shape().to_vec()),
+        void: scramble.void,
+        is_super: scramble.is_super(),
+        custom: scramble.custom().clone(),
+        scramble_source: app_log.scramble_source.clone(),
+        scramble: (remade.as_ref() != Some(scramble)).then(|| scramble.clone()),
+        moves: app_log
+            .current_moves()
+            .iter()
+            .map(|turn| turn.to_notation_with(scramble.shape(), &side_name))
+            .collect::<Vec<_>>()
+            .join(" "),
+        splits: app_log.splits.clone(),
+        markers: app_log.markers.clone(),
+        groups: app_log.groups.clone(),
+        time: app_log.time,
+        blindfold: app_log.blindfold.clone(),
+        metadata: app_log.metadata.clone(),
+    };
+    serde_json::to_writer(writer, &compact)?;
+    Ok(())
+}
+
+pub fn read(reader: impl Read) -> Result<AppLog, Box<dyn Error>> {
+    let compact: CompactLog = serde_json::from_reader(reader)?;
+    let scramble = match compact.scramble {
+        Some(scramble) => scramble,
+        None => {
+            let mut solved = match &compact.shape {
+                Some(shape) => Puzzle::make_solved_shape(shape),
+                None => Puzzle::make_solved(compact.n, compact.d),
+            };
+            solved.void = compact.void;
+            if compact.is_super {
+                solved.make_super();
+            }
+            solved.customize(compact.custom);
+            compact
+                .scramble_source
+                .remake(solved)
+                .ok_or("the log has no scramble and no way to make it again")?
+        }
+    };
+    let moves = compact
+        .moves
+        .split_whitespace()
+        .map(|st| Turn::parse_with(st, scramble.shape(), &parse_side))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(AppLog {
+        scramble,
+        moves,
+        history: None,
+        notation: "".to_string(),
+        splits: compact.splits,
+        markers: compact.markers,
+        groups: compact.groups,
+        scramble_source: compact.scramble_source,
+        time: compact.time,
+        blindfold: compact.blindfold,
+        metadata: compact.metadata,
+    })
+}
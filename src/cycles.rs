@@ -0,0 +1,159 @@
+use crate::notation::{parse_side, side_name};
+use crate::prefs::Prefs;
+use crate::puzzle::{ax, Piece, Puzzle};
+use std::collections::{HashMap, HashSet};
+
+// a slot for a piece, by its position and the sides of the piece that belongs there, like UFR
+pub type Slot = (Vec<i16>, String);
+
+// the pieces that aren't solved, as cycles of slots where the piece in each slot belongs in the
+// next one, and the pieces that are in their slots but twisted
+pub struct Cycles {
+    pub cycles: Vec<Vec<Slot>>,
+    pub twisted: Vec<Slot>,
+}
+
+// the colors of a piece, in the same order whatever its orientation
+fn color_set(piece: &Piece) -> Vec<i16> {
+    let mut colors = piece.colors();
+    colors.sort();
+    colors
+}
+
+// the sides of the piece's stickers, like UFR
+fn slot_name(prefs: &Prefs, piece: &Piece) -> String {
+    piece
+        .orientation
+        .iter()
+        .map(|&(side, _)| side_name(prefs, side))
+        .collect()
+}
+
+// the face colors, if no two sides are being solved to the same color
+fn distinct_face_colors(puzzle: &Puzzle) -> Result<HashMap<i16, i16>, String> {
+    let face_colors = puzzle.face_colors();
+    // on puzzles without centers, two sides can be mostly the same color
+    let distinct: HashSet<i16> = face_colors.values().copied().collect();
+    if distinct.len() < face_colors.len() {
+        return Err(
+            "two sides are being solved to the same color, so pieces have no slots yet".to_string(),
+        );
+    }
+    Ok(face_colors)
+}
+
+// only works when no two pieces have the same colors, like on puzzles with at most 3 layers
+pub fn find(puzzle: &Puzzle, prefs: &Prefs) -> Result<Cycles, String> {
+    let face_colors = distinct_face_colors(puzzle)?;
+    let counts = |piece: &Piece| !piece.orientation.is_empty() && !puzzle.is_void(&piece.position);
+
+    // the slot that each set of colors belongs in, going by the colors the sides are solved to
+    let solved = puzzle.make_solved_like();
+    let mut homes: HashMap<Vec<i16>, Vec<i16>> = HashMap::new();
+    let mut names: HashMap<Vec<i16>, String> = HashMap::new();
+    for piece in solved.pieces().iter().filter(|piece| counts(piece)) {
+        let mut colors: Vec<i16> = piece
+            .orientation
+            .iter()
+            .map(|&(side, _)| face_colors.get(&side).copied().unwrap_or(side))
+            .collect();
+        colors.sort();
+        if homes.insert(colors, piece.position.clone()).is_some() {
+            return Err("some pieces have the same colors, so they don't make cycles".to_string());
+        }
+        names.insert(piece.position.clone(), slot_name(prefs, piece));
+    }
+    let slot = |position: &Vec<i16>| (position.clone(), names[position].clone());
+
+    let mut next: HashMap<Vec<i16>, Vec<i16>> = HashMap::new();
+    let mut twisted = vec![];
+    for piece in puzzle.pieces().iter().filter(|piece| counts(piece)) {
+        if piece.is_solved(&face_colors) {
+            continue;
+        }
+        let Some(home) = homes.get(&color_set(piece)) else {
+            return Err("a piece has colors that no slot has".to_string());
+        };
+        if *home == piece.position {
+            twisted.push(slot(home));
+        } else {
+            next.insert(piece.position.clone(), home.clone());
+        }
+    }
+
+    // follow each slot to where its piece belongs until coming back around
+    let mut starts: Vec<&Vec<i16>> = next.keys().collect();
+    starts.sort();
+    let mut seen = HashSet::new();
+    let mut cycles = vec![];
+    for start in starts {
+        let mut cycle = vec![];
+        let mut position = start;
+        while seen.insert(position.clone()) {
+            cycle.push(slot(position));
+            match next.get(position) {
+                Some(home) => position = home,
+                None => break,
+            }
+        }
+        if !cycle.is_empty() {
+            cycles.push(cycle);
+        }
+    }
+    Ok(Cycles { cycles, twisted })
+}
+
+// where the pieces with the colors of these sides are, and the slots they belong in, both named
+// like UFR. bigger puzzles can have several pieces with the same colors.
+pub struct Located {
+    pub current: Vec<Slot>,
+    pub solved: Vec<Slot>,
+}
+
+pub fn locate(puzzle: &Puzzle, prefs: &Prefs, sides: &str) -> Result<Located, String> {
+    let mut wanted = vec![];
+    for ch in sides.chars() {
+        let side = parse_side(prefs, ch)
+            .filter(|&side| ax(side) < puzzle.d as i16)
+            .ok_or(format!("{ch} isn't a side"))?;
+        if wanted.contains(&side) {
+            return Err(format!("{ch} is there twice"));
+        }
+        if wanted.contains(&!side) {
+            return Err(format!(
+                "a piece can't have both {ch} and {}",
+                side_name(prefs, !side)
+            ));
+        }
+        wanted.push(side);
+    }
+    wanted.sort();
+
+    let face_colors = distinct_face_colors(puzzle)?;
+    let mut colors: Vec<i16> = wanted.iter().map(|side| face_colors[side]).collect();
+    colors.sort();
+    let slot = |piece: &Piece| (piece.position.clone(), slot_name(prefs, piece));
+    let pieces: Vec<Piece> = puzzle
+        .pieces()
+        .into_iter()
+        .filter(|piece| !puzzle.is_void(&piece.position))
+        .collect();
+    let current: Vec<Slot> = pieces
+        .iter()
+        .filter(|piece| color_set(piece) == colors)
+        .map(slot)
+        .collect();
+    let solved: Vec<Slot> = pieces
+        .iter()
+        .filter(|piece| {
+            let mut faced: Vec<i16> = piece.orientation.iter().map(|&(side, _)| side).collect();
+            faced.sort();
+            faced == wanted
+        })
+        .map(slot)
+        .collect();
+    if current.is_empty() {
+        return Err(format!("no piece has the colors of {sides}"));
+    }
+    Ok(Located { current, solved })
+}
@@ -0,0 +1,89 @@
+use crate::notation::parse_side;
+use crate::prefs::Prefs;
+use crate::puzzle::{ax, Custom, Puzzle};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+// a puzzle definition file, in JSON, for a puzzle that isn't a plain hypercube. sides are named
+// like in the prefs.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Definition {
+    // the layers along each axis
+    pub size: Vec<i16>,
+    #[serde(default)]
+    void: bool,
+    #[serde(default, rename = "super")]
+    is_super: bool,
+    // the piece types taken out, by how many stickers they have
+    #[serde(default)]
+    removed: Vec<usize>,
+    // a side of each axis that can turn, or every axis if this is left out
+    #[serde(default)]
+    turn: Option<Vec<char>>,
+    // sides that are solved with the color of another side
+    #[serde(default)]
+    colors: BTreeMap<char, char>,
+}
+
+pub fn read_file(path: &Path) -> Result<Definition, Box<dyn Error>> {
+    let file = File::open(path)
+        .map_err(|err| format!("could not open puzzle {}: {err}", path.display()))?;
+    serde_json::from_reader(BufReader::new(file))
+        .map_err(|err| format!("could not read puzzle {}: {err}", path.display()).into())
+}
+
+impl Definition {
+    pub fn d(&self) -> u16 {
+        self.size.len() as u16
+    }
+
+    // the solved puzzle, where the prefs already have axes for its dimension
+    pub fn make(&self, prefs: &Prefs) -> Result<Puzzle, String> {
+        let d = self.d();
+        let side = |name: char| match parse_side(prefs, name) {
+            Some(side) if ax(side) < d as i16 => Ok(side),
+            _ => Err(format!("{name} is not a side of a {d}D puzzle")),
+        };
+
+        if let Some(&count) = self.removed.iter().find(|&&count| count > d as usize) {
+            return Err(format!("a {d}D puzzle has no pieces with {count} stickers"));
+        }
+        let turn_axes = match &self.turn {
+            Some(names) if names.is_empty() => {
+                return Err("turn needs a side of at least one axis".to_string())
+            }
+            Some(names) => Some(
+                names
+                    .iter()
+                    .map(|&name| Ok(ax(side(name)?) as u16))
+                    .collect::<Result<Vec<_>, String>>()?,
+            ),
+            None => None,
+        };
+        let colors = self
+            .colors
+            .iter()
+            .map(|(&name, &color)| Ok((side(name)?, side(color)?)))
+            .collect::<Result<Vec<_>, String>>()?;
+
+        let mut puzzle = Puzzle::make_solved_shape(&self.size);
+        puzzle.void = self.void;
+        if self.is_super {
+            if d < 2 {
+                return Err("supercubes need at least 2 dimensions".to_string());
+            }
+            puzzle.make_super();
+        }
+        puzzle.customize(Custom {
+            removed: self.removed.clone(),
+            turn_axes,
+            colors,
+        });
+        Ok(puzzle)
+    }
+}
@@ -1,107 +1,589 @@
+use crate::notation::parse_side;
+use crate::prefs;
+use crate::puzzle::{ax, Piece, Puzzle};
 use crate::Prefs;
+use crossterm::style::Color;
+use std::collections::HashMap;
+use std::path::Path;
 
-pub const DIGITS: &'static str = "0123456789&";
+pub const DIGITS: &str = "0123456789&";
+// selects the pieces that are solved
+pub const SOLVED: char = '*';
+// followed by a side and a layer, like @R1, selects the pieces in that layer
+pub const LAYER: char = '@';
 
 #[derive(Debug, Clone)]
 enum FilterSelector {
     Side(i16),   // color
     Type(usize), // number of stickers
+    Solved,
+    // layer counted from the outside of the side starting at 1
+    Layer { side: i16, layer: i16 },
+}
+
+// what filters need to know about the puzzle besides the piece
+pub struct FilterContext {
+    shape: Vec<i16>,
+    // the color each side is being solved to
+    pub face_colors: HashMap<i16, i16>,
+}
+
+impl FilterContext {
+    pub fn new(puzzle: &Puzzle) -> Self {
+        FilterContext {
+            shape: puzzle.shape().to_vec(),
+            face_colors: puzzle.face_colors(),
+        }
+    }
+}
+
+// selectors combined with and, or, and not
+#[derive(Debug, Clone)]
+enum FilterExpr {
+    Selector(FilterSelector),
+    Not(Box<FilterExpr>),
+    // matches everything when empty
+    And(Vec<FilterExpr>),
+    Or(Vec<FilterExpr>),
+}
+
+pub const FULL_LEVEL: u8 = 100;
+// after % instead of a level, hides the pieces
+pub const HIDDEN: char = '-';
+// after % and followed by six hex digits instead of a level, draws the pieces in that color
+pub const RECOLOR: char = '#';
+
+// how the pieces matching a clause are drawn
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FilterStyle {
+    Hidden,
+    // brightness from 0 (the filtered color) to 100 (full)
+    Level(u8),
+    Color(Color),
+}
+
+impl FilterStyle {
+    // a piece matching several clauses is drawn in the most visible style
+    fn visibility(&self) -> u16 {
+        match self {
+            FilterStyle::Hidden => 0,
+            FilterStyle::Level(level) => *level as u16 + 1,
+            FilterStyle::Color(_) => FULL_LEVEL as u16 + 2,
+        }
+    }
+
+    pub fn is_shown(&self) -> bool {
+        !matches!(self, FilterStyle::Hidden | FilterStyle::Level(0))
+    }
 }
 
 #[derive(Debug, Clone)]
-struct FilterSelectorBool {
-    have: bool,
-    selector: FilterSelector,
+struct FilterClause {
+    expr: FilterExpr,
+    style: FilterStyle,
 }
 
-// filters are of the form F!U+FB
-// (true: i16) = must have color i16
-// (false: i16) = must not have color i16
-// disjunction of conjunctions
+// filters are of the form F!U+FB%50, optionally named like "last layer: F!U+FB%50", with
+// parentheses, & and | for more complicated ones like (F|U)!(R&B)
+// disjunction of clauses
 #[derive(Debug, Clone)]
-pub struct Filter(Vec<Vec<FilterSelectorBool>>);
+pub struct Filter {
+    clauses: Vec<FilterClause>,
+    pub name: Option<String>,
+    // the filter as it was written, without the name
+    pub source: String,
+}
 
 impl Default for Filter {
     fn default() -> Self {
-        Filter(vec![vec![]])
+        Filter {
+            clauses: vec![FilterClause {
+                expr: FilterExpr::And(vec![]),
+                style: FilterStyle::Level(FULL_LEVEL),
+            }],
+            name: None,
+            source: "".to_string(),
+        }
+    }
+}
+
+// one filter per line, skipping comments. every line that doesn't parse is reported.
+pub fn read_file(path: &Path, prefs: &Prefs) -> Result<Vec<Filter>, Vec<String>> {
+    let filters_str = std::fs::read_to_string(path)
+        .map_err(|err| vec![format!("could not read {}: {err}", path.display())])?;
+    let mut filters = vec![];
+    let mut errors = vec![];
+    for (i, line) in filters_str.lines().enumerate() {
+        if line.starts_with('#') {
+            continue;
+        }
+        match Filter::parse(line, prefs) {
+            Ok(filter) => filters.push(filter),
+            Err(err) => errors.push(format!("line {}: {err}", i + 1)),
+        }
+    }
+    if errors.is_empty() {
+        Ok(filters)
+    } else {
+        Err(errors)
     }
 }
 
 impl Filter {
     pub fn parse(st: &str, prefs: &Prefs) -> Result<Self, String> {
-        let mut filter = Filter(vec![]);
+        let (name, st) = match st.rsplit_once(':') {
+            Some((name, st)) => (Some(name.trim().to_string()), st),
+            None => (None, st),
+        };
+        let mut parser = Parser {
+            chars: st
+                .chars()
+                .enumerate()
+                .filter(|(_, c)| !c.is_whitespace())
+                .collect(),
+            pos: 0,
+            st: st.trim(),
+            offset: st.chars().take_while(|c| c.is_whitespace()).count(),
+            prefs,
+        };
+        let clauses = parser.clauses()?;
+        Ok(Filter {
+            clauses,
+            name,
+            source: st.trim().to_string(),
+        })
+    }
+}
 
-        for tst in st.split('+') {
-            let mut filter_sides = vec![];
+// recursive descent over the characters of a filter, ignoring whitespace
+//   clauses = clause ('+' clause)*
+//   clause  = or ('%' (level | '-' | '#' hex))?
+//   or      = and ('|' and)*         ('+' also means or inside parentheses)
+//   and     = (['&'] ['!'] item)*    (items after a ! are each negated)
+//   item    = selector | '(' or ')'
+struct Parser<'a> {
+    // index in the string of each character that isn't whitespace
+    chars: Vec<(usize, char)>,
+    pos: usize,
+    st: &'a str,
+    // leading whitespace that st doesn't include
+    offset: usize,
+    prefs: &'a Prefs,
+}
 
-            let haves: &str;
-            let have_nots: &str;
-            match tst.trim().split('!').collect::<Vec<_>>()[..] {
-                [a] => {
-                    haves = a;
-                    have_nots = "";
-                }
-                [a, b] => {
-                    haves = a;
-                    have_nots = b;
+impl Parser<'_> {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).map(|&(_, c)| c)
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        let found = self.peek() == Some(c);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    // points at the character the parser is at
+    fn error(&self, msg: &str) -> String {
+        match self.chars.get(self.pos) {
+            Some(&(i, _)) => format!("{msg} at character {} of {}", i - self.offset + 1, self.st),
+            None => format!("{msg} at the end of {}", self.st),
+        }
+    }
+
+    fn starts_item(&self, c: char) -> bool {
+        c == '('
+            || c == SOLVED
+            || c == LAYER
+            || DIGITS.contains(c)
+            || parse_side(self.prefs, c).is_some()
+    }
+
+    fn clauses(&mut self) -> Result<Vec<FilterClause>, String> {
+        let mut clauses = vec![self.clause()?];
+        while self.eat('+') {
+            clauses.push(self.clause()?);
+        }
+        match self.peek() {
+            Some(')') => Err(self.error("unmatched )")),
+            Some(c) => Err(self.error(&format!("unexpected {c}"))),
+            None => Ok(clauses),
+        }
+    }
+
+    fn clause(&mut self) -> Result<FilterClause, String> {
+        let expr = self.or(false)?;
+        let style = if !self.eat('%') {
+            FilterStyle::Level(FULL_LEVEL)
+        } else if self.eat(HIDDEN) {
+            FilterStyle::Hidden
+        } else if self.eat(RECOLOR) {
+            let hex = self.take_while(|c| c.is_ascii_hexdigit());
+            if hex.len() != 6 {
+                return Err(self.error(&format!("{RECOLOR} should be followed by six hex digits")));
+            }
+            FilterStyle::Color(prefs::hex(&hex).expect("hex digits parse"))
+        } else {
+            let start = self.pos;
+            let level_st = self.take_while(|c| c.is_ascii_digit());
+            if level_st.is_empty() {
+                return Err(self.error(&format!(
+                    "% should be followed by a level, {HIDDEN}, or {RECOLOR} and a color"
+                )));
+            }
+            match level_st.parse::<u8>() {
+                Ok(level) if level <= FULL_LEVEL => FilterStyle::Level(level),
+                _ => {
+                    self.pos = start;
+                    return Err(self.error(&format!("level should be at most {FULL_LEVEL}")));
                 }
-                _ => return Err("too many ! in string".to_string()),
             }
+        };
+        Ok(FilterClause { expr, style })
+    }
 
-            let mut add_sides = |have_st: &str, have: bool| -> Result<(), String> {
-                for ch in have_st.chars() {
-                    if ch.is_whitespace() {
-                        continue;
-                    }
+    fn take_while(&mut self, pred: impl Fn(char) -> bool) -> String {
+        let mut st = String::new();
+        while let Some(c) = self.peek().filter(|&c| pred(c)) {
+            st.push(c);
+            self.pos += 1;
+        }
+        st
+    }
 
-                    if let Some(ind) = prefs.axes.iter().position(|ax| ax.pos.name == ch) {
-                        filter_sides.push(FilterSelectorBool {
-                            have,
-                            selector: FilterSelector::Side(ind as i16),
-                        });
-                    } else if let Some(ind) = prefs.axes.iter().position(|ax| ax.neg.name == ch) {
-                        filter_sides.push(FilterSelectorBool {
-                            have,
-                            selector: FilterSelector::Side(!(ind as i16)),
-                        });
-                    } else if let Some(ind) = DIGITS.chars().position(|c| c == ch) {
-                        filter_sides.push(FilterSelectorBool {
-                            have,
-                            selector: FilterSelector::Type(ind),
-                        });
+    fn or(&mut self, nested: bool) -> Result<FilterExpr, String> {
+        let empty = |expr: &FilterExpr| matches!(expr, FilterExpr::And(items) if items.is_empty());
+        let mut terms = vec![self.and()?];
+        while let Some(c) = self.peek().filter(|&c| c == '|' || (nested && c == '+')) {
+            // a term with nothing in it matches everything, which is only useful on its own
+            if terms.last().is_some_and(empty) {
+                return Err(self.error(&format!("{c} should come after a term")));
+            }
+            self.pos += 1;
+            let term = self.and()?;
+            if empty(&term) {
+                return Err(self.error(&format!("{c} should be followed by a term")));
+            }
+            terms.push(term);
+        }
+        if nested && terms.last().is_some_and(empty) {
+            return Err(self.error("( should be followed by a term"));
+        }
+        Ok(if terms.len() == 1 {
+            terms.pop().unwrap()
+        } else {
+            FilterExpr::Or(terms)
+        })
+    }
+
+    fn and(&mut self) -> Result<FilterExpr, String> {
+        let mut items = vec![];
+        let mut negated = false;
+        loop {
+            match self.peek() {
+                Some('!') if negated => return Err(self.error("only one ! is allowed in a term")),
+                Some('!') => {
+                    negated = true;
+                    self.pos += 1;
+                }
+                // & between two items means and, anywhere else it's the digit for 10, so the
+                // first of && is still a digit like it was before & meant and
+                Some('&')
+                    if !items.is_empty()
+                        && self.chars.get(self.pos + 1).is_some_and(|&(_, c)| {
+                            c == '!' || (c != '&' && self.starts_item(c))
+                        }) =>
+                {
+                    self.pos += 1;
+                }
+                Some(c) if self.starts_item(c) => {
+                    let item = self.item()?;
+                    items.push(if negated {
+                        FilterExpr::Not(Box::new(item))
                     } else {
-                        return Err(format!("invalid character {ch}"));
-                    }
+                        item
+                    });
                 }
+                _ => break,
+            }
+        }
+        Ok(FilterExpr::And(items))
+    }
 
-                Ok(())
+    fn item(&mut self) -> Result<FilterExpr, String> {
+        if self.eat('(') {
+            let expr = self.or(true)?;
+            return match self.peek() {
+                Some(')') => {
+                    self.pos += 1;
+                    Ok(expr)
+                }
+                Some(c) => Err(self.error(&format!("expected ) instead of {c}"))),
+                None => Err(self.error("missing )")),
             };
-
-            add_sides(haves, true)?;
-            add_sides(have_nots, false)?;
-
-            filter.0.push(filter_sides)
         }
-
-        Ok(filter)
+        let ch = self.peek().expect("item starts with a character");
+        self.pos += 1;
+        let selector = if let Some(side) = parse_side(self.prefs, ch) {
+            FilterSelector::Side(side)
+        } else if let Some(ind) = DIGITS.chars().position(|c| c == ch) {
+            FilterSelector::Type(ind)
+        } else if ch == SOLVED {
+            FilterSelector::Solved
+        } else {
+            let Some(side) = self.peek().and_then(|c| parse_side(self.prefs, c)) else {
+                return Err(self.error(&format!("{LAYER} should be followed by a side")));
+            };
+            self.pos += 1;
+            let layer_st = self.take_while(|c| c.is_ascii_digit());
+            let Some(layer) = layer_st.parse::<i16>().ok().filter(|&layer| layer >= 1) else {
+                return Err(
+                    self.error(&format!("{LAYER} and a side should be followed by a layer"))
+                );
+            };
+            FilterSelector::Layer { side, layer }
+        };
+        Ok(FilterExpr::Selector(selector))
     }
 }
 
 impl FilterSelector {
-    fn matches_stickers(&self, colors: &[i16]) -> bool {
+    fn matches(&self, piece: &Piece, context: &FilterContext) -> bool {
         match self {
-            FilterSelector::Side(color) => colors.iter().any(|e| e == color),
-            FilterSelector::Type(n) => colors.len() == *n,
+            FilterSelector::Side(color) => piece.orientation.iter().any(|(_, e)| e == color),
+            FilterSelector::Type(n) => piece.orientation.len() == *n,
+            FilterSelector::Solved => piece.is_solved(&context.face_colors),
+            FilterSelector::Layer { side, layer } => {
+                let n = context.shape.get(ax(*side) as usize).copied().unwrap_or(0);
+                let coord = n + 1 - 2 * layer;
+                let coord = if *side >= 0 { coord } else { -coord };
+                piece.position[ax(*side) as usize] == coord
+            }
+        }
+    }
+}
+
+impl FilterExpr {
+    fn matches(&self, piece: &Piece, context: &FilterContext) -> bool {
+        match self {
+            FilterExpr::Selector(selector) => selector.matches(piece, context),
+            FilterExpr::Not(expr) => !expr.matches(piece, context),
+            FilterExpr::And(exprs) => exprs.iter().all(|expr| expr.matches(piece, context)),
+            FilterExpr::Or(exprs) => exprs.iter().any(|expr| expr.matches(piece, context)),
         }
     }
 }
 
 impl Filter {
-    pub fn matches_stickers(&self, colors: &[i16]) -> bool {
-        self.0.iter().any(|sides| {
-            sides
-                .iter()
-                .all(|side| side.selector.matches_stickers(colors) == side.have)
-        })
+    // the most visible style of all the clauses that match, or level 0 if none match. of
+    // several colors, the first one is used.
+    pub fn style(&self, piece: &Piece, context: &FilterContext) -> FilterStyle {
+        self.clauses
+            .iter()
+            .rev()
+            .filter(|clause| clause.expr.matches(piece, context))
+            .map(|clause| clause.style)
+            .max_by_key(|style| style.visibility())
+            .unwrap_or(FilterStyle::Level(0))
+    }
+
+    pub fn matches(&self, piece: &Piece, context: &FilterContext) -> bool {
+        self.style(piece, context).is_shown()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn prefs() -> Prefs {
+        Prefs::load(None).expect("default prefs load")
+    }
+
+    // how filters of the flat form A!B+C%50 matched before they had parentheses: every selector
+    // before the ! has to match and every one after it has to not match
+    fn flat_level(st: &str, prefs: &Prefs, piece: &Piece, context: &FilterContext) -> u8 {
+        let st = st.rsplit_once(':').map_or(st, |(_, st)| st);
+        let selector = |chars: &mut std::iter::Peekable<std::str::Chars>| {
+            let ch = chars.next()?;
+            Some(if let Some(side) = parse_side(prefs, ch) {
+                FilterSelector::Side(side)
+            } else if let Some(ind) = DIGITS.chars().position(|c| c == ch) {
+                FilterSelector::Type(ind)
+            } else if ch == SOLVED {
+                FilterSelector::Solved
+            } else {
+                let side = parse_side(prefs, chars.next()?)?;
+                let mut layer = String::new();
+                while let Some(digit) = chars.next_if(|c| c.is_ascii_digit()) {
+                    layer.push(digit);
+                }
+                FilterSelector::Layer {
+                    side,
+                    layer: layer.parse().ok()?,
+                }
+            })
+        };
+        st.split('+')
+            .filter_map(|term| {
+                let (term, level) = match term.split_once('%') {
+                    Some((term, level)) => (term, level.trim().parse().unwrap()),
+                    None => (term, FULL_LEVEL),
+                };
+                let (haves, have_nots) = term.split_once('!').unwrap_or((term, ""));
+                let all = |st: &str, have: bool| {
+                    let st: String = st.chars().filter(|c| !c.is_whitespace()).collect();
+                    let mut chars = st.chars().peekable();
+                    let mut matched = true;
+                    while chars.peek().is_some() {
+                        let selector = selector(&mut chars).expect("flat filter is valid");
+                        matched &= selector.matches(piece, context) == have;
+                    }
+                    matched
+                };
+                (all(haves, true) && all(have_nots, false)).then_some(level)
+            })
+            .max()
+            .unwrap_or(0)
+    }
+
+    fn assert_same_as_flat(puzzle: &Puzzle, filters: &[&str]) {
+        let prefs = prefs();
+        let context = FilterContext::new(puzzle);
+        for st in filters {
+            let filter = Filter::parse(st, &prefs).unwrap();
+            for piece in puzzle.pieces() {
+                assert_eq!(
+                    filter.style(&piece, &context),
+                    FilterStyle::Level(flat_level(st, &prefs, &piece, &context)),
+                    "{st} on {piece:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn flat_filters_match_as_before() {
+        let mut puzzle = Puzzle::make_solved(3, 4);
+        puzzle.scramble(40, &mut StdRng::seed_from_u64(1));
+        assert_same_as_flat(
+            &puzzle,
+            &[
+                "",
+                "R",
+                "RU",
+                "R!U",
+                "!R",
+                "F!UR",
+                "RU!F+B%50",
+                "2!*",
+                "3 R ! U",
+                "last layer: U!*",
+                "@R1!U",
+                "@L2U+R%0",
+                "FU+F%50+U%10",
+                "4+3%20+2!O%40",
+                "*+%30",
+            ],
+        );
+    }
+
+    #[test]
+    fn ten_is_still_a_digit() {
+        let mut puzzle = Puzzle::make_solved(2, 10);
+        puzzle.scramble(20, &mut StdRng::seed_from_u64(2));
+        assert_same_as_flat(&puzzle, &["&", "&&", "R&", "&R", "R&&U", "R!&", "!&R"]);
+    }
+
+    fn error(st: &str) -> String {
+        Filter::parse(st, &prefs()).unwrap_err()
+    }
+
+    #[test]
+    fn errors_point_at_the_mistake() {
+        assert_eq!(error("(R!U"), "missing ) at the end of (R!U");
+        assert_eq!(error("R!(U"), "missing ) at the end of R!(U");
+        assert_eq!(error("R)"), "unmatched ) at character 2 of R)");
+        assert_eq!(error("(R|U))"), "unmatched ) at character 6 of (R|U))");
+        assert_eq!(
+            error("(R U%50)"),
+            "expected ) instead of % at character 5 of (R U%50)"
+        );
+        assert_eq!(
+            error("()"),
+            "( should be followed by a term at character 2 of ()"
+        );
+        assert_eq!(
+            error("R&(U|)"),
+            "| should be followed by a term at character 6 of R&(U|)"
+        );
+        assert_eq!(error("R&(U"), "missing ) at the end of R&(U");
+        assert_eq!(
+            error("R|"),
+            "| should be followed by a term at the end of R|"
+        );
+        assert_eq!(
+            error("|R"),
+            "| should come after a term at character 1 of |R"
+        );
+        assert_eq!(
+            error("R&|"),
+            "| should be followed by a term at the end of R&|"
+        );
+        assert_eq!(
+            error("(R||U)"),
+            "| should be followed by a term at character 4 of (R||U)"
+        );
+        assert_eq!(
+            error("(R+)"),
+            "+ should be followed by a term at character 4 of (R+)"
+        );
+        assert_eq!(
+            error("!!R"),
+            "only one ! is allowed in a term at character 2 of !!R"
+        );
+        assert_eq!(
+            error("R!U!F"),
+            "only one ! is allowed in a term at character 4 of R!U!F"
+        );
+        assert_eq!(
+            error("(!R!U)"),
+            "only one ! is allowed in a term at character 4 of (!R!U)"
+        );
+        assert_eq!(
+            error("  R%101"),
+            "level should be at most 100 at character 3 of R%101"
+        );
+        assert_eq!(
+            error("name: R@"),
+            "@ should be followed by a side at the end of R@"
+        );
+    }
+
+    #[test]
+    fn groups_combine() {
+        let prefs = prefs();
+        let mut puzzle = Puzzle::make_solved(3, 3);
+        puzzle.scramble(30, &mut StdRng::seed_from_u64(3));
+        let context = FilterContext::new(&puzzle);
+        let side = |name| parse_side(&prefs, name).unwrap();
+        let has = |piece: &Piece, name| piece.orientation.iter().any(|&(_, c)| c == side(name));
+        let check = |st: &str, expected: &dyn Fn(&Piece) -> bool| {
+            let filter = Filter::parse(st, &prefs).unwrap();
+            for piece in puzzle.pieces() {
+                assert_eq!(filter.matches(&piece, &context), expected(&piece), "{st}");
+            }
+        };
+        check("(R|U)!(F|L)", &|p| {
+            (has(p, 'R') || has(p, 'U')) && !has(p, 'F') && !has(p, 'L')
+        });
+        check("R&!U", &|p| has(p, 'R') && !has(p, 'U'));
+        check("!(R&U)", &|p| !(has(p, 'R') && has(p, 'U')));
+        check("(R+U)2", &|p| {
+            (has(p, 'R') || has(p, 'U')) && p.orientation.len() == 2
+        });
     }
 }
@@ -0,0 +1,317 @@
+use crate::definition;
+use crate::metrics::{phases, MoveCounts, Phase};
+use crate::notation::{self, Alg};
+use crate::prefs::Prefs;
+use crate::puzzle::Puzzle;
+use crate::solver;
+use crate::timer::format_duration;
+use crate::{check_shape, check_size, read_log, AppLog, ScrambleSource};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::io::Read;
+use std::path::Path;
+use std::time::Duration;
+
+// the puzzle that a subcommand starts from: a log at its last move, or else a solved puzzle of
+// size n and dimension d. the prefs get axes for the dimension.
+pub fn start_puzzle(
+    log: Option<&Path>,
+    n: Option<i16>,
+    d: Option<u16>,
+    prefs: &mut Prefs,
+) -> Result<Puzzle, Box<dyn Error>> {
+    let puzzle = match log {
+        Some(path) => {
+            let app_log = read_log(path)?;
+            let mut puzzle = app_log.scramble.clone();
+            for (i, turn) in app_log.current_moves().into_iter().enumerate() {
+                puzzle.turn(turn).map_err(|err| {
+                    format!("move {} of {} can't be made: {err}", i + 1, path.display())
+                })?;
+            }
+            puzzle
+        }
+        None => {
+            let (Some(n), Some(d)) = (n, d) else {
+                return Err("give a log, or n and d for a solved puzzle".into());
+            };
+            prefs.extend_axes(d)?;
+            check_size(n, d, prefs)?;
+            Puzzle::make_solved(n, d)
+        }
+    };
+    prefs.extend_axes(puzzle.d)?;
+    prefs.validate(puzzle.d)?;
+    Ok(puzzle)
+}
+
+// a solved hypercuboid with these layers along its axes
+pub fn start_shape(shape: &[i16], prefs: &mut Prefs) -> Result<Puzzle, Box<dyn Error>> {
+    let d = shape.len() as u16;
+    prefs.extend_axes(d)?;
+    check_shape(shape, prefs)?;
+    prefs.validate(d)?;
+    Ok(Puzzle::make_solved_shape(shape))
+}
+
+// the solved puzzle that a definition file describes
+pub fn start_definition(path: &Path, prefs: &mut Prefs) -> Result<Puzzle, Box<dyn Error>> {
+    let definition = definition::read_file(path)?;
+    let d = definition.d();
+    prefs.extend_axes(d)?;
+    check_shape(&definition.size, prefs)?;
+    prefs.validate(d)?;
+    Ok(definition
+        .make(prefs)
+        .map_err(|err| format!("in {}: {err}", path.display()))?)
+}
+
+// make the moves, written like typed moves, and say whether the puzzle ends up solved. with no
+// moves given, they are read from stdin.
+pub fn apply(
+    mut puzzle: Puzzle,
+    moves: &[String],
+    dump: bool,
+    prefs: &Prefs,
+) -> Result<(), Box<dyn Error>> {
+    let moves = if moves.is_empty() {
+        let mut st = String::new();
+        std::io::stdin().read_to_string(&mut st)?;
+        st
+    } else {
+        moves.join(" ")
+    };
+    let count = make_moves(&mut puzzle, &moves, prefs)?;
+
+    println!("applied {count} moves");
+    println!(
+        "{}",
+        if puzzle.is_solved() {
+            "solved"
+        } else {
+            "not solved"
+        }
+    );
+    // the same format as the scramble in a log
+    if dump {
+        println!("{}", serde_json::to_string(&puzzle)?);
+    }
+    Ok(())
+}
+
+// make moves written like typed moves, returning how many there were
+fn make_moves(puzzle: &mut Puzzle, moves: &str, prefs: &Prefs) -> Result<usize, Box<dyn Error>> {
+    let turns = Alg::parse(moves, prefs, puzzle.shape())?.expand();
+    for (i, turn) in turns.iter().enumerate() {
+        if let Err(err) = puzzle.turn(turn.clone()) {
+            return Err(format!(
+                "move {} ({}) can't be made: {err}",
+                i + 1,
+                turn.to_notation(prefs, puzzle.shape())
+            )
+            .into());
+        }
+    }
+    Ok(turns.len())
+}
+
+// make the moves, and print moves that solve the puzzle from there, or only the next few
+pub fn solution(
+    mut puzzle: Puzzle,
+    moves: &[String],
+    next: Option<usize>,
+    prefs: &Prefs,
+) -> Result<(), Box<dyn Error>> {
+    make_moves(&mut puzzle, &moves.join(" "), prefs)?;
+    let turns = solver::solve(&puzzle)?;
+    if turns.is_empty() {
+        println!("already solved");
+        return Ok(());
+    }
+    let shown = next.unwrap_or(turns.len()).min(turns.len());
+    println!(
+        "{}",
+        notation::to_sequence(&turns[..shown], prefs, puzzle.shape())
+    );
+    if shown < turns.len() {
+        println!("the next {shown} of {} moves", turns.len());
+    } else {
+        println!("{} moves", turns.len());
+    }
+    Ok(())
+}
+
+// replay a log from its scramble, and report how long it is, whether it ends solved, and any
+// moves that can't be made, which are skipped
+pub fn verify(path: &Path, prefs: &mut Prefs) -> Result<(), Box<dyn Error>> {
+    let app_log = read_log(path)?;
+    prefs.extend_axes(app_log.scramble.d)?;
+    println!(
+        "{}, {}",
+        app_log.scramble.size_name(),
+        check_scramble(&app_log)
+    );
+    if let Some(metadata) = &app_log.metadata {
+        let solver = match &metadata.solver {
+            Some(solver) => format!(" by {solver}"),
+            None => "".to_string(),
+        };
+        println!(
+            "made{solver} at {}, open for {}, saved by version {}",
+            metadata.created,
+            format_duration(metadata.duration),
+            metadata.version
+        );
+        if let Some(comment) = &metadata.comment {
+            println!("{comment}");
+        }
+    }
+
+    let mut puzzle = app_log.scramble.clone();
+    let mut made = vec![];
+    for (i, turn) in app_log.current_moves().into_iter().enumerate() {
+        match puzzle.turn(turn.clone()) {
+            Ok(_) => made.push(turn),
+            Err(err) => println!(
+                "move {} ({}) can't be made: {err}",
+                i + 1,
+                turn.to_notation(prefs, app_log.scramble.shape())
+            ),
+        }
+    }
+    println!("{}", MoveCounts::new(&made).summary());
+    for phase in phases(&app_log.current_moves(), &app_log.markers) {
+        let mut line = format!("{}: {} STM", phase.name, phase.stm);
+        if let Some(time) = phase.time {
+            line.push_str(&format!(", {}", format_duration(time)));
+        }
+        println!("{line}");
+    }
+    if let Some(time) = app_log.time {
+        println!("time {}", format_duration(time));
+    }
+    if let Some(blindfold) = &app_log.blindfold {
+        println!("{}", blindfold.summary());
+    }
+    println!(
+        "{}",
+        if puzzle.is_solved() {
+            "solved"
+        } else {
+            "not solved"
+        }
+    );
+    Ok(())
+}
+
+// make the scramble again from its seed, to see if it's the one in the log
+fn check_scramble(app_log: &AppLog) -> &'static str {
+    let scramble = &app_log.scramble;
+    let remade = match app_log.scramble_source {
+        ScrambleSource::Unknown => return "the log doesn't say how it was scrambled",
+        ScrambleSource::Random { seed: None, .. } => {
+            return "scrambled without a seed, so the scramble can't be checked"
+        }
+        ref source => source.remake(scramble.make_solved_like()),
+    };
+    if remade.as_ref() == Some(scramble) {
+        "the scramble matches how it was made"
+    } else {
+        "the scramble doesn't match how it was made"
+    }
+}
+
+// a log that was scrambled and ends solved
+struct Solve {
+    stm: usize,
+    time: Option<Duration>,
+    phases: Vec<Phase>,
+}
+
+fn solve(app_log: &AppLog) -> Option<Solve> {
+    if app_log.scramble.is_solved() {
+        return None;
+    }
+    let mut puzzle = app_log.scramble.clone();
+    let moves = app_log.current_moves();
+    for turn in &moves {
+        puzzle.turn(turn.clone()).ok()?;
+    }
+    puzzle.is_solved().then(|| Solve {
+        stm: MoveCounts::new(&moves).stm,
+        time: app_log.time,
+        phases: phases(&moves, &app_log.markers),
+    })
+}
+
+// the logs directly in dir, by size, counting the ones that are solves. logs that can't be read
+// are left out.
+pub fn stats(dir: &Path) -> Result<(), Box<dyn Error>> {
+    let entries = std::fs::read_dir(dir)
+        .map_err(|err| format!("could not read logs in {}: {err}", dir.display()))?;
+    let mut sizes: BTreeMap<(u16, String), (usize, Vec<Solve>)> = BTreeMap::new();
+    for entry in entries {
+        let path = entry?.path();
+        if path
+            .extension()
+            .is_none_or(|ext| ext != "log" && ext != "clog")
+        {
+            continue;
+        }
+        let Ok(app_log) = read_log(&path) else {
+            continue;
+        };
+        let (logs, solves) = sizes
+            .entry((app_log.scramble.d, app_log.scramble.size_name()))
+            .or_default();
+        *logs += 1;
+        solves.extend(solve(&app_log));
+    }
+    if sizes.is_empty() {
+        println!("no logs in {}", dir.display());
+    }
+
+    for ((_, size), (logs, solves)) in sizes {
+        let mut line = format!("{size}: {} solves of {logs} logs", solves.len());
+        if let Some(best) = solves.iter().map(|solve| solve.stm).min() {
+            let mean =
+                solves.iter().map(|solve| solve.stm).sum::<usize>() as f64 / solves.len() as f64;
+            line.push_str(&format!(", STM best {best} mean {mean:.1}"));
+        }
+        let times: Vec<Duration> = solves.iter().filter_map(|solve| solve.time).collect();
+        if let Some(best) = times.iter().min() {
+            let mean = times.iter().sum::<Duration>() / times.len() as u32;
+            line.push_str(&format!(
+                ", time best {} mean {}",
+                format_duration(*best),
+                format_duration(mean)
+            ));
+        }
+        println!("{line}");
+
+        // phases with the same name are counted together, in the order they first appear
+        let mut names: Vec<&str> = vec![];
+        for phase in solves.iter().flat_map(|solve| &solve.phases) {
+            if !names.contains(&phase.name.as_str()) {
+                names.push(&phase.name);
+            }
+        }
+        for name in names {
+            let phases: Vec<&Phase> = solves
+                .iter()
+                .flat_map(|solve| &solve.phases)
+                .filter(|phase| phase.name == name)
+                .collect();
+            let mean =
+                phases.iter().map(|phase| phase.stm).sum::<usize>() as f64 / phases.len() as f64;
+            let mut line = format!("  {name}: {} times, STM mean {mean:.1}", phases.len());
+            let times: Vec<Duration> = phases.iter().filter_map(|phase| phase.time).collect();
+            if !times.is_empty() {
+                let mean = times.iter().sum::<Duration>() / times.len() as u32;
+                line.push_str(&format!(", time mean {}", format_duration(mean)));
+            }
+            println!("{line}");
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,167 @@
+use crate::prefs::Prefs;
+use crate::{KeybindAxial, KeybindSet};
+
+// keys that don't print as themselves
+pub fn key_name(c: char) -> String {
+    match c {
+        '\t' => "tab".to_string(),
+        '\n' => "enter".to_string(),
+        ' ' => "space".to_string(),
+        c => c.to_string(),
+    }
+}
+
+// the keys that do the same thing in every keybind set
+fn global_lines(prefs: &Prefs) -> Vec<String> {
+    let g = &prefs.global_keys;
+    let damage = prefs.damage_repeat;
+    [
+        (format!("{} x{damage}", g.scramble), "scramble"),
+        (format!("{} x{damage}", g.reset), "reset"),
+        (format!("{} {}", g.undo, g.redo), "undo, redo"),
+        (
+            format!("{} {}", g.prev_branch, g.next_branch),
+            "branch that redo follows",
+        ),
+        (key_name(g.keybind_mode), "next keybind set"),
+        (key_name(g.axis_mode), "axis or side keybinds"),
+        (
+            format!("{} {}", g.prev_filter, g.next_filter),
+            "previous, next filter",
+        ),
+        (key_name(g.live_filter_mode), "live filter"),
+        (
+            key_name(g.save_live_filter),
+            "in a live filter, add it to the filters",
+        ),
+        (key_name(g.filter_name_mode), "filter by name"),
+        (key_name(g.reload_filters), "read the filter file again"),
+        (key_name(g.hide_filtered), "hide or dim filtered pieces"),
+        (key_name(g.focus), "only the sides the filter shows"),
+        (key_name(g.move_entry_mode), "type moves"),
+        (
+            key_name(g.command_mode),
+            "type a command like save, scramble, filter, seek, or export",
+        ),
+        (key_name(g.marker_mode), "mark the end of a phase"),
+        (
+            key_name(g.trial_mode),
+            "start a trial, or throw away the moves made in it",
+        ),
+        (
+            key_name(g.hint),
+            "next moves of a solution, on a 2^3 or 3^3",
+        ),
+        (key_name(g.theme), "next color theme"),
+        (key_name(g.save), "save"),
+        (key_name(g.save_as_mode), "save as"),
+        (
+            format!("{} {}", key_name(g.next_tab), key_name(g.new_tab)),
+            "next tab, new tab",
+        ),
+        (format!("{} {}", g.zoom_out, g.zoom_in), "zoom out, zoom in"),
+        (key_name(g.settings_mode), "rebind keys"),
+        (key_name(g.reset_mode), "cancel"),
+        (key_name(g.help), "this help"),
+    ]
+    .into_iter()
+    .map(|(keys, action)| format!("  {keys:<8} {action}"))
+    .collect()
+}
+
+// how to make a turn in a keybind set
+fn turn_lines(prefs: &Prefs, d: u16, keybind_set: KeybindSet) -> Vec<String> {
+    let rotate = prefs.global_keys.rotate;
+    match keybind_set {
+        KeybindSet::ThreeKey if d == 2 => vec![
+            "a side selector, then the axis along the row or column twice to flip it".to_string(),
+        ],
+        KeybindSet::ThreeKey => vec![
+            "a side selector, then two axis keys to turn the first axis to the second".to_string(),
+            "shift on the second axis key turns the second axis to the first instead".to_string(),
+            format!("{rotate} instead of the side selector rotates the whole puzzle"),
+        ],
+        KeybindSet::FixedKey if d == 2 => vec!["a side selector flips that side".to_string()],
+        KeybindSet::FixedKey if d == 3 => vec![
+            "a side selector rotates that side counterclockwise, and its side key clockwise"
+                .to_string(),
+        ],
+        KeybindSet::FixedKey => vec![
+            format!(
+                "a side selector, then {} axis keys to fix the plane of the turn",
+                d - 2
+            ),
+            format!("{rotate} anywhere before the end rotates the whole puzzle"),
+        ],
+        KeybindSet::XyzKey => {
+            let keys: Vec<String> = prefs
+                .axes
+                .iter()
+                .take(3)
+                .map(|axis| format!("{} {}", axis.pos.keys.side, axis.neg.keys.side))
+                .collect();
+            vec![
+                "a side selector holds that side".to_string(),
+                format!(
+                    "then {} turn it clockwise around the other axes in order, like a 3D puzzle",
+                    keys.join(", ")
+                ),
+                format!("{rotate} before the side selector rotates the whole puzzle"),
+            ]
+        }
+    }
+}
+
+pub fn help_lines(
+    prefs: &Prefs,
+    n: i16,
+    d: u16,
+    keybind_set: KeybindSet,
+    keybind_axial: KeybindAxial,
+) -> Vec<String> {
+    let mut lines = vec![
+        format!(
+            "{} mode with {}, press {} or {} to go back",
+            keybind_set.name(),
+            keybind_axial.name(),
+            prefs.global_keys.help,
+            prefs.global_keys.reset_mode
+        ),
+        "".to_string(),
+    ];
+    lines.extend(turn_lines(prefs, d, keybind_set));
+    let layers: String = prefs.global_keys.layers.iter().take(n as usize).collect();
+    lines.push(format!(
+        "{layers} before a turn picks the layer, and two of them pick all the layers between"
+    ));
+    lines.push(format!(
+        "{} before a turn picks the middle layer, or the middle two for even sizes",
+        prefs.global_keys.slice
+    ));
+    lines.push(format!(
+        "{} before or during a turn makes it once more each time",
+        prefs.global_keys.repeat_turn
+    ));
+    lines.push("".to_string());
+
+    lines.push("  side  selector  turn".to_string());
+    for axis in prefs.axes.iter().take(d as usize) {
+        let turn_keys = match keybind_axial {
+            KeybindAxial::Axial => axis.axis_key.to_string(),
+            KeybindAxial::Side => format!("{} {}", axis.pos.keys.side, axis.neg.keys.side),
+        };
+        lines.push(format!(
+            "  {} {}   {} {}       {turn_keys}",
+            axis.pos.name, axis.neg.name, axis.pos.keys.select, axis.neg.keys.select
+        ));
+    }
+    lines.push("".to_string());
+    lines.extend(global_lines(prefs));
+    lines.extend(
+        prefs
+            .algs
+            .iter()
+            .map(|alg| format!("  {:<8} {}", key_name(alg.key), alg.name)),
+    );
+    lines
+}
@@ -0,0 +1,299 @@
+use crate::puzzle::Turn;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone)]
+struct Node {
+    turn: Turn,
+    // None if the turn was made from the scramble
+    parent: Option<usize>,
+    children: Vec<usize>,
+    // index into children of the branch that redo follows
+    selected: usize,
+    // turns made together by one key share a group, and are undone together
+    #[serde(default)]
+    group: Option<usize>,
+}
+
+// every turn made since the scramble, as a tree. undoing and then making a different turn starts
+// a new branch instead of throwing away the turns that were undone.
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct History {
+    nodes: Vec<Node>,
+    roots: Vec<usize>,
+    selected_root: usize,
+    // the last turn applied to the puzzle, or None at the scramble
+    current: Option<usize>,
+}
+
+impl History {
+    pub fn from_moves(moves: Vec<Turn>) -> Self {
+        let mut history = History::default();
+        for turn in moves {
+            history.push(turn);
+        }
+        history
+    }
+
+    fn children(&self, node: Option<usize>) -> &[usize] {
+        match node {
+            Some(node) => &self.nodes[node].children,
+            None => &self.roots,
+        }
+    }
+
+    fn selected_mut(&mut self, node: Option<usize>) -> &mut usize {
+        match node {
+            Some(node) => &mut self.nodes[node].selected,
+            None => &mut self.selected_root,
+        }
+    }
+
+    pub fn push(&mut self, turn: Turn) {
+        let new = self.nodes.len();
+        self.nodes.push(Node {
+            turn,
+            parent: self.current,
+            children: vec![],
+            selected: 0,
+            group: None,
+        });
+        match self.current {
+            Some(node) => self.nodes[node].children.push(new),
+            None => self.roots.push(new),
+        }
+        let count = self.children(self.current).len();
+        *self.selected_mut(self.current) = count - 1;
+        self.current = Some(new);
+    }
+
+    // take the last turn out of the tree entirely, as if it was never made, and return the index
+    // its node had. nodes after it move down one index. does nothing if turns were made after it.
+    pub fn remove_last(&mut self) -> Option<usize> {
+        let removed = self
+            .current
+            .filter(|&node| self.nodes[node].children.is_empty())?;
+        let parent = self.nodes[removed].parent;
+        let siblings = match parent {
+            Some(node) => &mut self.nodes[node].children,
+            None => &mut self.roots,
+        };
+        siblings.retain(|&child| child != removed);
+        let count = siblings.len();
+        let selected = self.selected_mut(parent);
+        *selected = (*selected).min(count.saturating_sub(1));
+        self.nodes.remove(removed);
+
+        let shift = |ind: usize| if ind > removed { ind - 1 } else { ind };
+        for node in &mut self.nodes {
+            node.parent = node.parent.map(shift);
+            node.children
+                .iter_mut()
+                .for_each(|child| *child = shift(*child));
+            // a group is named after its last turn, which is now the one before the removed one
+            node.group = match node.group {
+                Some(group) if group == removed => parent,
+                group => group.map(shift),
+            };
+        }
+        self.roots.iter_mut().for_each(|root| *root = shift(*root));
+        self.current = parent.map(shift);
+        Some(removed)
+    }
+
+    // push turn, or merge it into the last turn if they turn the same layers in the same plane,
+    // returning the index the last turn's node had if it was taken out to do that
+    pub fn push_merged(&mut self, turn: Turn) -> Option<usize> {
+        if let Some(merged) = self.last().and_then(|last| last.merge(&turn)) {
+            // a turn with branches after it stays, so the branches aren't lost
+            if let Some(removed) = self.remove_last() {
+                if let Some(merged) = merged {
+                    self.push(merged);
+                }
+                return Some(removed);
+            }
+        }
+        self.push(turn);
+        None
+    }
+
+    // the node of the last turn applied, or None at the scramble. a node always stands for the
+    // same state of the puzzle.
+    pub fn current_node(&self) -> Option<usize> {
+        self.current
+    }
+
+    // the turns from the state at the closest node before the current one that found is true
+    // for, or else from the scramble, with that node
+    pub fn moves_since(&self, found: impl Fn(usize) -> bool) -> (Option<usize>, Vec<Turn>) {
+        let mut moves = vec![];
+        let mut node = self.current;
+        while let Some(ind) = node.filter(|&ind| !found(ind)) {
+            moves.push(self.nodes[ind].turn.clone());
+            node = self.nodes[ind].parent;
+        }
+        moves.reverse();
+        (node, moves)
+    }
+
+    // every node, including the ones on branches that were undone
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    // the last turn applied to the puzzle
+    pub fn last(&self) -> Option<&Turn> {
+        self.current.map(|node| &self.nodes[node].turn)
+    }
+
+    // put the turns made since there were count turns into one group
+    pub fn group_since(&mut self, count: usize) {
+        let Some(group) = self.current else {
+            return;
+        };
+        let mut node = self.current;
+        for _ in count..self.move_count() {
+            let Some(ind) = node else { break };
+            self.nodes[ind].group = Some(group);
+            node = self.nodes[ind].parent;
+        }
+    }
+
+    // the turns to invert, in order, to go back to before the last turn's group, or just the
+    // last turn if it isn't in one
+    pub fn undo_group(&mut self) -> Vec<Turn> {
+        let group = self.current.and_then(|node| self.nodes[node].group);
+        let mut turns = vec![];
+        while let Some(turn) = self.undo() {
+            turns.push(turn);
+            if group.is_none() || self.current.and_then(|node| self.nodes[node].group) != group {
+                break;
+            }
+        }
+        turns
+    }
+
+    // the turn to invert to go back one step
+    pub fn undo(&mut self) -> Option<Turn> {
+        let node = self.current?;
+        let parent = self.nodes[node].parent;
+        let ind = self
+            .children(parent)
+            .iter()
+            .position(|&child| child == node)
+            .expect("node is a child of its parent");
+        *self.selected_mut(parent) = ind;
+        self.current = parent;
+        Some(self.nodes[node].turn.clone())
+    }
+
+    // the turn to apply to go forward along the selected branch
+    pub fn redo(&mut self) -> Option<Turn> {
+        let children = self.children(self.current);
+        let selected = match self.current {
+            Some(node) => self.nodes[node].selected,
+            None => self.selected_root,
+        };
+        let node = *children.get(selected)?;
+        self.current = Some(node);
+        Some(self.nodes[node].turn.clone())
+    }
+
+    // move the branch that redo follows by offset, returning the new branch and the branch count
+    pub fn switch_branch(&mut self, offset: isize) -> Option<(usize, usize)> {
+        let count = self.children(self.current).len();
+        if count < 2 {
+            return None;
+        }
+        let selected = self.selected_mut(self.current);
+        *selected = (*selected as isize + offset).rem_euclid(count as isize) as usize;
+        Some((*selected, count))
+    }
+
+    // the turns from the scramble to the current state
+    pub fn moves(&self) -> Vec<Turn> {
+        let mut moves = vec![];
+        let mut node = self.current;
+        while let Some(ind) = node {
+            moves.push(self.nodes[ind].turn.clone());
+            node = self.nodes[ind].parent;
+        }
+        moves.reverse();
+        moves
+    }
+
+    pub fn move_count(&self) -> usize {
+        let mut len = 0;
+        let mut node = self.current;
+        while let Some(ind) = node {
+            len += 1;
+            node = self.nodes[ind].parent;
+        }
+        len
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::puzzle::PuzzleTurn;
+
+    fn turn(from: i16, to: i16) -> Turn {
+        Turn::Puzzle(PuzzleTurn { from, to })
+    }
+
+    #[test]
+    fn cancelled_turn_is_removed() {
+        let mut history = History::default();
+        history.push_merged(turn(0, 1));
+        history.push_merged(turn(1, 0));
+        assert_eq!(history.move_count(), 0);
+        assert_eq!(history.node_count(), 0);
+        assert!(history.redo().is_none());
+        assert!(history.switch_branch(1).is_none());
+    }
+
+    #[test]
+    fn merged_turn_replaces_the_last() {
+        let mut history = History::default();
+        history.push_merged(turn(0, 2));
+        history.push_merged(turn(0, 1));
+        history.push_merged(turn(0, 1));
+        assert_eq!(
+            history.moves(),
+            [turn(0, 2), Turn::Repeat(Box::new(turn(0, 1)), 2)]
+        );
+        assert_eq!(history.node_count(), 2);
+        history.undo();
+        assert!(history.switch_branch(1).is_none());
+        assert_eq!(history.redo(), Some(Turn::Repeat(Box::new(turn(0, 1)), 2)));
+    }
+
+    #[test]
+    fn turn_with_branches_after_it_stays() {
+        let mut history = History::from_moves(vec![turn(0, 1), turn(0, 2)]);
+        history.undo();
+        assert!(history.remove_last().is_none());
+        history.push_merged(turn(0, 1));
+        assert_eq!(history.move_count(), 2);
+        assert_eq!(history.node_count(), 3);
+    }
+
+    #[test]
+    fn removing_renumbers_later_nodes() {
+        let mut history = History::from_moves(vec![turn(0, 1), turn(0, 2)]);
+        history.undo();
+        history.push(turn(1, 2));
+        history.group_since(1);
+        history.undo();
+        history.switch_branch(-1);
+        history.redo();
+        assert_eq!(history.remove_last(), Some(1));
+        assert_eq!(history.node_count(), 2);
+        assert_eq!(history.current_node(), Some(0));
+        assert!(history.switch_branch(1).is_none());
+        assert_eq!(history.redo(), Some(turn(1, 2)));
+        assert_eq!(history.current_node(), Some(1));
+        assert_eq!(history.undo_group(), [turn(1, 2)]);
+        assert_eq!(history.moves(), [turn(0, 1)]);
+    }
+}
@@ -1,8 +1,102 @@
-use std::collections::HashMap;
+use itertools::Itertools;
+use std::collections::{HashMap, HashSet};
 use std::iter::once;
 
-const GAPS: &[i16] = &[0, 1, 0, 2, 1, 10, 4, 40, 18, 160, 72];
-const GAPS_COMPACT: &[i16] = &[0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0];
+// past the end of the table, each gap grows from the one two dimensions down as much as that one
+// grew from the one two dimensions further down
+fn gap(gaps: &[u16], d: u16) -> i16 {
+    match gaps.get(d as usize) {
+        Some(&gap) => gap as i16,
+        None if d < 4 => 0,
+        None => {
+            let (two, four) = (gap(gaps, d - 2) as i32, gap(gaps, d - 4) as i32);
+            if four == 0 {
+                two as i16
+            } else {
+                (two * two / four) as i16
+            }
+        }
+    }
+}
+
+// the axes of a puzzle of dimension d from the innermost level of the layout out: the ones listed
+// that the puzzle has, then the rest in order
+pub fn axis_order(listed: &[usize], d: usize) -> Vec<usize> {
+    let mut order: Vec<usize> = listed
+        .iter()
+        .copied()
+        .filter(|&axis| axis < d)
+        .unique()
+        .collect();
+    order.extend((0..d).filter(|axis| !listed.contains(axis)));
+    order
+}
+
+// the side a position is a sticker on, or None for a piece
+fn face_side(pos: &[i16], shape: &[i16]) -> Option<i16> {
+    let axis = pos.iter().zip(shape).position(|(c, n)| c.abs() == *n)?;
+    Some(if pos[axis] < 0 {
+        !(axis as i16)
+    } else {
+        axis as i16
+    })
+}
+
+// where each row or column goes when the ones that only had hidden things in them are taken out,
+// and how many there are then. the gaps on either side of the ones taken out become one gap as
+// wide as the wider of them.
+fn squeeze(before: &HashSet<i16>, after: &HashSet<i16>, len: i16) -> (HashMap<i16, i16>, i16) {
+    let mut places = HashMap::new();
+    let leading = (0..len).take_while(|i| !before.contains(i)).count() as i16;
+    let (mut at, mut run, mut gap) = (leading, 0, 0);
+    let mut last = None;
+    for i in 0..len {
+        if after.contains(&i) {
+            if last.is_some() {
+                at += gap;
+            }
+            places.insert(i, at);
+            last = Some(i);
+            at += 1;
+            (run, gap) = (0, 0);
+        } else if before.contains(&i) {
+            run = 0;
+        } else {
+            run += 1;
+            gap = gap.max(run);
+        }
+    }
+    let trailing = before.iter().max().map_or(0, |max| len - 1 - max);
+    (places, at + trailing)
+}
+
+// the coordinate on a puzzle of size m that covers a coordinate on a puzzle of size n. the
+// stickers and the outer layers stay apart and the inner layers merge.
+fn zoom_coord(c: i16, n: i16, m: i16) -> i16 {
+    if c.abs() == n {
+        c.signum() * m
+    } else if c.abs() == n - 1 && m > 1 {
+        c.signum() * (m - 1)
+    } else {
+        0
+    }
+}
+
+// the sizes of the smaller puzzles that a puzzle of size n is drawn as when zoomed out
+pub fn zoom_sizes(n: i16) -> Vec<i16> {
+    [3, 1].into_iter().filter(|&m| m < n).collect()
+}
+
+// a layout of a smaller puzzle, each cell of which stands for the cells of the full layout that it
+// covers
+#[derive(Debug, Clone)]
+pub struct Zoomed {
+    pub size: i16,
+    // the layers along each axis, which is the size except on axes that had fewer to begin with
+    pub shape: Vec<i16>,
+    pub layout: Layout,
+    pub covers: HashMap<Vec<i16>, Vec<Vec<i16>>>,
+}
 
 #[derive(Debug, Clone)]
 pub struct Layout {
@@ -10,6 +104,10 @@ pub struct Layout {
     pub height: u16,
     pub points: HashMap<(i16, i16), Vec<i16>>,
     pub keybind_hints: HashMap<(i16, i16), Option<i16>>, // None: core, Some(i): side i
+    // box-drawing characters around each three-dimensional block
+    pub borders: HashMap<(i16, i16), char>,
+    // the side each label names, next to the first and last blocks of the outermost level
+    pub labels: HashMap<(i16, i16), i16>,
 }
 
 impl Layout {
@@ -19,6 +117,8 @@ impl Layout {
             height: 0,
             points: HashMap::new(),
             keybind_hints: HashMap::new(),
+            borders: HashMap::new(),
+            labels: HashMap::new(),
         }
     }
 
@@ -40,6 +140,12 @@ impl Layout {
         for ((x, y), val) in &self.keybind_hints {
             out.keybind_hints.insert((x + shift, *y), *val);
         }
+        for ((x, y), val) in &self.borders {
+            out.borders.insert((x + shift, *y), *val);
+        }
+        for ((x, y), val) in &self.labels {
+            out.labels.insert((x + shift, *y), *val);
+        }
         out.width = (self.width as i16 + shift) as u16;
         out.height = self.height;
         out
@@ -53,11 +159,175 @@ impl Layout {
         for ((x, y), val) in &self.keybind_hints {
             out.keybind_hints.insert((*x, y + shift), *val);
         }
+        for ((x, y), val) in &self.borders {
+            out.borders.insert((*x, y + shift), *val);
+        }
+        for ((x, y), val) in &self.labels {
+            out.labels.insert((*x, y + shift), *val);
+        }
         out.width = self.width;
         out.height = (self.height as i16 + shift) as u16;
         out
     }
 
+    // make room for two characters in every cell
+    pub fn widen(self) -> Self {
+        let mut out = Self::new();
+        for ((x, y), val) in &self.points {
+            out.points.insert((2 * x, *y), val.to_vec());
+        }
+        for ((x, y), val) in &self.keybind_hints {
+            out.keybind_hints.insert((2 * x, *y), *val);
+        }
+        // lines going right continue through the second character
+        for ((x, y), &val) in &self.borders {
+            out.borders.insert((2 * x, *y), val);
+            if matches!(val, '─' | '┌' | '└') {
+                out.borders.insert((2 * x + 1, *y), '─');
+            }
+        }
+        for ((x, y), val) in &self.labels {
+            out.labels.insert((2 * x, *y), *val);
+        }
+        out.width = self.width * 2;
+        out.height = self.height;
+        out
+    }
+
+    // for each (side of a face, side along that face), the arrow pointing that way on the screen,
+    // found from where the neighboring stickers are drawn
+    pub fn arrows(&self, shape: &[i16]) -> HashMap<(i16, i16), char> {
+        let places: HashMap<&Vec<i16>, (i16, i16)> =
+            self.points.iter().map(|(xy, pos)| (pos, *xy)).collect();
+        let mut arrows = HashMap::new();
+        for ((x, y), pos) in &self.points {
+            let Some(face_ax) = pos.iter().zip(shape).position(|(c, n)| c.abs() == *n) else {
+                continue;
+            };
+            let face = if pos[face_ax] < 0 {
+                !(face_ax as i16)
+            } else {
+                face_ax as i16
+            };
+            for axis in (0..pos.len()).filter(|&axis| axis != face_ax) {
+                let mut next = pos.clone();
+                next[axis] += 2;
+                let Some((x1, y1)) = places.get(&next) else {
+                    continue;
+                };
+                let (dx, dy) = (x1 - x, y1 - y);
+                let (pos_arrow, neg_arrow) = if dx.abs() >= dy.abs() {
+                    if dx > 0 {
+                        ('→', '←')
+                    } else {
+                        ('←', '→')
+                    }
+                } else if dy > 0 {
+                    ('↓', '↑')
+                } else {
+                    ('↑', '↓')
+                };
+                arrows.entry((face, axis as i16)).or_insert(pos_arrow);
+                arrows.entry((face, !(axis as i16))).or_insert(neg_arrow);
+            }
+        }
+        arrows
+    }
+
+    // where the stickers of each side are
+    pub fn faces(&self, shape: &[i16]) -> HashMap<i16, Vec<(i16, i16)>> {
+        let mut faces: HashMap<i16, Vec<(i16, i16)>> = HashMap::new();
+        for (&xy, pos) in &self.points {
+            if let Some(side) = face_side(pos, shape) {
+                faces.entry(side).or_default().push(xy);
+            }
+        }
+        faces
+    }
+
+    // only the sides with a sticker that shows, and the pieces with a sticker on them, with the
+    // rows and columns that are left empty taken out, or everything if nothing shows. the boxes
+    // and labels don't line up after that, so they're left out.
+    pub fn focus(&self, shape: &[i16], shows: impl Fn(&[i16]) -> bool) -> Layout {
+        let stickers: Vec<(i16, i16)> = self
+            .faces(shape)
+            .into_values()
+            .filter(|places| places.iter().any(|xy| shows(&self.points[xy])))
+            .flatten()
+            .collect();
+        if stickers.is_empty() {
+            return self.clone();
+        }
+        let pieces: HashSet<Vec<i16>> = stickers
+            .iter()
+            .map(|xy| {
+                let mut piece = self.points[xy].clone();
+                if let Some(side) = face_side(&piece, shape) {
+                    let axis = if side < 0 { !side } else { side } as usize;
+                    piece[axis] -= piece[axis].signum();
+                }
+                piece
+            })
+            .collect();
+        let kept: HashSet<(i16, i16)> = self
+            .points
+            .iter()
+            .filter(|(_, pos)| pieces.contains(*pos))
+            .map(|(&xy, _)| xy)
+            .chain(stickers)
+            .collect();
+
+        let (columns, width) = squeeze(
+            &self.points.keys().map(|&(x, _)| x).collect(),
+            &kept.iter().map(|&(x, _)| x).collect(),
+            self.width as i16,
+        );
+        let (rows, height) = squeeze(
+            &self.points.keys().map(|&(_, y)| y).collect(),
+            &kept.iter().map(|&(_, y)| y).collect(),
+            self.height as i16,
+        );
+        let place = |(x, y)| (columns[&x], rows[&y]);
+        Layout {
+            width: width as u16,
+            height: height as u16,
+            points: (kept.iter())
+                .map(|&xy| (place(xy), self.points[&xy].clone()))
+                .collect(),
+            keybind_hints: (self.keybind_hints.iter())
+                .filter(|(xy, _)| kept.contains(*xy))
+                .map(|(&xy, &hint)| (place(xy), hint))
+                .collect(),
+            borders: HashMap::new(),
+            labels: HashMap::new(),
+        }
+    }
+
+    // the shape of the puzzle of size m that a puzzle of this shape zooms out to
+    pub fn zoom_shape(shape: &[i16], m: i16) -> Vec<i16> {
+        shape.iter().map(|&n| n.min(m)).collect()
+    }
+
+    // the layout of a puzzle of size m standing for this layout of a puzzle of the given shape
+    pub fn zoom_out(&self, shape: &[i16], m: i16, zoomed_layout: Layout) -> Zoomed {
+        let zoomed_shape = Self::zoom_shape(shape, m);
+        let mut covers: HashMap<Vec<i16>, Vec<Vec<i16>>> = HashMap::new();
+        for pos in self.points.values() {
+            let zoomed_pos = pos
+                .iter()
+                .zip(shape.iter().zip(&zoomed_shape))
+                .map(|(&c, (&n, &m))| zoom_coord(c, n, m))
+                .collect();
+            covers.entry(zoomed_pos).or_default().push(pos.clone());
+        }
+        Zoomed {
+            size: m,
+            shape: zoomed_shape,
+            layout: zoomed_layout,
+            covers,
+        }
+    }
+
     fn squish_left(self) -> Self {
         let shift = -self.points.keys().map(|(x, _y)| x).min().unwrap_or(&0);
         self.move_right(shift)
@@ -88,6 +358,8 @@ impl Layout {
     fn union(&mut self, other: Self) -> &mut Self {
         self.points.extend(other.points);
         self.keybind_hints.extend(other.keybind_hints);
+        self.borders.extend(other.borders);
+        self.labels.extend(other.labels);
         self.width = self.width.max(other.width);
         self.height = self.height.max(other.height);
         self
@@ -125,9 +397,14 @@ impl Layout {
         out
     }
 
-    fn clean(mut self, n: i16) -> Self {
-        self.points
-            .retain(|_key, val| val.iter().filter(|x| x.abs() == n).count() <= 1);
+    fn clean(mut self, shape: &[i16]) -> Self {
+        self.points.retain(|_key, val| {
+            val.iter()
+                .zip(shape)
+                .filter(|(x, n)| x.abs() == **n)
+                .count()
+                <= 1
+        });
         self
     }
 
@@ -139,28 +416,155 @@ impl Layout {
         lower
     }
 
-    pub fn make_layout(n: i16, d: u16, compact: bool, vertical: bool) -> Layout {
-        let gaps = if compact { GAPS_COMPACT } else { GAPS };
+    // surround each three-dimensional block with a box, and label the first and last blocks of
+    // the outermost level with their sides. the blocks are told apart by their coordinates past
+    // the third axis, and the levels above the third leave room for the boxes between them.
+    fn frame(self, shape: &[i16], horizontal: bool) -> Self {
+        // room for the labels and the boxes at the edges
+        let mut out = if horizontal {
+            self.move_right(1).move_down(2)
+        } else {
+            self.move_right(3).move_down(1)
+        };
+        let bounds = |points: Vec<(i16, i16)>| {
+            let xs = points.iter().map(|&(x, _)| x);
+            let ys = points.iter().map(|&(_, y)| y);
+            (
+                xs.clone().min().unwrap_or(0),
+                ys.clone().min().unwrap_or(0),
+                xs.max().unwrap_or(0),
+                ys.max().unwrap_or(0),
+            )
+        };
 
+        let mut blocks: HashMap<&[i16], Vec<(i16, i16)>> = HashMap::new();
+        for (&xy, pos) in &out.points {
+            blocks
+                .entry(pos.get(3..).unwrap_or(&[]))
+                .or_default()
+                .push(xy);
+        }
+        let mut borders = HashMap::new();
+        for points in blocks.into_values() {
+            let (x0, y0, x1, y1) = bounds(points);
+            let (x0, y0, x1, y1) = (x0 - 1, y0 - 1, x1 + 1, y1 + 1);
+            for x in x0 + 1..x1 {
+                borders.insert((x, y0), '─');
+                borders.insert((x, y1), '─');
+            }
+            for y in y0 + 1..y1 {
+                borders.insert((x0, y), '│');
+                borders.insert((x1, y), '│');
+            }
+            borders.insert((x0, y0), '┌');
+            borders.insert((x1, y0), '┐');
+            borders.insert((x0, y1), '└');
+            borders.insert((x1, y1), '┘');
+        }
+        out.borders = borders;
+
+        if let Some(&n) = shape.last() {
+            let axis = shape.len() - 1;
+            for side in [!(axis as i16), axis as i16] {
+                let c = if side < 0 { -n } else { n };
+                let points = out
+                    .points
+                    .iter()
+                    .filter(|(_, pos)| pos[axis] == c)
+                    .map(|(&xy, _)| xy)
+                    .collect();
+                let (x0, y0, x1, y1) = bounds(points);
+                let place = if horizontal {
+                    ((x0 + x1) / 2, 0)
+                } else {
+                    (0, (y0 + y1) / 2)
+                };
+                out.labels.insert(place, side);
+            }
+        }
+        let cells = out.points.keys().chain(out.borders.keys());
+        out.width = (cells.clone().map(|&(x, _)| x).max().unwrap_or(-1) + 1) as u16;
+        out.height = (cells.map(|&(_, y)| y).max().unwrap_or(-1) + 1) as u16;
+        out
+    }
+
+    // the top level is joined horizontally or vertically as given, and the levels below alternate
+    // with the odd dimensions horizontal. the keybind hints only fit when every axis has more
+    // than two layers. the gaps between the blocks of each level are given by dimension, and the
+    // levels go through the axes in the given order from the innermost out.
+    pub fn make_layout(
+        shape: &[i16],
+        order: &[usize],
+        gaps: &[u16],
+        make_horizontal: bool,
+        borders: bool,
+    ) -> Layout {
+        let hints = shape.iter().all(|&n| n > 2);
+        let ordered: Vec<i16> = order.iter().map(|&axis| shape[axis]).collect();
+        let mut layout = Self::make_level(&ordered, hints, gaps, make_horizontal, borders);
+        if borders {
+            layout = layout.frame(&ordered, make_horizontal);
+        }
+
+        // back from the levels to the axes they stand for
+        let side = |side: i16| {
+            if side < 0 {
+                !(order[(!side) as usize] as i16)
+            } else {
+                order[side as usize] as i16
+            }
+        };
+        for pos in layout.points.values_mut() {
+            let mut unordered = pos.clone();
+            for (level, &axis) in order.iter().enumerate() {
+                unordered[axis] = pos[level];
+            }
+            *pos = unordered;
+        }
+        for hint in layout.keybind_hints.values_mut() {
+            *hint = hint.map(side);
+        }
+        for label in layout.labels.values_mut() {
+            *label = side(*label);
+        }
+        layout
+    }
+
+    fn make_level(
+        shape: &[i16],
+        hints: bool,
+        gaps: &[u16],
+        make_horizontal: bool,
+        borders: bool,
+    ) -> Layout {
+        let d = shape.len() as u16;
         if d == 0 {
             Layout {
                 width: 1,
                 height: 1,
                 points: HashMap::from([((0, 0), vec![])]),
-                keybind_hints: if n > 2 {
+                keybind_hints: if hints {
                     HashMap::from([((0, 0), None)])
                 } else {
                     HashMap::new()
                 },
+                borders: HashMap::new(),
+                labels: HashMap::new(),
             }
         } else {
-            let make_horizontal = d % 2 == 1 && !vertical;
-
-            let lower = Self::make_layout(n, ((d as i16) - 1) as u16, compact, false);
+            let lower_d = d - 1;
+            let n = shape[lower_d as usize];
+            let lower = Self::make_level(
+                &shape[..lower_d as usize],
+                hints,
+                gaps,
+                lower_d % 2 == 1,
+                borders,
+            );
             let mut row = vec![];
 
             for i in once(-n).chain((-n + 1..n).step_by(2)).chain(once(n)) {
-                let mut lower = lower.clone().push_all(i).clean(n);
+                let mut lower = lower.clone().push_all(i).clean(shape);
                 if i.abs() == n {
                     if make_horizontal {
                         lower = lower.squish_horiz();
@@ -185,11 +589,13 @@ impl Layout {
 
                 row.push(lower);
             }
+            // the boxes around the blocks below take a character on each side
+            let gap = gap(gaps, d) + if borders && d > 3 { 2 } else { 0 };
             if make_horizontal {
-                Self::concat_horiz(row, gaps[d as usize])
+                Self::concat_horiz(row, gap)
             } else {
                 row.reverse();
-                Self::concat_vert(row, gaps[d as usize])
+                Self::concat_vert(row, gap)
             }
         }
     }
@@ -1,36 +1,75 @@
 use crate::prefs::BACKSPACE_CODE;
 use crate::prefs::ESCAPE_CODE;
-use clap::Parser;
+use autosave::Autosave;
+use clap::{Parser, Subcommand};
 use crossterm::{
     cursor,
-    event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
-    style::{self, Stylize},
+    event::{
+        self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, MouseButton, MouseEvent,
+        MouseEventKind,
+    },
+    style::{Color, Stylize},
     terminal, ExecutableCommand, QueueableCommand,
 };
-use filters::Filter;
-use layout::Layout;
+use filters::{Filter, FilterContext, FilterStyle};
+use history::History;
+use itertools::Itertools;
+use layout::{Layout, Zoomed};
+use metrics::MoveCounts;
+use notation::Alg;
 use prefs::Prefs;
-use puzzle::{ax, Puzzle, PuzzleTurn, SideTurn, Turn};
-use rand::rngs::ThreadRng;
+use puzzle::{ax, Puzzle, PuzzleTurn, SideTurn, Turn, TurnError};
+use rand::rngs::{StdRng, ThreadRng};
+use rand::{Rng, SeedableRng};
+use records::{Records, SolveRecord};
+use screen::Screen;
 use serde::{Deserialize, Serialize};
+use session::Session;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
 use std::fs::File;
 use std::io::BufReader;
 use std::io::BufWriter;
 use std::io::{self, Write};
-use std::path::PathBuf;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
 use std::thread::sleep;
 use std::time::{Duration, Instant};
+use stdout_manager::StdoutManager;
+use timer::Timer;
 
+mod autosave;
+mod commands;
+mod compact;
+mod cycles;
+mod definition;
 mod filters;
+mod headless;
+mod help;
+mod history;
 mod layout;
+mod metrics;
+mod notation;
 mod prefs;
+mod presets;
 mod puzzle;
+mod records;
+mod screen;
+mod session;
+mod solver;
+mod stdout_manager;
+mod timer;
 
 const FRAME_LENGTH: Duration = Duration::from_millis(1000 / 30);
+// the order command gives up after making the moves this many times
+const MAX_ORDER: usize = 100_000;
 
 #[derive(PartialEq)]
 enum TurnLayer {
-    Layer(i16),
+    // the outermost and innermost layers turned, counted from the outside starting at 0
+    Layers(i16, i16),
+    // the middle layer of the side's axis, or the middle two when it has an even number
+    Middle,
     WholePuzzle,
 }
 
@@ -42,6 +81,7 @@ struct TurnBuild {
     fixed: Vec<i16>,
 }
 
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
 enum KeybindAxial {
     Axial, // select axes, fewer keys
     Side,  // select sides, more keys
@@ -63,31 +103,33 @@ impl KeybindAxial {
     }
 }
 
-#[derive(PartialEq)]
+#[derive(PartialEq, Serialize, Deserialize, Clone, Copy, Debug)]
+// the names are saved in sessions and prefs
+#[allow(clippy::enum_variant_names)]
 enum KeybindSet {
     ThreeKey, // MC7D, works in d dimensions, depends on axial flag
+    // has addition inversion keys in 3d
     FixedKey, // works in d dimensions, requires d-2 keypresses, depends on axial flag
-              // has addition inversion keys in 3d
-              //XyzKey, // HSC, 4d only
+    XyzKey,   // HSC, 4d only
 }
 
 impl KeybindSet {
-    fn valid(&self, n: i16) -> bool {
+    fn valid(&self, n: i16, d: u16) -> bool {
         match self {
             Self::ThreeKey => true,
             Self::FixedKey => n >= 3,
-            //Self::XyzKey => n == 4,
+            Self::XyzKey => d == 4,
         }
     }
 
-    fn next(&self, n: i16) -> Self {
+    fn next(&self, n: i16, d: u16) -> Self {
         let next = match self {
             Self::ThreeKey => Self::FixedKey,
-            Self::FixedKey => Self::ThreeKey, //Self::XyzKey,
-                                              //Self::XyzKey => Self::ThreeKey,
+            Self::FixedKey => Self::XyzKey,
+            Self::XyzKey => Self::ThreeKey,
         };
-        if !next.valid(n) {
-            next.next(n)
+        if !next.valid(n, d) {
+            next.next(n, d)
         } else {
             next
         }
@@ -97,7 +139,7 @@ impl KeybindSet {
         match self {
             Self::ThreeKey => "three-key".to_string(),
             Self::FixedKey => "fixed-key".to_string(),
-            //Self::XyzKey => "xyz".to_string(),
+            Self::XyzKey => "xyz".to_string(),
         }
     }
 }
@@ -107,6 +149,16 @@ enum AppMode {
     #[default]
     Turn,
     LiveFilter,
+    MoveEntry,
+    SaveAs,
+    FilterName,
+    Help,
+    Settings,
+    Command,
+    Marker,
+    Summary,
+    // the moves that make the scramble, to make it on another puzzle
+    Sequence,
 }
 
 struct AppState {
@@ -116,31 +168,319 @@ struct AppState {
     current_keys: String,
     current_turn: TurnBuild,
     alert: u8,
+    // the stickers changed by the last turn, and how many frames they stay highlighted for
+    animation: Option<(HashSet<usize>, u8)>,
     damage_counter: Option<(char, u8)>,
     rng: ThreadRng,
+    scramble_length: usize,
+    // every scramble uses this seed if it is set. otherwise a new seed is picked and logged.
+    seed: Option<u64>,
     keybind_set: KeybindSet,
     keybind_axial: KeybindAxial,
     message: Option<String>,
-    undo_history: Vec<Turn>,
-    redo_history: Vec<Turn>,
+    history: History,
+    // counts every change to the history, to tell when there is something new to save
+    changes: usize,
+    saved_changes: usize,
+    // the last log written, by saving or autosaving
+    last_written: Option<PathBuf>,
+    quit_pending: bool,
+    // set by the quit command for the main loop, with whether to quit with unsaved changes
+    quit_requested: Option<bool>,
+    last_turn: Option<Turn>,
     filters: Vec<Filter>,
     filter_ind: usize,
+    // the file the filters were loaded from, which saved live filters are added to
+    filters_path: Option<PathBuf>,
     use_live_filter: bool,
     live_filter_string: String,
     live_filter_pending: Filter,
     live_filter: Filter,
+    // how many times the turn being entered is made
+    turn_repeat: u8,
+    // draw filtered pieces as blank instead of in the filtered color
+    hide_filtered: bool,
+    // lay out only the sides with pieces the filter shows
+    focus: bool,
+    // the puzzle drawn next to this one to compare colors with
+    reference: Option<commands::Reference>,
+    // the puzzle as it was when the keyframe command was used, and whether the stickers that
+    // changed since then are highlighted
+    keyframe: Option<Puzzle>,
+    show_diff: bool,
+    // the preset being changed in live filter mode
+    editing_filter: Option<usize>,
+    // what has been typed in move entry or save-as mode
+    text_entry: String,
     filename: PathBuf,
     prefs: Prefs,
+    // where rebinding keys in the settings writes the prefs
+    prefs_path: PathBuf,
+    // the binding picked in the settings, whether the next key rebinds it, and whether anything
+    // was rebound since the prefs were written
+    setting: usize,
+    rebinding: bool,
+    prefs_changed: bool,
+    auto_advance: bool,
+    // a turn undoing the last one removes both, and turns of the same layers are combined
+    merge_moves: bool,
+    splits: Vec<Split>,
+    markers: Vec<Marker>,
+    groups: Vec<Group>,
+    // copies of the puzzle at some nodes of the history, by node
+    snapshots: HashMap<usize, Puzzle>,
+    scramble_source: ScrambleSource,
+    timer: Timer,
+    // for the log metadata. the time open is counted from open_since, plus open_before from
+    // earlier times the log was loaded.
+    created: String,
+    open_since: Instant,
+    open_before: Duration,
+    solver: Option<String>,
+    comment: Option<String>,
+    // the state from before the trial being made, if there is one
+    trial: Option<Trial>,
+    blindfold: Option<Blindfold>,
+    // pieces picked out by a command, until the puzzle changes
+    highlight: Option<Highlight>,
+    // solves since then count toward the session means, in RFC 3339
+    session_started: String,
+    // whether this scramble's solve was added to the records, and the lines about the records
+    // for the summary
+    solve_recorded: bool,
+    record_lines: Vec<String>,
+    // stepping through a loaded log. turning is disabled until the replay is ended.
+    replaying: bool,
+    replay_length: usize,
+    // when the rewind command started and how many moves it has undone, until there are none
+    // left or a key is pressed
+    rewind: Option<(Instant, usize)>,
+    // the only sides that can turn, for practicing with a few of them. the whole puzzle can
+    // always rotate.
+    only_sides: Option<Vec<i16>>,
+    // the scramble in notation, while it's shown
+    sequence: String,
+    // the moves last typed or made with an alg key, for the order command
+    last_alg: Option<String>,
+    // (index, count) of the tab this is in, if there is more than one
+    tab: Option<(usize, usize)>,
+    // the sticker clicked first when turning with the mouse
+    clicked: Option<Vec<i16>>,
+    // the screen position of the sticker selected with the arrow keys
+    cursor: Option<(i16, i16)>,
+}
+
+// moves made in a trial can be thrown away, going back to this. until then, saving saves this
+// instead.
+struct Trial {
+    puzzle: Puzzle,
+    history: History,
+    splits: Vec<Split>,
+    markers: Vec<Marker>,
+    groups: Vec<Group>,
+    timer: Timer,
+    last_turn: Option<Turn>,
+}
+
+// how the scramble state of a log was made
+#[derive(Serialize, Deserialize, Clone, Default)]
+enum ScrambleSource {
+    // logs from before this was recorded
+    #[default]
+    Unknown,
+    // the scramble is the solved puzzle
+    Solved,
+    Random {
+        moves: usize,
+        seed: Option<u64>,
+    },
+    // picked uniformly from the reachable states
+    RandomState {
+        seed: u64,
+    },
+    // picked uniformly from the reachable states where only the pieces in these slots move
+    Partial {
+        seed: u64,
+        pieces: Vec<Vec<i16>>,
+    },
+}
+
+impl ScrambleSource {
+    // the scramble made again from the solved puzzle, if it can be
+    fn remake(&self, mut puzzle: Puzzle) -> Option<Puzzle> {
+        match *self {
+            Self::Partial { seed, ref pieces } => {
+                puzzle.scramble_random_pieces(pieces, &mut StdRng::seed_from_u64(seed))
+            }
+            Self::Unknown | Self::Random { seed: None, .. } => return None,
+            Self::Solved => (),
+            Self::Random {
+                moves,
+                seed: Some(seed),
+            } => puzzle.scramble(moves, &mut StdRng::seed_from_u64(seed)),
+            Self::RandomState { seed } => {
+                puzzle.scramble_random_state(&mut StdRng::seed_from_u64(seed))
+            }
+        }
+        Some(puzzle)
+    }
+
+    // the turns the scramble was made with, if they can be made again
+    fn turns(&self, solved: &Puzzle) -> Option<Vec<Turn>> {
+        match *self {
+            Self::Random {
+                moves,
+                seed: Some(seed),
+            } => Some(solved.scramble_turns(moves, &mut StdRng::seed_from_u64(seed))),
+            _ => None,
+        }
+    }
+
+    fn seed(&self) -> Option<u64> {
+        match self {
+            Self::Random { seed, .. } => *seed,
+            Self::RandomState { seed } | Self::Partial { seed, .. } => Some(*seed),
+            Self::Unknown | Self::Solved => None,
+        }
+    }
+}
+
+// about the log rather than the moves, for people reading it
+#[derive(Serialize, Deserialize, Clone)]
+struct LogMetadata {
+    n: i16,
+    d: u16,
+    // when the puzzle was scrambled or reset, in RFC 3339
+    created: String,
+    // how long the log has been open, adding up every time it was loaded
+    duration: Duration,
+    // the version of the program that last saved it
+    version: String,
+    seed: Option<u64>,
+    #[serde(default)]
+    solver: Option<String>,
+    #[serde(default)]
+    comment: Option<String>,
+    // the color theme from the prefs the log was last saved with
+    #[serde(default)]
+    theme: Option<String>,
+}
+
+// moves that were entered together as a commutator or conjugate
+#[derive(Serialize, Deserialize, Clone)]
+struct Group {
+    // the number of moves before the group
+    start: usize,
+    len: usize,
+    notation: String,
+}
+
+// the point in the solve where the pieces of a filter were all solved
+#[derive(Serialize, Deserialize, Clone)]
+struct Split {
+    filter_ind: usize,
+    moves: usize,
+}
+
+// a point in the solve named by the solver, like the end of a phase
+#[derive(Serialize, Deserialize, Clone)]
+struct Marker {
+    name: String,
+    moves: usize,
+    // the time on the timer, if it had started
+    time: Option<Duration>,
+}
+
+// a blindfolded solve, timed from when memorizing starts. the colors are hidden from the end of
+// the memo until the puzzle is solved or they are shown again.
+#[derive(Serialize, Deserialize, Clone, Default)]
+struct Blindfold {
+    // how long memorizing took, once it has ended
+    memo: Option<Duration>,
+    // how long solving took after the memo, if it was solved with the colors hidden
+    execution: Option<Duration>,
+    // whether the colors were shown again before it was solved
+    #[serde(default)]
+    revealed: bool,
+}
+
+impl Blindfold {
+    fn hidden(&self) -> bool {
+        self.memo.is_some() && self.execution.is_none() && !self.revealed
+    }
+
+    fn summary(&self) -> String {
+        let time = |time: Option<Duration>| time.map(timer::format_duration).unwrap_or_default();
+        match (self.memo, self.execution) {
+            (None, _) => "blindfolded, still memorizing".to_string(),
+            (Some(memo), Some(execution)) => format!(
+                "blindfolded: memo {}, execution {}",
+                time(Some(memo)),
+                time(Some(execution))
+            ),
+            (memo, None) if self.revealed => {
+                format!(
+                    "blindfolded: memo {}, colors shown before the end",
+                    time(memo)
+                )
+            }
+            (memo, None) => format!("blindfolded: memo {}, not solved yet", time(memo)),
+        }
+    }
+}
+
+// pieces picked out by a command, drawn on another background while the puzzle stays the same
+struct Highlight {
+    // the changes count when they were picked
+    changes: usize,
+    pieces: Vec<Vec<i16>>,
+    // which cycle was picked, for the next one to follow it
+    cycle: usize,
 }
 
 #[derive(Serialize, Deserialize)]
 struct AppLog {
     scramble: Puzzle,
     moves: Vec<Turn>,
+    // the whole tree, including branches that were undone. moves is the current branch.
+    #[serde(default)]
+    history: Option<History>,
+    // the moves in text notation, for reading. this is ignored when loading.
+    #[serde(default)]
+    notation: String,
+    #[serde(default)]
+    splits: Vec<Split>,
+    #[serde(default)]
+    markers: Vec<Marker>,
+    #[serde(default)]
+    groups: Vec<Group>,
+    #[serde(default)]
+    scramble_source: ScrambleSource,
+    #[serde(default)]
+    time: Option<Duration>,
+    #[serde(default)]
+    blindfold: Option<Blindfold>,
+    #[serde(default)]
+    metadata: Option<LogMetadata>,
+}
+
+impl AppLog {
+    // the moves from the scramble to where the log was saved
+    fn current_moves(&self) -> Vec<Turn> {
+        match &self.history {
+            Some(history) => history.moves(),
+            None => self.moves.clone(),
+        }
+    }
 }
 
 impl AppState {
     fn new(n: i16, d: u16, prefs: Prefs) -> Self {
+        let (keybind_set, keybind_axial) = match prefs.start_keybinds(d) {
+            Some(keybinds) if keybinds.set.valid(n, d) => (keybinds.set, keybinds.axial),
+            Some(keybinds) => (KeybindSet::ThreeKey, keybinds.axial),
+            None => (KeybindSet::ThreeKey, KeybindAxial::Axial),
+        };
         Self {
             puzzle: Puzzle::make_solved(n, d),
             scramble: Puzzle::make_solved(n, d),
@@ -148,28 +488,112 @@ impl AppState {
             current_keys: "".to_string(),
             current_turn: Default::default(),
             alert: Default::default(),
+            animation: None,
             damage_counter: Default::default(),
             rng: rand::thread_rng(),
-            keybind_set: KeybindSet::ThreeKey,
-            keybind_axial: KeybindAxial::Axial,
+            scramble_length: prefs.scramble_length,
+            seed: None,
+            keybind_set,
+            keybind_axial,
             message: Default::default(),
-            undo_history: Default::default(),
-            redo_history: Default::default(),
+            history: Default::default(),
+            changes: 0,
+            saved_changes: 0,
+            last_written: None,
+            quit_pending: false,
+            quit_requested: None,
+            last_turn: None,
             filters: vec![],
             filter_ind: 0,
+            filters_path: None,
             use_live_filter: false,
             live_filter_string: "".to_string(),
             live_filter: Default::default(),
             live_filter_pending: Default::default(),
+            editing_filter: None,
+            hide_filtered: false,
+            focus: false,
+            reference: None,
+            keyframe: None,
+            show_diff: false,
+            turn_repeat: 1,
+            text_entry: "".to_string(),
             filename: Self::new_filename(),
+            prefs_path: PathBuf::from(prefs::DEFAULT_FILE_PATH_STR),
+            setting: 0,
+            rebinding: false,
+            prefs_changed: false,
+            auto_advance: false,
+            merge_moves: false,
+            splits: vec![],
+            markers: vec![],
+            groups: vec![],
+            snapshots: HashMap::new(),
+            scramble_source: ScrambleSource::Solved,
+            timer: Timer::new(Duration::from_secs(prefs.inspection_seconds)),
+            created: chrono::Local::now().to_rfc3339(),
+            open_since: Instant::now(),
+            open_before: Duration::ZERO,
+            solver: prefs.solver.clone(),
+            comment: None,
+            trial: None,
+            blindfold: None,
+            highlight: None,
+            session_started: chrono::Local::now().to_rfc3339(),
+            solve_recorded: false,
+            record_lines: vec![],
+            replaying: false,
+            replay_length: 0,
+            rewind: None,
+            only_sides: None,
+            sequence: String::new(),
+            last_alg: None,
+            tab: None,
+            clicked: None,
+            cursor: None,
             prefs,
         }
     }
 
     fn to_app_log(&self) -> AppLog {
+        let (history, splits, markers, groups, timer) = match &self.trial {
+            Some(trial) => (
+                &trial.history,
+                &trial.splits,
+                &trial.markers,
+                &trial.groups,
+                &trial.timer,
+            ),
+            None => (
+                &self.history,
+                &self.splits,
+                &self.markers,
+                &self.groups,
+                &self.timer,
+            ),
+        };
         AppLog {
             scramble: self.scramble.clone(),
-            moves: self.undo_history.clone(),
+            moves: history.moves(),
+            history: Some(history.clone()),
+            notation: notation::to_sequence(&history.moves(), &self.prefs, self.puzzle.shape()),
+            splits: splits.clone(),
+            markers: markers.clone(),
+            groups: groups.clone(),
+            scramble_source: self.scramble_source.clone(),
+            time: timer.final_time(),
+            blindfold: self.blindfold.clone(),
+            metadata: Some(LogMetadata {
+                n: self.puzzle.n,
+                d: self.puzzle.d,
+                created: self.created.clone(),
+                duration: self.open_before + self.open_since.elapsed(),
+                version: env!("CARGO_PKG_VERSION").to_string(),
+                seed: self.scramble_source.seed(),
+                solver: self.solver.clone(),
+                comment: self.comment.clone(),
+                theme: self.prefs.theme().map(str::to_string),
+            }),
         }
     }
 
@@ -177,10 +601,70 @@ impl AppState {
         let mut state = AppState::new(app_log.scramble.n, app_log.scramble.d, prefs);
         state.scramble = app_log.scramble.clone();
         state.puzzle = app_log.scramble;
-        state.undo_history = app_log.moves.clone();
-        for mov in app_log.moves {
-            state.puzzle.turn(mov);
+        state.history = app_log
+            .history
+            .unwrap_or_else(|| History::from_moves(app_log.moves.clone()));
+        state.splits = app_log.splits;
+        state.markers = app_log.markers;
+        state.groups = app_log.groups;
+        state.scramble_source = app_log.scramble_source;
+        if let Some(time) = app_log.time {
+            state.timer.set_final_time(time);
+        }
+        state.blindfold = app_log.blindfold;
+        if let Some(metadata) = app_log.metadata {
+            state.created = metadata.created;
+            state.open_before = metadata.duration;
+            state.solver = metadata.solver.or(state.solver);
+            state.comment = metadata.comment;
+            // a theme that isn't in these prefs leaves the colors as they are
+            let _ = state.prefs.set_theme(metadata.theme.as_deref());
         }
+        // redo the moves to where the log was saved, keeping snapshots on the way
+        let count = state.history.move_count();
+        while state.history.undo().is_some() {}
+        for i in 1..=count {
+            if let Some(turn) = state.history.redo() {
+                let _ = state.puzzle.turn(turn);
+                state.take_snapshot(i);
+            }
+        }
+        state
+    }
+
+    // go back to the scramble, keeping the moves to redo
+    fn start_replay(&mut self) {
+        self.replay_length = self.history.move_count();
+        while self.history.undo().is_some() {}
+        self.puzzle = self.scramble.clone();
+        self.last_turn = None;
+        self.replaying = true;
+    }
+
+    // a solved puzzle to open in a new tab, with the same settings and filters
+    fn new_tab(&self, number: usize) -> Self {
+        let mut state = AppState::new(self.puzzle.n, self.puzzle.d, self.prefs.clone());
+        state.puzzle = self.solved_puzzle();
+        state.scramble = state.puzzle.clone();
+        state.filters = self.filters.clone();
+        state.filters_path = self.filters_path.clone();
+        state.auto_advance = self.auto_advance;
+        state.merge_moves = self.merge_moves;
+        state.only_sides = self.only_sides.clone();
+        state.scramble_length = self.scramble_length;
+        state.seed = self.seed;
+        state.keybind_set = self.keybind_set;
+        state.keybind_axial = self.keybind_axial;
+        state.prefs_path = self.prefs_path.clone();
+        state.session_started = self.session_started.clone();
+        let stem = self
+            .filename
+            .file_stem()
+            .unwrap_or_default()
+            .to_string_lossy();
+        state.filename = self
+            .filename
+            .with_file_name(format!("{stem}-tab{number}.log"));
         state
     }
 
@@ -194,28 +678,121 @@ impl AppState {
         ))
     }
 
-    fn save(&self) -> Result<(), Box<dyn std::error::Error>> {
+    fn save(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.write_log(&self.filename)?;
+        self.saved_changes = self.changes;
+        self.last_written = Some(self.filename.clone());
+        Ok(())
+    }
+
+    // whether the history changed since the log was last saved or loaded
+    fn is_dirty(&self) -> bool {
+        self.changes != self.saved_changes
+    }
+
+    // with unsaved changes here or in another tab, quitting has to be asked for twice in a row
+    fn request_quit(&mut self, others_dirty: bool) -> bool {
+        if !(self.is_dirty() || others_dirty) || self.quit_pending {
+            return true;
+        }
+        self.quit_pending = true;
+        self.message = Some(format!(
+            "unsaved changes: press ctrl+c again to quit, or {} to save",
+            self.prefs.global_keys.save
+        ));
+        false
+    }
+
+    fn write_log(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
         let app_log = self.to_app_log();
 
-        if let Some(parent) = self.filename.parent() {
+        if let Some(parent) = path.parent() {
             std::fs::create_dir_all(parent)?
         };
-        let file = File::create(self.filename.clone())?;
+        let file = File::create(path)?;
         let mut writer = BufWriter::new(file);
-        serde_json::to_writer(&mut writer, &app_log)?;
+        if compact::is_compact(path) {
+            compact::write(&app_log, &mut writer)?;
+        } else {
+            serde_json::to_writer(&mut writer, &app_log)?;
+        }
         writer.flush()?;
         Ok(())
     }
 
+    fn solved_puzzle(&self) -> Puzzle {
+        self.puzzle.make_solved_like()
+    }
+
     fn flush_modes(&mut self) {
         self.current_keys = "".to_string();
         self.current_turn = Default::default();
         self.live_filter_string = Default::default();
+        self.editing_filter = None;
+        self.text_entry = Default::default();
+        self.clicked = None;
     }
 
-    fn process_key(&mut self, c: char, _mods: KeyModifiers) {
+    fn process_key(&mut self, c: char, mods: KeyModifiers) {
         self.message = None;
-        if c == self.prefs.global_keys.scramble || c == self.prefs.global_keys.reset {
+        self.quit_pending = false;
+        if self.rewind.take().is_some() {
+            self.message = Some(format!(
+                "stopped rewinding at move {}",
+                self.history.move_count()
+            ));
+            return;
+        }
+        if matches!(
+            self.mode,
+            AppMode::MoveEntry
+                | AppMode::SaveAs
+                | AppMode::FilterName
+                | AppMode::Command
+                | AppMode::Marker
+        ) {
+            self.process_text_key(c);
+            return;
+        }
+        if matches!(self.mode, AppMode::Settings) {
+            self.process_settings_key(c);
+            return;
+        }
+        if matches!(self.mode, AppMode::Help) {
+            if c == self.prefs.global_keys.help || c == self.prefs.global_keys.reset_mode {
+                self.mode = Default::default();
+            }
+            return;
+        }
+        if matches!(self.mode, AppMode::Sequence) {
+            self.mode = Default::default();
+            return;
+        }
+        // any key goes back from the summary, and the save key saves first
+        if matches!(self.mode, AppMode::Summary) {
+            self.mode = Default::default();
+            if c == self.prefs.global_keys.save {
+                self.message = Some(match self.save() {
+                    Ok(()) => format!("saved to {}", self.filename.display()),
+                    Err(err) => format!("could not save: {err}"),
+                });
+            }
+            return;
+        }
+        // shift on the key that finishes a three-key turn makes it the other way, before the
+        // shifted key can be taken as a global key
+        let lower = c.to_lowercase().next().unwrap_or(c);
+        let invert = mods.contains(KeyModifiers::SHIFT)
+            && lower != c
+            && matches!(self.mode, AppMode::Turn)
+            && self.keybind_set == KeybindSet::ThreeKey
+            && self.current_turn.from.is_some()
+            && self.get_axis_key(lower).is_some();
+        let c = if invert { lower } else { c };
+        // live filters can use the same keys, like - to hide pieces
+        if (c == self.prefs.global_keys.scramble || c == self.prefs.global_keys.reset)
+            && !matches!(self.mode, AppMode::LiveFilter)
+        {
             match self.damage_counter {
                 None => self.damage_counter = Some((c, 1)),
                 Some((ch, i)) if ch == c => {
@@ -230,30 +807,117 @@ impl AppState {
         if let Some((ch, dr)) = self.damage_counter {
             if dr == self.prefs.damage_repeat {
                 self.flush_modes();
-                if ch == self.prefs.global_keys.scramble && self.puzzle.d >= 3 {
-                    self.puzzle = Puzzle::make_solved(self.puzzle.n, self.puzzle.d);
-                    self.puzzle.scramble(&mut self.rng);
-                    self.message = Some("scrambled with 5000 turns".to_string());
-                    self.scramble = self.puzzle.clone();
-                    self.undo_history = vec![];
-                    self.redo_history = vec![];
+                if self.trial.is_some() {
+                    self.message = Some("end the trial first".to_string());
+                } else if ch == self.prefs.global_keys.scramble {
+                    self.new_scramble(self.scramble_length);
                 } else if ch == self.prefs.global_keys.reset {
-                    self.puzzle = Puzzle::make_solved(self.puzzle.n, self.puzzle.d);
+                    self.puzzle = self.solved_puzzle();
                     self.message = Some("puzzle reset".to_string());
+                    self.scramble_source = ScrambleSource::Solved;
+                    self.timer.disarm();
                     self.scramble = self.puzzle.clone();
-                    self.undo_history = vec![];
-                    self.redo_history = vec![];
+                    self.history = Default::default();
+                    self.changes += 1;
+                    self.last_turn = None;
+                    self.reset_splits();
                 }
                 self.damage_counter = None;
             }
         } else if c == self.prefs.global_keys.reset_mode {
+            if self.replaying && matches!(self.mode, AppMode::Turn) {
+                self.replaying = false;
+                self.message = Some("replay ended".to_string());
+            }
             self.mode = Default::default();
+            self.cursor = None;
+            self.turn_repeat = 1;
             self.flush_modes();
-            self.message = None;
         } else if c == self.prefs.global_keys.live_filter_mode
             && !matches!(self.mode, AppMode::LiveFilter)
         {
             self.mode = AppMode::LiveFilter;
+            // start from the preset being used so it can be changed
+            if let Some(filter) = self.filters.get(self.filter_ind) {
+                if !self.use_live_filter {
+                    self.live_filter_string = filter.source.clone();
+                    self.live_filter_pending = filter.clone();
+                    self.editing_filter = Some(self.filter_ind);
+                }
+            }
+        } else if c == self.prefs.global_keys.command_mode && matches!(self.mode, AppMode::Turn) {
+            self.flush_modes();
+            self.mode = AppMode::Command;
+        } else if c == self.prefs.global_keys.trial_mode
+            && matches!(self.mode, AppMode::Turn)
+            && !self.replaying
+        {
+            self.flush_modes();
+            match self.trial {
+                Some(_) => self.discard_trial(),
+                None => self.start_trial(),
+            }
+        } else if c == self.prefs.global_keys.marker_mode && matches!(self.mode, AppMode::Turn) {
+            self.flush_modes();
+            self.mode = AppMode::Marker;
+        } else if c == self.prefs.global_keys.move_entry_mode && matches!(self.mode, AppMode::Turn)
+        {
+            self.flush_modes();
+            self.mode = AppMode::MoveEntry;
+        } else if c == self.prefs.global_keys.settings_mode && matches!(self.mode, AppMode::Turn) {
+            self.flush_modes();
+            self.mode = AppMode::Settings;
+        } else if c == self.prefs.global_keys.help && matches!(self.mode, AppMode::Turn) {
+            self.flush_modes();
+            self.mode = AppMode::Help;
+        } else if c == self.prefs.global_keys.hide_filtered && matches!(self.mode, AppMode::Turn) {
+            self.hide_filtered = !self.hide_filtered;
+            self.message = Some(if self.hide_filtered {
+                "hiding filtered pieces".to_string()
+            } else {
+                "dimming filtered pieces".to_string()
+            });
+        } else if c == self.prefs.global_keys.focus && matches!(self.mode, AppMode::Turn) {
+            self.focus = !self.focus;
+            self.message = Some(if self.focus {
+                "showing only the sides with pieces the filter shows".to_string()
+            } else {
+                "showing every side".to_string()
+            });
+        } else if c == self.prefs.global_keys.hint && matches!(self.mode, AppMode::Turn) {
+            self.hint();
+        } else if c == self.prefs.global_keys.theme && matches!(self.mode, AppMode::Turn) {
+            self.next_theme();
+        } else if let Some(alg) = self
+            .prefs
+            .algs
+            .iter()
+            .find(|alg| alg.key == c && matches!(self.mode, AppMode::Turn) && !self.replaying)
+        {
+            let alg = alg.clone();
+            self.flush_modes();
+            match Alg::parse(&alg.moves, &self.prefs, self.puzzle.shape()) {
+                Err(err) => self.message = Some(format!("{}: {err}", alg.name)),
+                Ok(parsed) => {
+                    let start = self.history.move_count();
+                    if self.apply_alg(&parsed, &alg.moves) {
+                        self.history.group_since(start);
+                        if self.message.is_none() {
+                            self.message = Some(alg.name);
+                        }
+                    }
+                }
+            }
+        } else if c == self.prefs.global_keys.reload_filters && matches!(self.mode, AppMode::Turn) {
+            self.reload_filters();
+        } else if c == self.prefs.global_keys.filter_name_mode && matches!(self.mode, AppMode::Turn)
+        {
+            self.flush_modes();
+            self.mode = AppMode::FilterName;
+        } else if c == self.prefs.global_keys.save_as_mode && matches!(self.mode, AppMode::Turn) {
+            self.flush_modes();
+            self.mode = AppMode::SaveAs;
+            self.text_entry = self.filename.display().to_string();
         } else if c == self.prefs.global_keys.save {
             match self.save() {
                 Ok(()) => self.message = Some(format!("saved to {}", self.filename.display())),
@@ -267,7 +931,7 @@ impl AppState {
 
                     if c == self.prefs.global_keys.keybind_mode {
                         self.flush_modes();
-                        self.keybind_set = self.keybind_set.next(self.puzzle.n);
+                        self.keybind_set = self.keybind_set.next(self.puzzle.n, self.puzzle.d);
                         self.message = Some(format!("set keybinds to {}", self.keybind_set.name()))
                     } else if c == self.prefs.global_keys.axis_mode {
                         if self.puzzle.d > 6 {
@@ -280,28 +944,51 @@ impl AppState {
                         }
                     } else if c == self.prefs.global_keys.undo {
                         self.flush_modes();
-                        let undid = self.undo_history.pop();
-                        match undid {
-                            None => {
-                                self.message = Some("nothing to undo".to_string());
-                            }
-                            Some(undid) => {
-                                self.puzzle.turn(undid.inverse());
-                                self.redo_history.push(undid)
-                            }
+                        let undid = self.history.undo_group();
+                        if undid.is_empty() {
+                            self.message = Some("nothing to undo".to_string());
+                        }
+                        let mut moved = HashSet::new();
+                        for turn in undid {
+                            moved.extend(self.puzzle.turn(turn.inverse()).unwrap_or_default());
+                            self.changes += 1;
+                            self.last_turn = Some(turn.inverse());
                         }
+                        if !moved.is_empty() {
+                            self.animate(Some(moved.into_iter().collect()));
+                        }
+                        self.check_stage();
                     } else if c == self.prefs.global_keys.redo {
                         self.flush_modes();
-                        let redid = self.redo_history.pop();
+                        let redid = self.history.redo();
                         match redid {
                             None => {
                                 self.message = Some("nothing to redo".to_string());
                             }
                             Some(redid) => {
-                                self.puzzle.turn(redid.clone());
-                                self.undo_history.push(redid)
+                                let moved = self.puzzle.turn(redid.clone());
+                                self.take_snapshot(self.history.move_count());
+                                self.changes += 1;
+                                self.animate(moved.ok());
+                                self.last_turn = Some(redid);
+                                self.check_stage();
                             }
                         }
+                    } else if c == self.prefs.global_keys.next_branch
+                        || c == self.prefs.global_keys.prev_branch
+                    {
+                        self.flush_modes();
+                        let offset = if c == self.prefs.global_keys.next_branch {
+                            1
+                        } else {
+                            -1
+                        };
+                        self.message = Some(match self.history.switch_branch(offset) {
+                            None => "no other branches".to_string(),
+                            Some((branch, count)) => {
+                                format!("redo branch {} of {count}", branch + 1)
+                            }
+                        });
                     } else if c == self.prefs.global_keys.next_filter {
                         if self.filters.is_empty() {
                             self.message = Some("no filters loaded".to_string());
@@ -320,15 +1007,29 @@ impl AppState {
                             self.use_live_filter = false;
                             self.message = Some("previous filter".to_string());
                         }
+                    } else if self.replaying {
+                        self.message = Some("press escape to end the replay and turn".to_string());
                     } else if let Some(s) =
                         self.prefs.global_keys.layers.iter().position(|ch| ch == &c)
                     {
-                        if s as i16 >= self.puzzle.n {
+                        let s = s as i16;
+                        if s >= self.puzzle.n {
                             return;
                         }
-                        self.flush_modes();
+                        // a second layer key makes a range of layers
+                        match self.current_turn.layer {
+                            Some(TurnLayer::Layers(l, m))
+                                if l == m && self.current_turn.side.is_none() =>
+                            {
+                                self.current_turn.layer =
+                                    Some(TurnLayer::Layers(l.min(s), l.max(s)));
+                            }
+                            _ => {
+                                self.flush_modes();
+                                self.current_turn.layer = Some(TurnLayer::Layers(s, s));
+                            }
+                        }
                         self.current_keys.push(c);
-                        self.current_turn.layer = Some(TurnLayer::Layer(s as i16));
                     } else if let Some(s) = self
                         .prefs
                         .axes
@@ -359,6 +1060,16 @@ impl AppState {
                         self.current_keys.push(c);
                         self.current_turn.side = Some(!(s as i16));
                         just_pressed_side = true;
+                    } else if c == self.prefs.global_keys.slice {
+                        if self.puzzle.n < 3 {
+                            self.message = Some("no middle layers to turn".to_string());
+                            return;
+                        }
+                        self.flush_modes();
+                        self.current_keys.push(c);
+                        self.current_turn.layer = Some(TurnLayer::Middle);
+                    } else if c == self.prefs.global_keys.repeat_turn {
+                        self.turn_repeat = self.turn_repeat.saturating_add(1);
                     } else if c == self.prefs.global_keys.rotate {
                         if self.keybind_set == KeybindSet::ThreeKey {
                             self.flush_modes();
@@ -392,10 +1103,13 @@ impl AppState {
 
                                 if let Some(side) = side {
                                     if let Some(from) = self.current_turn.from {
-                                        let turn_out = self.perform_turn(side, from, s);
+                                        let turn_out = if invert {
+                                            self.perform_turn(side, s, from)
+                                        } else {
+                                            self.perform_turn(side, from, s)
+                                        };
 
-                                        if turn_out.is_none() {
-                                            self.alert = self.prefs.alert_frames * 4 - 1;
+                                        if turn_out.is_err() {
                                             self.current_keys = self.current_keys
                                                 [..self.current_keys.len() - 2]
                                                 .to_string();
@@ -407,6 +1121,14 @@ impl AppState {
                                 }
                             }
                         }
+                        KeybindSet::FixedKey if self.puzzle.d == 2 => {
+                            // the only move on a side is flipping it along the other axis
+                            if let (Some(side), true) = (self.current_turn.side, just_pressed_side)
+                            {
+                                let other = 1 - ax(side);
+                                let _ = self.perform_turn(side, other, other);
+                            }
+                        }
                         KeybindSet::FixedKey if self.puzzle.d == 3 => {
                             let flip;
                             if let Some(s) =
@@ -447,14 +1169,41 @@ impl AppState {
                             {
                                 if flip {
                                     if side < 0 {
-                                        self.perform_turn(side, (!side + 1) % 3, (!side + 2) % 3);
+                                        let _ = self.perform_turn(
+                                            side,
+                                            (!side + 1) % 3,
+                                            (!side + 2) % 3,
+                                        );
                                     } else {
-                                        self.perform_turn(side, (side + 2) % 3, (side + 1) % 3);
+                                        let _ =
+                                            self.perform_turn(side, (side + 2) % 3, (side + 1) % 3);
                                     }
                                 } else if side < 0 {
-                                    self.perform_turn(side, (!side + 2) % 3, (!side + 1) % 3);
+                                    let _ =
+                                        self.perform_turn(side, (!side + 2) % 3, (!side + 1) % 3);
                                 } else {
-                                    self.perform_turn(side, (side + 1) % 3, (side + 2) % 3);
+                                    let _ = self.perform_turn(side, (side + 1) % 3, (side + 2) % 3);
+                                }
+                            }
+                        }
+                        KeybindSet::XyzKey => {
+                            // the side keys of the first three axes turn the held side around
+                            // the other axes, like turning a 3D puzzle
+                            let around = self.current_turn.side.and_then(|side| {
+                                let slot = self.prefs.axes.iter().take(3).enumerate().find_map(
+                                    |(s, ax)| {
+                                        (ax.pos.keys.side == c).then_some(s as i16).or_else(|| {
+                                            (ax.neg.keys.side == c).then_some(!(s as i16))
+                                        })
+                                    },
+                                )?;
+                                Some((side, xyz_axis(side, slot)))
+                            });
+                            if let Some((side, around)) = around {
+                                self.current_keys.push(c);
+                                let (from, to) = xyz_plane(side, around);
+                                if self.perform_turn(side, from, to).is_err() {
+                                    self.current_keys.pop();
                                 }
                             }
                         }
@@ -488,12 +1237,13 @@ impl AppState {
                                             }
                                         }
 
-                                        let mut turn_out = Some(()); // i wish we had try blocks
-
-                                        if axes.len() > self.puzzle.d as usize {
+                                        let turn_out = if axes.len() > self.puzzle.d as usize {
                                             // there was a duplicate in axes
-                                            turn_out = None;
-                                        }
+                                            self.reject_turn(TurnError::SameAxis);
+                                            Err(TurnError::SameAxis)
+                                        } else {
+                                            Ok(())
+                                        };
 
                                         let turn_out = turn_out.and_then(|_| {
                                             for i in 0..axes.len() {
@@ -511,8 +1261,7 @@ impl AppState {
                                             self.perform_turn(side, from, to)
                                         });
 
-                                        if turn_out.is_none() {
-                                            self.alert = self.prefs.alert_frames * 4 - 1;
+                                        if turn_out.is_err() {
                                             self.current_keys =
                                                 self.current_keys[..self.current_keys.len()
                                                     - self.current_turn.fixed.len()]
@@ -527,7 +1276,38 @@ impl AppState {
                 }
 
                 AppMode::LiveFilter => {
-                    if c == '+' || c == '!' {
+                    let style = self
+                        .live_filter_string
+                        .rsplit('+')
+                        .next()
+                        .and_then(|clause| clause.split_once('%'))
+                        .map(|(_, style)| style);
+                    let in_level = style.is_some();
+                    // letters after # are the color
+                    let in_color = style.is_some_and(|style| {
+                        style.starts_with(filters::RECOLOR) && style.len() < 7
+                    });
+                    // digits after @ and a side are the layer
+                    let in_layer = self
+                        .live_filter_string
+                        .rsplit_once(filters::LAYER)
+                        .is_some_and(|(_, rest)| {
+                            let mut rest = rest.chars();
+                            rest.next().is_some() && rest.all(|c| c.is_ascii_digit())
+                        });
+                    if c == '+'
+                        || c == '!'
+                        || c == '%'
+                        || c == '&'
+                        || c == '|'
+                        || c == '('
+                        || c == ')'
+                        || c == filters::SOLVED
+                        || c == filters::LAYER
+                        || ((in_level || in_layer) && c.is_ascii_digit())
+                        || (in_level && (c == filters::HIDDEN || c == filters::RECOLOR))
+                        || (in_color && c.is_ascii_hexdigit())
+                    {
                         self.live_filter_string.push(c);
                     } else if let Some((s, side)) = self
                         .prefs
@@ -572,9 +1352,23 @@ impl AppState {
                         self.live_filter_pending = filter.clone();
                     }
 
-                    if c == '\n' {
+                    if c == self.prefs.global_keys.save_live_filter {
+                        match filter_result {
+                            Ok(filter) => self.save_live_filter(filter),
+                            Err(err) => self.message = Some(err),
+                        }
+                    } else if c == '\n' {
                         if let Err(err) = filter_result {
                             self.message = Some(err);
+                        } else if let Some(ind) = self.editing_filter {
+                            let mut filter = self.live_filter_pending.clone();
+                            if filter.name.is_none() {
+                                filter.name = self.filters[ind].name.clone();
+                            }
+                            self.filters[ind] = filter;
+                            self.message = Some(format!("changed filter {}", ind + 1));
+                            self.flush_modes();
+                            self.mode = Default::default();
                         } else {
                             self.flush_modes();
                             self.mode = Default::default();
@@ -583,328 +1377,2786 @@ impl AppState {
                         }
                     }
                 }
+
+                // handled before the global keys
+                AppMode::MoveEntry
+                | AppMode::SaveAs
+                | AppMode::FilterName
+                | AppMode::Command
+                | AppMode::Marker
+                | AppMode::Summary
+                | AppMode::Sequence
+                | AppMode::Help
+                | AppMode::Settings => (),
             }
         }
     }
 
-    fn get_axis_key(&self, c: char) -> Option<i16> {
-        match self.keybind_axial {
-            KeybindAxial::Axial => self.prefs.axes.iter().position(|ax| ax.axis_key == c),
-            KeybindAxial::Side => self.prefs.axes.iter().enumerate().find_map(|(s, ax)| {
-                (ax.pos.keys.side == c)
-                    .then_some(s)
-                    .or_else(|| (ax.neg.keys.side == c).then_some(!s))
-            }),
+    // pick a binding, then press enter and the new key. leaving the settings writes the prefs.
+    fn process_settings_key(&mut self, c: char) {
+        let reset_mode = self.prefs.global_keys.reset_mode;
+        if self.rebinding {
+            self.rebinding = false;
+            if c == reset_mode {
+                return;
+            }
+            let (name, pointer) = &self.prefs.bindings(self.puzzle.d)[self.setting];
+            if let Err(err) = self.prefs.rebind(pointer, c) {
+                self.message = Some(err);
+                return;
+            }
+            self.prefs_changed = true;
+            let problems = self.prefs.problems(self.puzzle.d);
+            self.message = Some(if problems.is_empty() {
+                format!("{name} is now {c}")
+            } else {
+                format!("{name} is now {c}, but {}", problems.join(", "))
+            });
+        } else if c == '\n' {
+            self.rebinding = true;
+        } else if c == reset_mode {
+            self.mode = Default::default();
+            if self.prefs_changed {
+                self.message = Some(match self.prefs.write(&self.prefs_path) {
+                    Ok(()) => format!("saved keybinds to {}", self.prefs_path.display()),
+                    Err(err) => format!("could not save prefs: {err}"),
+                });
+                self.prefs_changed = false;
+            }
         }
-        .map(|s| s as i16)
     }
 
-    fn perform_turn(&mut self, side: i16, from: i16, to: i16) -> Option<()> {
-        let turn = match self.current_turn.layer {
-            Some(TurnLayer::WholePuzzle) => Turn::Puzzle(PuzzleTurn { from, to }),
-            _ => {
-                let mut layer_min;
-                let mut layer_max;
-                match self.current_turn.layer {
-                    None => {
-                        layer_min = self.puzzle.n - 1;
-                        layer_max = self.puzzle.n - 1;
-                    }
-                    Some(TurnLayer::Layer(l)) => {
-                        layer_min = self.puzzle.n - 1 - 2 * l;
-                        layer_max = self.puzzle.n - 1 - 2 * l;
-                    }
-                    Some(TurnLayer::WholePuzzle) => unreachable!(),
-                }
-                if side < 0 {
-                    layer_min *= -1;
-                    layer_max *= -1;
-                    std::mem::swap(&mut layer_min, &mut layer_max)
-                };
-                Turn::Side(SideTurn {
-                    side,
-                    layer_min,
-                    layer_max,
-                    from,
-                    to,
-                })
-            }
-        };
-
-        self.undo_history.push(turn.clone());
-        let turn_out = self.puzzle.turn(turn);
-
-        if turn_out.is_some() && self.puzzle.is_solved() {
-            self.message = Some("solved!".to_string());
+    fn move_setting(&mut self, offset: i16) {
+        if !self.rebinding {
+            let count = self.prefs.bindings(self.puzzle.d).len() as i16;
+            self.setting = (self.setting as i16 + offset).rem_euclid(count) as usize;
         }
-
-        turn_out
     }
 
-    fn get_message(&self) -> String {
-        if let Some(message) = &self.message {
-            return message.to_string();
-        }
-        match self.mode {
-            AppMode::Turn => self.current_keys.clone(),
-            AppMode::LiveFilter => format!("live filter: {}", self.live_filter_string),
+    // the settings list, scrolled to fit in the given number of rows
+    fn settings_lines(&self, rows: usize) -> Vec<String> {
+        let mut lines = vec![
+            format!(
+                "pick a key with the arrow keys and press enter to rebind it, {} to save and go back",
+                self.prefs.global_keys.reset_mode
+            ),
+            "".to_string(),
+        ];
+        let bindings = self.prefs.bindings(self.puzzle.d);
+        let shown = rows.saturating_sub(lines.len() + 2).max(1);
+        let start = self
+            .setting
+            .saturating_sub(shown / 2)
+            .min(bindings.len().saturating_sub(shown));
+        for (i, (name, pointer)) in bindings.iter().enumerate().skip(start).take(shown) {
+            let key = self.prefs.key_at(pointer).unwrap_or(prefs::NO_KEY);
+            let key = if i == self.setting && self.rebinding {
+                "press a key".to_string()
+            } else {
+                key.to_string()
+            };
+            let marker = if i == self.setting { '>' } else { ' ' };
+            lines.push(format!("{marker} {name:<20} {key}"));
         }
+        lines.push("".to_string());
+        lines.push(self.message.clone().unwrap_or_default());
+        lines
     }
-}
-
-/// Flat hypercube simulator
-#[derive(Parser, Debug)]
-#[command(version, about, long_about = None)]
-struct Args {
-    /// Number of layers of the puzzle
-    n: Option<i16>,
-    /// Dimension of the puzzle
-    d: Option<u16>,
-
-    /// Display in compact mode
-    #[arg(short, long)]
-    compact: bool,
-
-    /// File that contains the filters for the solve, one per line
-    #[arg(short, long)]
-    filters: Option<PathBuf>,
-
-    /// Log file to open
-    #[arg(short, long)]
-    log: Option<PathBuf>,
-
-    /// Display in vertical mode. This has no effect if d is even.
-    #[arg(long)]
-    vertical: bool,
 
-    /// Display using colored boxes.
-    #[arg(long)]
-    boxes: bool,
+    // keeps the old filters if any line has a mistake
+    fn reload_filters(&mut self) {
+        let Some(path) = &self.filters_path else {
+            self.message = Some("no filter file was given".to_string());
+            return;
+        };
+        match filters::read_file(path, &self.prefs) {
+            Ok(filters) => {
+                self.message = Some(format!("read {} filters", filters.len()));
+                self.filters = filters;
+                self.filter_ind = self.filter_ind.min(self.filters.len().saturating_sub(1));
+                // a solved puzzle hasn't been started, so its stages aren't done yet
+                if !self.puzzle.is_solved() {
+                    self.check_stage();
+                }
+            }
+            Err(errors) => self.message = Some(errors.join("; ")),
+        }
+    }
 
-    /// Preferences file
-    #[arg(short, long)]
-    prefs: Option<PathBuf>,
-}
+    // add the live filter to the end of the filters and use it, and to the filters file if there
+    // is one
+    fn save_live_filter(&mut self, filter: Filter) {
+        self.filters.push(filter);
+        self.filter_ind = self.filters.len() - 1;
+        self.use_live_filter = false;
+        let added = format!("added filter {}", self.filters.len());
+        self.message = Some(match &self.filters_path {
+            None => added,
+            Some(path) => {
+                let line = format!("{}\n", self.live_filter_string);
+                let written = std::fs::OpenOptions::new()
+                    .append(true)
+                    .open(path)
+                    .and_then(|mut file| file.write_all(line.as_bytes()));
+                match written {
+                    Ok(()) => format!("{added} to {}", path.display()),
+                    Err(err) => format!("{added}, but could not write it to the file: {err}"),
+                }
+            }
+        });
+        self.flush_modes();
+        self.mode = Default::default();
+    }
 
-fn main_inner() -> Result<(), Box<dyn std::error::Error>> {
-    let args = Args::parse();
-    let prefs: Prefs = {
-        let path = args
-            .prefs
-            .unwrap_or(PathBuf::from(prefs::DEFAULT_FILE_PATH_STR));
-        let file = File::open(path)?;
-        let reader = BufReader::new(file);
-        serde_json::from_reader(reader)?
-    };
+    // splits, markers, groups, and the metadata only make sense for the moves since the scramble
+    fn reset_splits(&mut self) {
+        self.splits = vec![];
+        self.markers = vec![];
+        self.snapshots.clear();
+        self.solve_recorded = false;
+        self.groups = vec![];
+        self.created = chrono::Local::now().to_rfc3339();
+        self.open_since = Instant::now();
+        self.open_before = Duration::ZERO;
+        self.comment = None;
+        self.blindfold = None;
+        if self.auto_advance {
+            self.filter_ind = 0;
+        }
+    }
 
-    let mut state;
-    if let Some(log_file) = args.log {
-        let file = File::open(log_file)?;
-        let reader = BufReader::new(file);
-        let app_log = serde_json::from_reader(reader).map_err(std::io::Error::other)?;
-        state = AppState::from_app_log(app_log, prefs);
-    } else {
-        let Some(n) = args.n else {
-            return Err("n must be specified".into());
-        };
-        let Some(d) = args.d else {
-            return Err("d must be specified".into());
-        };
+    // the filter the puzzle is drawn with, or None for no filter
+    fn shown_filter(&self) -> Option<&Filter> {
+        if matches!(self.mode, AppMode::LiveFilter) {
+            Some(&self.live_filter_pending)
+        } else if self.use_live_filter {
+            Some(&self.live_filter)
+        } else {
+            self.filters.get(self.filter_ind)
+        }
+    }
 
-        if d > prefs.max_dim() {
-            return Err(format!(
-                "dimension should be less than or equal to {}",
-                prefs.max_dim()
-            )
-            .into());
+    // drop the splits of moves that were undone, going back to their stages, and advance past
+    // every filter whose pieces are all solved
+    fn check_stage(&mut self) {
+        // a replay steps through moves that are already recorded, splits and all
+        if self.replaying {
+            return;
         }
-        if d < 1 {
-            return Err("dimension should be greater than 0".into());
+        let count = self.history.move_count();
+        if let Some(first) = self.splits.iter().position(|split| split.moves > count) {
+            if self.auto_advance && !self.use_live_filter {
+                self.filter_ind = self.splits[first].filter_ind;
+            }
+            self.splits.truncate(first);
         }
-        if n > prefs.max_layers() {
-            return Err(format!(
-                "side should be less than or equal to {}",
-                prefs.max_layers()
-            )
-            .into());
+        if !self.auto_advance || self.use_live_filter {
+            return;
         }
-        if d < 1 {
-            return Err("side should be greater than 0".into());
+        let context = FilterContext::new(&self.puzzle);
+        let pieces = self.puzzle.pieces();
+        while let Some(filter) = self.filters.get(self.filter_ind) {
+            let done = pieces.iter().all(|piece| {
+                self.puzzle.is_void(&piece.position)
+                    || !filter.matches(piece, &context)
+                    || piece.is_solved(&context.face_colors)
+            });
+            if !done {
+                break;
+            }
+            // a stage from a log that was loaded already has its split
+            if self
+                .splits
+                .iter()
+                .any(|split| split.filter_ind == self.filter_ind)
+            {
+                self.filter_ind += 1;
+                continue;
+            }
+            self.splits.push(Split {
+                filter_ind: self.filter_ind,
+                moves: self.history.move_count(),
+            });
+            self.message = Some(format!(
+                "stage {} done in {} moves",
+                self.filter_ind + 1,
+                self.history.move_count()
+            ));
+            self.filter_ind += 1;
         }
-
-        state = AppState::new(n, d, prefs);
     }
 
-    if let Some(path) = args.filters {
-        let filters_str = std::fs::read_to_string(path).expect("Invalid filter file");
-        state.filters = filters_str
-            .lines()
-            .map(|l| Filter::parse(&l, &state.prefs).unwrap())
-            .collect();
+    // every key is typed into the text, so global keys don't apply here
+    fn process_text_key(&mut self, c: char) {
+        if c == self.prefs.global_keys.reset_mode {
+            self.mode = Default::default();
+            self.flush_modes();
+        } else if c == BACKSPACE_CODE {
+            self.text_entry.pop();
+        } else if c == '\n' {
+            match self.mode {
+                AppMode::MoveEntry => self.enter_moves(),
+                AppMode::FilterName => self.enter_filter_name(),
+                AppMode::SaveAs => self.enter_save_as(),
+                AppMode::Command => self.enter_command(),
+                AppMode::Marker => self.enter_marker(),
+                _ => unreachable!(),
+            }
+        } else if !c.is_control() {
+            self.text_entry.push(c);
+        }
     }
 
-    let layout = Layout::make_layout(state.puzzle.n, state.puzzle.d, args.compact, args.vertical)
-        .move_right(1);
-    //println!("{:?}", layout.keybind_hints);
-    //return Ok(());
-
-    let mut stdout = io::stdout();
-    terminal::enable_raw_mode()?;
-    stdout.execute(terminal::EnterAlternateScreen)?;
-    stdout.execute(cursor::Hide)?;
-
-    loop {
-        let frame_begin = Instant::now();
+    fn enter_moves(&mut self) {
+        match Alg::parse(&self.text_entry, &self.prefs, self.puzzle.shape()) {
+            Err(err) => self.message = Some(err),
+            Ok(alg) => {
+                let written = self.text_entry.trim().to_string();
+                self.mode = Default::default();
+                self.flush_modes();
+                if self.apply_alg(&alg, &written) && self.message.is_none() {
+                    self.message = Some(format!("applied {} moves", alg.expand().len()));
+                }
+            }
+        }
+    }
+
+    // markers after the current move were for moves that have been undone, so they are replaced
+    fn enter_marker(&mut self) {
+        let name = self.text_entry.trim().to_string();
+        if name.is_empty() {
+            self.message = Some("type a name for the marker".to_string());
+            return;
+        }
+        let moves = self.history.move_count();
+        self.markers.retain(|marker| marker.moves <= moves);
+        self.message = Some(format!("marked {name} at move {moves}"));
+        self.markers.push(Marker {
+            name,
+            moves,
+            time: self.timer.elapsed(),
+        });
+        self.changes += 1;
+        self.mode = Default::default();
+        self.flush_modes();
+    }
+
+    // returns false if one of the moves can't be made, stopping there
+    fn apply_alg(&mut self, alg: &Alg, written: &str) -> bool {
+        let start = self.history.move_count();
+        for (i, turn) in alg.expand().into_iter().enumerate() {
+            if let Err(err) = self.apply_turn(turn) {
+                self.message = Some(format!("move {} can't be made: {err}", i + 1));
+                return false;
+            }
+        }
+        self.last_alg = Some(written.to_string());
+        if alg.is_grouped() {
+            self.groups.push(Group {
+                start,
+                len: self.history.move_count().saturating_sub(start),
+                notation: written.to_string(),
+            });
+        }
+        true
+    }
+
+    fn enter_save_as(&mut self) {
+        if self.text_entry.trim().is_empty() {
+            self.message = Some("type a file name to save to".to_string());
+            return;
+        }
+        if self.save_as(PathBuf::from(self.text_entry.trim())) {
+            self.mode = Default::default();
+            self.flush_modes();
+        }
+    }
+
+    // the new name is kept for later saves, but only if saving to it works
+    fn save_as(&mut self, path: PathBuf) -> bool {
+        let old_filename = std::mem::replace(&mut self.filename, path);
+        match self.save() {
+            Ok(()) => {
+                self.message = Some(format!("saved to {}", self.filename.display()));
+                true
+            }
+            Err(err) => {
+                self.message = Some(format!("could not save: {err}"));
+                self.filename = old_filename;
+                false
+            }
+        }
+    }
+
+    // a mistake in the command leaves it there to be fixed
+    fn enter_command(&mut self) {
+        let command = match commands::Command::parse(&self.text_entry) {
+            Ok(command) => command,
+            Err(err) => {
+                self.message = Some(err);
+                return;
+            }
+        };
+        self.mode = Default::default();
+        self.flush_modes();
+        match command {
+            commands::Command::Save(None) => match self.save() {
+                Ok(()) => self.message = Some(format!("saved to {}", self.filename.display())),
+                Err(err) => self.message = Some(format!("could not save: {err}")),
+            },
+            commands::Command::Save(Some(path)) => {
+                self.save_as(path);
+            }
+            commands::Command::Scramble(_) | commands::Command::Partial if self.replaying => {
+                self.message = Some("press escape to end the replay first".to_string());
+            }
+            commands::Command::Scramble(_) | commands::Command::Partial if self.trial.is_some() => {
+                self.message = Some("end the trial first".to_string());
+            }
+            commands::Command::Scramble(length) => {
+                self.new_scramble(length.unwrap_or(self.scramble_length))
+            }
+            commands::Command::Partial => self.partial_scramble(),
+            commands::Command::Filter(st) if st.is_empty() => {
+                self.use_live_filter = false;
+                self.message = Some("using the filter list".to_string());
+            }
+            commands::Command::Filter(st) => match Filter::parse(&st, &self.prefs) {
+                Ok(filter) => {
+                    self.live_filter_pending = filter.clone();
+                    self.live_filter = filter;
+                    self.use_live_filter = true;
+                }
+                Err(err) => self.message = Some(err),
+            },
+            commands::Command::Seek(target) => self.seek(target),
+            commands::Command::Apply(_) if self.replaying => {
+                self.message = Some("press escape to end the replay first".to_string());
+            }
+            commands::Command::Apply(path) => {
+                self.message = Some(match self.apply_file(&path) {
+                    Ok(count) => format!("applied {count} moves from {}", path.display()),
+                    Err(err) => err,
+                });
+            }
+            commands::Command::Export(path) => {
+                let path = path.unwrap_or_else(|| self.filename.with_extension("txt"));
+                self.message = Some(match std::fs::write(&path, self.export_text()) {
+                    Ok(()) => format!("exported to {}", path.display()),
+                    Err(err) => format!("could not export: {err}"),
+                });
+            }
+            commands::Command::Comment(comment) => {
+                self.message = Some(if comment.is_empty() {
+                    "removed the comment".to_string()
+                } else {
+                    "changed the comment".to_string()
+                });
+                self.comment = (!comment.is_empty()).then_some(comment);
+                self.changes += 1;
+            }
+            commands::Command::Keep if self.trial.is_none() => {
+                self.message = Some("there is no trial to keep".to_string());
+            }
+            commands::Command::Keep => {
+                self.trial = None;
+                self.message = Some("kept the moves from the trial".to_string());
+                self.changes += 1;
+            }
+            commands::Command::Blindfold => self.blindfold_step(),
+            commands::Command::Cycles => self.next_cycle(),
+            commands::Command::Locate(sides) => self.locate(&sides),
+            commands::Command::Progress => {
+                self.message = Some(if self.colors_hidden() {
+                    "the colors are hidden".to_string()
+                } else {
+                    let types = self
+                        .puzzle
+                        .progress()
+                        .into_iter()
+                        .map(|(stickers, (solved, total))| format!("{stickers}c {solved}/{total}"))
+                        .join(", ");
+                    format!("solved pieces by number of colors: {types}")
+                })
+            }
+            commands::Command::Rewind if self.history.move_count() == 0 => {
+                self.message = Some("nothing to undo".to_string());
+            }
+            commands::Command::Rewind if self.prefs.rewind_speed == 0 => self.seek(Some(0)),
+            commands::Command::Rewind => {
+                self.rewind = Some((Instant::now(), 0));
+                self.message = Some("rewinding, press any key to stop".to_string());
+            }
+            commands::Command::Order(moves) => self.alg_order(&moves),
+            commands::Command::Sequence => self.show_sequence(),
+            commands::Command::Only(sides) if sides.is_empty() => {
+                self.only_sides = None;
+                self.message = Some("every side can turn".to_string());
+            }
+            commands::Command::Only(sides) => match self.parse_sides(&sides) {
+                Ok(parsed) => {
+                    self.only_sides = Some(parsed);
+                    self.message = Some(format!("only {sides} can turn"));
+                }
+                Err(err) => self.message = Some(err),
+            },
+            commands::Command::Keyframe => {
+                self.keyframe = Some(self.puzzle.clone());
+                self.show_diff = true;
+                self.message = Some(format!(
+                    "kept a keyframe at move {}, and highlighting what changes from it",
+                    self.history.move_count()
+                ));
+            }
+            commands::Command::Diff => match &self.keyframe {
+                None => {
+                    let command = self.prefs.global_keys.command_mode;
+                    self.message = Some(format!("no keyframe yet, {command}keyframe keeps one"));
+                }
+                Some(keyframe) => {
+                    self.show_diff = !self.show_diff;
+                    self.message = Some(if self.show_diff {
+                        let changed = self.puzzle.changed_stickers(keyframe);
+                        format!("{changed} stickers changed since the keyframe")
+                    } else {
+                        "not highlighting changes".to_string()
+                    });
+                }
+            },
+            commands::Command::Reference(reference) => {
+                self.reference = reference.filter(|&reference| self.reference != Some(reference));
+                self.message = Some(
+                    match self.reference {
+                        Some(commands::Reference::Solved) => {
+                            "drawing the solved puzzle next to this one"
+                        }
+                        Some(commands::Reference::Scramble) => {
+                            "drawing the scramble next to this one"
+                        }
+                        None => "not drawing a reference",
+                    }
+                    .to_string(),
+                );
+            }
+            commands::Command::Quit { force } => self.quit_requested = Some(force),
+        }
+    }
+
+    // the keyframe to highlight the changes from, if they're highlighted. they would give away
+    // where the colors went during a blindfolded solve.
+    fn diff_keyframe(&self) -> Option<&Puzzle> {
+        (self.keyframe.as_ref()).filter(|_| self.show_diff && !self.colors_hidden())
+    }
+
+    // the puzzle the reference is drawn from
+    fn reference_puzzle(&self) -> Option<Cow<'_, Puzzle>> {
+        match self.reference? {
+            commands::Reference::Solved => Some(Cow::Owned(self.solved_puzzle())),
+            commands::Reference::Scramble => Some(Cow::Borrowed(&self.scramble)),
+        }
+    }
+
+    // undo the moves the rewind command is due to have undone by now
+    fn rewind_step(&mut self) {
+        let Some((started, undone)) = &mut self.rewind else {
+            return;
+        };
+        let due = (started.elapsed().as_secs_f64() * self.prefs.rewind_speed as f64) as usize;
+        let mut moved = HashSet::new();
+        while *undone < due {
+            let Some(turn) = self.history.undo() else {
+                self.rewind = None;
+                self.message = Some("rewound to the scramble".to_string());
+                break;
+            };
+            *undone += 1;
+            moved.extend(self.puzzle.turn(turn.inverse()).unwrap_or_default());
+            self.changes += 1;
+            self.last_turn = Some(turn.inverse());
+        }
+        if !moved.is_empty() {
+            self.animate(Some(moved.into_iter().collect()));
+        }
+        self.check_stage();
+    }
+
+    // start memorizing for a blindfolded solve, end the memo by hiding the colors, or show them
+    // again before the end
+    fn blindfold_step(&mut self) {
+        let command = format!("{}blindfold", self.prefs.global_keys.command_mode);
+        let Some(blindfold) = &mut self.blindfold else {
+            if self.replaying || self.trial.is_some() {
+                self.message = Some("end the replay or trial first".to_string());
+            } else if self.scramble.is_solved() || self.history.move_count() > 0 {
+                self.message =
+                    Some("a blindfolded solve starts right after a scramble".to_string());
+            } else {
+                self.blindfold = Some(Blindfold::default());
+                self.timer.start();
+                self.changes += 1;
+                self.message = Some(format!(
+                    "memorizing: {command} again or a turn hides the colors"
+                ));
+            }
+            return;
+        };
+        if blindfold.memo.is_none() {
+            self.end_memo();
+        } else if blindfold.hidden() {
+            blindfold.revealed = true;
+            self.changes += 1;
+            self.message = Some("showing the colors, so the solve isn't blindfolded".to_string());
+        } else {
+            self.message = Some("the blindfolded solve is over, scramble for another".to_string());
+        }
+    }
+
+    fn end_memo(&mut self) {
+        if let Some(blindfold) = &mut self.blindfold {
+            blindfold.memo = self.timer.elapsed();
+            self.changes += 1;
+            self.message = Some(format!(
+                "memo {}, the colors are hidden until it's solved or {}blindfold shows them",
+                timer::format_duration(blindfold.memo.unwrap_or_default()),
+                self.prefs.global_keys.command_mode
+            ));
+        }
+    }
+
+    // pick out the cycle after the one picked last, or the first one if the puzzle changed since
+    fn next_cycle(&mut self) {
+        if self.colors_hidden() {
+            self.message = Some("the colors are hidden".to_string());
+            return;
+        }
+        let found = match cycles::find(&self.puzzle, &self.prefs) {
+            Ok(found) => found,
+            Err(err) => {
+                self.message = Some(err);
+                return;
+            }
+        };
+        let names = |slots: &[cycles::Slot]| slots.iter().map(|(_, name)| name).join(" ");
+        let twisted = match found.twisted.len() {
+            0 => "".to_string(),
+            _ => format!(", twisted: {}", names(&found.twisted)),
+        };
+        if found.cycles.is_empty() {
+            self.message = Some(match found.twisted.len() {
+                0 => "every piece is solved".to_string(),
+                _ => format!("no cycles{twisted}"),
+            });
+            self.highlight = Some(Highlight {
+                changes: self.changes,
+                pieces: found.twisted.into_iter().map(|(pos, _)| pos).collect(),
+                cycle: 0,
+            });
+            return;
+        }
+        let ind = match self.current_highlight() {
+            Some(highlight) => (highlight.cycle + 1) % found.cycles.len(),
+            None => 0,
+        };
+        let cycle = &found.cycles[ind];
+        self.message = Some(format!(
+            "cycle {} of {}: {} ({} pieces){twisted}",
+            ind + 1,
+            found.cycles.len(),
+            names(cycle),
+            cycle.len()
+        ));
+        self.highlight = Some(Highlight {
+            changes: self.changes,
+            pieces: cycle.iter().map(|(pos, _)| pos.clone()).collect(),
+            cycle: ind,
+        });
+    }
+
+    // pick out the pieces with the colors of the sides, and the slots they belong in
+    fn locate(&mut self, sides: &str) {
+        if self.colors_hidden() {
+            self.message = Some("the colors are hidden".to_string());
+            return;
+        }
+        let located = match cycles::locate(&self.puzzle, &self.prefs, sides) {
+            Ok(located) => located,
+            Err(err) => {
+                self.message = Some(err);
+                return;
+            }
+        };
+        let names = |slots: &[cycles::Slot]| slots.iter().map(|(_, name)| name).join(" ");
+        self.message = Some(format!(
+            "{sides} is at {}, and belongs at {}",
+            names(&located.current),
+            names(&located.solved)
+        ));
+        self.highlight = Some(Highlight {
+            changes: self.changes,
+            pieces: located
+                .current
+                .into_iter()
+                .chain(located.solved)
+                .map(|(pos, _)| pos)
+                .collect(),
+            cycle: 0,
+        });
+    }
+
+    // sides written by name, like RUF, which have to be in the puzzle
+    fn parse_sides(&self, st: &str) -> Result<Vec<i16>, String> {
+        st.chars()
+            .filter(|c| !c.is_whitespace())
+            .map(|c| {
+                notation::parse_side(&self.prefs, c)
+                    .filter(|&side| (ax(side) as u16) < self.puzzle.d)
+                    .ok_or_else(|| format!("{c} isn't a side of the puzzle"))
+            })
+            .collect()
+    }
+
+    // make the moves over and over on a solved copy of the puzzle until it is solved again
+    fn alg_order(&mut self, moves: &str) {
+        let written = if !moves.is_empty() {
+            moves.to_string()
+        } else if let Some(last) = &self.last_alg {
+            last.clone()
+        } else {
+            self.message = Some("order takes moves, or uses the last ones typed".to_string());
+            return;
+        };
+        let alg = match Alg::parse(&written, &self.prefs, self.puzzle.shape()) {
+            Ok(alg) => alg,
+            Err(err) => {
+                self.message = Some(err);
+                return;
+            }
+        };
+        let turns = alg.expand();
+        let start = self.puzzle.make_solved_like();
+        let mut scratch = start.clone();
+        for order in 1..=MAX_ORDER {
+            for (i, turn) in turns.iter().enumerate() {
+                if let Err(err) = scratch.turn(turn.clone()) {
+                    self.message = Some(format!("move {} can't be made: {err}", i + 1));
+                    return;
+                }
+            }
+            if scratch == start {
+                self.message = Some(format!("{written} has order {order}"));
+                return;
+            }
+        }
+        self.message = Some(format!("{written} has order more than {MAX_ORDER}"));
+    }
+
+    // the themes in the order of the prefs, then the colors from before any theme
+    fn next_theme(&mut self) {
+        let next = match self.prefs.theme() {
+            None => self.prefs.themes.first(),
+            Some(current) => self
+                .prefs
+                .themes
+                .iter()
+                .skip_while(|theme| theme.name != current)
+                .nth(1),
+        }
+        .map(|theme| theme.name.clone());
+        self.message = Some(match self.prefs.set_theme(next.as_deref()) {
+            Ok(()) => format!("theme: {}", next.as_deref().unwrap_or("none")),
+            Err(err) => err,
+        });
+        self.changes += 1;
+    }
+
+    // the next few moves of a solution from here, and how many moves it has
+    fn hint(&mut self) {
+        if self.colors_hidden() {
+            self.message = Some("the colors are hidden".to_string());
+            return;
+        }
+        self.message = Some(match solver::solve(&self.puzzle) {
+            Err(err) => err,
+            Ok(turns) if turns.is_empty() => "the puzzle is solved".to_string(),
+            Ok(turns) => {
+                let shown = self.prefs.hint_moves.clamp(1, turns.len());
+                format!(
+                    "hint: {} ({} moves to solve)",
+                    notation::to_sequence(&turns[..shown], &self.prefs, self.puzzle.shape()),
+                    turns.len()
+                )
+            }
+        });
+    }
+
+    // the pieces picked out, if the puzzle hasn't changed since
+    fn current_highlight(&self) -> Option<&Highlight> {
+        self.highlight
+            .as_ref()
+            .filter(|highlight| highlight.changes == self.changes)
+    }
+
+    // the colors can't be seen during a blindfolded solve
+    fn colors_hidden(&self) -> bool {
+        self.blindfold.as_ref().is_some_and(Blindfold::hidden)
+    }
+
+    fn start_trial(&mut self) {
+        self.trial = Some(Trial {
+            puzzle: self.puzzle.clone(),
+            history: self.history.clone(),
+            splits: self.splits.clone(),
+            markers: self.markers.clone(),
+            groups: self.groups.clone(),
+            timer: self.timer.clone(),
+            last_turn: self.last_turn.clone(),
+        });
+        self.message = Some(format!(
+            "started a trial: {} throws away the moves made in it, and {}keep keeps them",
+            self.prefs.global_keys.trial_mode, self.prefs.global_keys.command_mode
+        ));
+    }
+
+    fn discard_trial(&mut self) {
+        let Some(trial) = self.trial.take() else {
+            return;
+        };
+        self.puzzle = trial.puzzle;
+        self.history = trial.history;
+        self.splits = trial.splits;
+        self.markers = trial.markers;
+        self.groups = trial.groups;
+        self.timer = trial.timer;
+        self.last_turn = trial.last_turn;
+        // snapshots of nodes made in the trial would be wrong for new nodes with the same index
+        let nodes = self.history.node_count();
+        self.snapshots.retain(|&node, _| node < nodes);
+        self.changes += 1;
+        self.message = Some("threw away the trial".to_string());
+        self.animate(None);
+    }
+
+    // make the moves in a text file, written like typed moves. anything after // on a line is
+    // left out, so exported solves can be applied again.
+    fn apply_file(&mut self, path: &Path) -> Result<usize, String> {
+        let st = std::fs::read_to_string(path)
+            .map_err(|err| format!("could not read {}: {err}", path.display()))?;
+        let moves = st
+            .lines()
+            .map(|line| line.split("//").next().unwrap_or_default())
+            .join(" ");
+        let alg = Alg::parse(&moves, &self.prefs, self.puzzle.shape())
+            .map_err(|err| format!("in {}: {err}", path.display()))?;
+        if !self.apply_alg(&alg, moves.trim()) {
+            return Err(format!(
+                "in {}: {}",
+                path.display(),
+                self.message.take().unwrap_or_default()
+            ));
+        }
+        Ok(alg.expand().len())
+    }
+
+    // the scramble and the moves in notation for posting, with a line for each stage
+    fn export_text(&self) -> String {
+        let notation =
+            |turns: &[Turn]| notation::to_sequence(turns, &self.prefs, self.puzzle.shape());
+        let mut lines = vec![self.puzzle.size_name()];
+        match (
+            &self.scramble_source,
+            self.scramble_source
+                .turns(&self.scramble.make_solved_like()),
+        ) {
+            (ScrambleSource::Random { seed, .. }, Some(turns)) => {
+                lines.push(format!(
+                    "scramble ({} turns, seed {}):",
+                    turns.len(),
+                    seed.unwrap_or_default()
+                ));
+                lines.push(notation(&turns));
+            }
+            (ScrambleSource::RandomState { seed }, _) => {
+                lines.push(format!("scramble: a random state from seed {seed}"))
+            }
+            (ScrambleSource::Partial { seed, pieces }, _) => lines.push(format!(
+                "scramble: a random state of {} pieces from seed {seed}",
+                pieces.len()
+            )),
+            (ScrambleSource::Solved, _) => lines.push("scramble: none".to_string()),
+            _ => lines.push("scramble: unknown".to_string()),
+        }
+
+        lines.push("".to_string());
+        lines.push("solution:".to_string());
+        let moves = self.history.moves();
+        // a line ends at each split and each marker
+        let mut ends: Vec<(usize, String)> = self
+            .splits
+            .iter()
+            .map(|split| {
+                let name = self
+                    .filters
+                    .get(split.filter_ind)
+                    .and_then(|filter| filter.name.clone())
+                    .unwrap_or_else(|| format!("stage {}", split.filter_ind + 1));
+                (split.moves, name)
+            })
+            .chain(
+                self.markers
+                    .iter()
+                    .map(|marker| (marker.moves, marker.name.clone())),
+            )
+            .collect();
+        ends.sort_by_key(|&(moves, _)| moves);
+        let mut start = 0;
+        for (end, name) in ends {
+            let end = end.clamp(start, moves.len());
+            lines.push(format!(
+                "{}  // {name}, {} moves",
+                notation(&moves[start..end]),
+                end - start
+            ));
+            start = end;
+        }
+        if start < moves.len() {
+            lines.push(notation(&moves[start..]));
+        }
+
+        lines.push("".to_string());
+        let mut total = MoveCounts::new(&moves).summary();
+        if let Some(time) = self.timer.final_time() {
+            total.push_str(&format!(", time {}", timer::format_duration(time)));
+        }
+        lines.push(total);
+        lines.join("\n") + "\n"
+    }
+
+    // a new scramble replaces the moves, keeping the seed if one was given
+    fn new_scramble(&mut self, length: usize) {
+        if self.puzzle.d < 2 {
+            self.message = Some("a 1D puzzle has no turns to scramble with".to_string());
+            return;
+        }
+        let seed = self.seed.unwrap_or_else(|| self.rng.gen());
+        self.scramble_from_seed(length, seed);
+    }
+
+    fn scramble_from_seed(&mut self, length: usize, seed: u64) {
+        self.puzzle = self.solved_puzzle();
+        let mut rng = StdRng::seed_from_u64(seed);
+        if self.puzzle.has_random_state() {
+            self.puzzle.scramble_random_state(&mut rng);
+            self.message = Some(format!("scrambled to a random state (seed {seed})"));
+            self.scramble_source = ScrambleSource::RandomState { seed };
+        } else {
+            self.puzzle.scramble(length, &mut rng);
+            self.message = Some(format!("scrambled with {length} turns (seed {seed})"));
+            self.scramble_source = ScrambleSource::Random {
+                moves: length,
+                seed: Some(seed),
+            };
+        }
+        self.start_scramble();
+    }
+
+    // scramble only the pieces the current filter shows, for practicing a few of them
+    fn partial_scramble(&mut self) {
+        let solved = self.solved_puzzle();
+        if !solved.has_random_state() {
+            self.message = Some("only 2^3 and 3^3 puzzles can be partly scrambled".to_string());
+            return;
+        }
+        let filter = if self.use_live_filter {
+            &self.live_filter
+        } else if let Some(filter) = self.filters.get(self.filter_ind) {
+            filter
+        } else {
+            &Default::default()
+        };
+        let context = FilterContext::new(&solved);
+        let pieces: Vec<Vec<i16>> = solved
+            .pieces()
+            .into_iter()
+            .filter(|piece| filter.matches(piece, &context))
+            .map(|piece| piece.position)
+            .collect();
+        if pieces.is_empty() {
+            self.message = Some("the filter doesn't show any pieces".to_string());
+            return;
+        }
+
+        let seed = self.seed.unwrap_or_else(|| self.rng.gen());
+        let mut puzzle = solved.clone();
+        puzzle.scramble_random_pieces(&pieces, &mut StdRng::seed_from_u64(seed));
+        // a single piece can't move on its own, and a few pieces can land solved by chance
+        if puzzle == solved {
+            self.message = Some("the pieces the filter shows ended up solved".to_string());
+            return;
+        }
+        self.puzzle = puzzle;
+        self.message = Some(format!(
+            "scrambled the pieces the filter shows (seed {seed})"
+        ));
+        self.scramble_source = ScrambleSource::Partial { seed, pieces };
+        self.start_scramble();
+    }
+
+    // the puzzle was just scrambled, so it is the new scramble with no moves
+    fn start_scramble(&mut self) {
+        self.timer.arm();
+        self.scramble = self.puzzle.clone();
+        self.history = Default::default();
+        self.changes += 1;
+        self.last_turn = None;
+        self.reset_splits();
+        if self.prefs.show_scramble {
+            self.show_sequence();
+        }
+    }
+
+    // the turns of a scramble made with turns, or else the inverse of a solution to it
+    fn scramble_turns(&self) -> Result<Vec<Turn>, String> {
+        if let Some(turns) = self
+            .scramble_source
+            .turns(&self.scramble.make_solved_like())
+        {
+            return Ok(turns);
+        }
+        let solution = solver::solve(&self.scramble)
+            .map_err(|_| "this scramble wasn't made with turns that can be shown".to_string())?;
+        Ok(solution.iter().rev().map(Turn::inverse).collect())
+    }
+
+    fn show_sequence(&mut self) {
+        match self.scramble_turns() {
+            Ok(turns) => {
+                self.sequence = notation::to_sequence(&turns, &self.prefs, self.scramble.shape());
+                self.mode = AppMode::Sequence;
+            }
+            Err(err) => self.message = Some(err),
+        }
+    }
+
+    // the scramble sequence wrapped to the terminal, with as much as fits
+    fn sequence_lines(&self, columns: usize, rows: usize) -> Vec<String> {
+        let count = self.sequence.split_whitespace().count();
+        let mut lines = vec![
+            format!(
+                "scramble of the {} in {count} moves:",
+                self.scramble.size_name()
+            ),
+            "".to_string(),
+        ];
+        let shown = rows.saturating_sub(lines.len() + 2).max(1);
+        let mut wrapped: Vec<String> = vec![];
+        for word in self.sequence.split_whitespace() {
+            match wrapped.last_mut() {
+                Some(line) if line.len() + 1 + word.len() <= columns => {
+                    line.push(' ');
+                    line.push_str(word);
+                }
+                _ => wrapped.push(word.to_string()),
+            }
+        }
+        let more = wrapped.len() > shown;
+        lines.extend(wrapped.into_iter().take(shown));
+        lines.push("".to_string());
+        lines.push(if more {
+            format!(
+                "and more, {}export writes them all. any key goes back",
+                self.prefs.global_keys.command_mode
+            )
+        } else {
+            "any key goes back".to_string()
+        });
+        lines
+    }
+
+    // undo or redo along the branches redo follows until target moves are made, or as far as
+    // they go. with no target, redo everything.
+    fn seek(&mut self, target: Option<usize>) {
+        let mut undone = vec![];
+        let mut redone = vec![];
+        while target.is_some_and(|target| self.history.move_count() > target) {
+            let Some(turn) = self.history.undo() else {
+                break;
+            };
+            undone.push(turn);
+        }
+        while target.is_none_or(|target| self.history.move_count() < target) {
+            let Some(turn) = self.history.redo() else {
+                break;
+            };
+            redone.push(turn);
+        }
+        self.changes += undone.len() + redone.len();
+
+        // going back a long way is faster by making the moves left from the closest snapshot or
+        // the scramble
+        let mut moved = HashSet::new();
+        let (from, turns) = self
+            .history
+            .moves_since(|node| self.snapshots.contains_key(&node));
+        if turns.len() < undone.len() + redone.len() {
+            self.puzzle = match from {
+                Some(node) => self.snapshots[&node].clone(),
+                None => self.scramble.clone(),
+            };
+            for turn in turns {
+                let _ = self.puzzle.turn(turn);
+            }
+        } else {
+            for turn in undone {
+                moved.extend(self.puzzle.turn(turn.inverse()).unwrap_or_default());
+            }
+            for turn in redone {
+                moved.extend(self.puzzle.turn(turn).unwrap_or_default());
+            }
+        }
+
+        self.last_turn = None;
+        let count = self.history.move_count();
+        self.take_snapshot(count);
+        self.message = Some(match target {
+            Some(target) if count == target => format!("at move {count}"),
+            _ => format!("at move {count}, the last one to redo"),
+        });
+        if !moved.is_empty() {
+            self.animate(Some(moved.into_iter().collect()));
+        }
+        self.check_stage();
+    }
+
+    // use the first filter with the typed name, or else the first one it starts
+    fn enter_filter_name(&mut self) {
+        let typed = self.text_entry.trim().to_lowercase();
+        let names: Vec<String> = self
+            .filters
+            .iter()
+            .map(|filter| filter.name.clone().unwrap_or_default().to_lowercase())
+            .collect();
+        let found = names
+            .iter()
+            .position(|name| *name == typed)
+            .or_else(|| names.iter().position(|name| name.starts_with(&typed)));
+        match found {
+            Some(ind) => {
+                self.filter_ind = ind;
+                self.use_live_filter = false;
+                self.mode = Default::default();
+                self.flush_modes();
+            }
+            None => self.message = Some(format!("no filter named {}", self.text_entry.trim())),
+        }
+    }
+
+    // click a sticker, then click where it should go, to turn the layer it is in. the second
+    // sticker has to be on the same side, one turn away.
+    fn process_click(&mut self, pos: Option<Vec<i16>>) {
+        self.message = None;
+        if !matches!(self.mode, AppMode::Turn) || self.replaying || self.rewind.is_some() {
+            return;
+        }
+        let Some(pos) = pos.filter(|pos| self.puzzle.face_axis(pos).is_some()) else {
+            self.flush_modes();
+            return;
+        };
+        let Some(first) = self.clicked.take() else {
+            self.message = Some(format!(
+                "{}, click where the sticker should go",
+                self.piece_info(&pos)
+            ));
+            self.clicked = Some(pos);
+            return;
+        };
+
+        let side = self.puzzle.face(&first);
+        // turning from one axis to another takes the sticker at p to p with p[to] = p[from] and
+        // p[from] = -p[to]. in 2D, turning an axis to itself flips it.
+        let rotation = (0..self.puzzle.d as i16)
+            .cartesian_product(0..self.puzzle.d as i16)
+            .filter(|&(from, to)| {
+                ax(side) != from && ax(side) != to && (from != to || self.puzzle.d == 2)
+            })
+            .find(|&(from, to)| {
+                let mut turned = first.clone();
+                turned[to as usize] = first[from as usize];
+                turned[from as usize] = -first[to as usize];
+                first != pos && turned == pos
+            });
+        match rotation {
+            Some((from, to)) => {
+                let _ = self.perform_turn(side, from, to);
+            }
+            None => {
+                self.message = Some("no turn moves the sticker there".to_string());
+            }
+        }
+        self.flush_modes();
+    }
+
+    // move the cursor to the nearest sticker in a direction, starting at the top left sticker
+    fn move_cursor(&mut self, layout: &Layout, (dx, dy): (i16, i16)) {
+        let stickers = layout
+            .points
+            .iter()
+            .filter(|(_, pos)| self.puzzle.face_axis(pos).is_some())
+            .filter(|(_, pos)| !self.puzzle.is_void(pos));
+        let next = match self.cursor {
+            None => stickers.min_by_key(|((x, y), _)| (*y, *x)),
+            Some((cx, cy)) => stickers
+                .filter_map(|(xy, pos)| {
+                    let (x, y) = *xy;
+                    let along = (x - cx) * dx + (y - cy) * dy;
+                    let across = ((x - cx) * dy + (y - cy) * dx).abs();
+                    (along > 0).then_some(((along + 2 * across, across), xy, pos))
+                })
+                .min_by_key(|&(dist, _, _)| dist)
+                .map(|(_, xy, pos)| (xy, pos)),
+        };
+        if let Some((&xy, pos)) = next {
+            self.cursor = Some(xy);
+            self.message = Some(self.piece_info(pos));
+        }
+    }
+
+    // the position, type, and colors of the piece a sticker is on
+    fn piece_info(&self, pos: &[i16]) -> String {
+        if self.puzzle.is_void(pos) {
+            return "removed piece".to_string();
+        }
+        let piece = self.puzzle.piece(pos);
+        if self.colors_hidden() {
+            return format!(
+                "{}-sticker piece at ({})",
+                piece.orientation.len(),
+                piece.position.iter().join(" ")
+            );
+        }
+        let colors: String = piece
+            .colors()
+            .into_iter()
+            .map(|color| notation::side_name(&self.prefs, color))
+            .collect();
+        format!(
+            "{}-sticker piece at ({}) colored {colors}",
+            piece.orientation.len(),
+            piece.position.iter().join(" ")
+        )
+    }
+
+    fn get_axis_key(&self, c: char) -> Option<i16> {
+        match self.keybind_axial {
+            KeybindAxial::Axial => self.prefs.axes.iter().position(|ax| ax.axis_key == c),
+            KeybindAxial::Side => self.prefs.axes.iter().enumerate().find_map(|(s, ax)| {
+                (ax.pos.keys.side == c)
+                    .then_some(s)
+                    .or_else(|| (ax.neg.keys.side == c).then_some(!s))
+            }),
+        }
+        .map(|s| s as i16)
+    }
+
+    // the coordinates of the layer picked for a turn of side, not counting the stickers
+    fn turn_layers(&self, side: i16) -> (i16, i16) {
+        let n = self.puzzle.size(ax(side) as usize);
+        let (outer, inner) = match self.current_turn.layer {
+            Some(TurnLayer::Layers(outer, inner)) => (outer, inner),
+            Some(TurnLayer::Middle) => ((n - 1) / 2, n / 2),
+            _ => (0, 0),
+        };
+        let mut layer_min = n - 1 - 2 * inner;
+        let mut layer_max = n - 1 - 2 * outer;
+        if side < 0 {
+            layer_min *= -1;
+            layer_max *= -1;
+            std::mem::swap(&mut layer_min, &mut layer_max)
+        };
+        (layer_min, layer_max)
+    }
+
+    // the axis and the range of coordinates along it of the stickers that the turn being
+    // entered would move
+    fn pending_range(&self) -> Option<(usize, RangeInclusive<i16>)> {
+        let side = self.current_turn.side?;
+        if self.current_turn.layer == Some(TurnLayer::WholePuzzle) {
+            return None;
+        }
+        let (layer_min, layer_max) = self.turn_layers(side);
+        Some((ax(side) as usize, layer_min - 1..=layer_max + 1))
+    }
+
+    fn perform_turn(&mut self, side: i16, from: i16, to: i16) -> Result<(), TurnError> {
+        let turn = match self.current_turn.layer {
+            Some(TurnLayer::WholePuzzle) => Turn::Puzzle(PuzzleTurn { from, to }),
+            _ => {
+                let (layer_min, layer_max) = self.turn_layers(side);
+                Turn::Side(SideTurn {
+                    side,
+                    layer_min,
+                    layer_max,
+                    from,
+                    to,
+                })
+            }
+        };
+        let turn = match std::mem::replace(&mut self.turn_repeat, 1) {
+            1 => turn,
+            times => Turn::Repeat(Box::new(turn), times),
+        };
+
+        self.apply_turn(turn)
+    }
+
+    fn record_turn(&mut self, turn: Turn) {
+        if !self.merge_moves {
+            self.history.push(turn);
+        } else if let Some(removed) = self.history.push_merged(turn) {
+            // the nodes from the removed one on have new indices
+            self.snapshots.retain(|&node, _| node < removed);
+        }
+    }
+
+    // a turn that can't be made flashes the alert and says why
+    fn apply_turn(&mut self, turn: Turn) -> Result<(), TurnError> {
+        let turn_out = if self
+            .only_sides
+            .as_ref()
+            .zip(turn.side())
+            .is_some_and(|(sides, side)| !sides.contains(&side))
+        {
+            Err(TurnError::NotPracticed)
+        } else {
+            self.puzzle.turn(turn.clone())
+        };
+
+        if let Err(err) = &turn_out {
+            self.reject_turn(*err);
+        }
+        if let Ok(moved) = &turn_out {
+            self.animate(Some(moved.clone()));
+            self.record_turn(turn.clone());
+            self.take_snapshot(self.history.move_count());
+            self.changes += 1;
+            // rotating the whole puzzle doesn't end inspection or the memo
+            if !turn.is_rotation() {
+                if self.blindfold.as_ref().is_some_and(|b| b.memo.is_none()) {
+                    self.end_memo();
+                }
+                self.timer.start();
+            }
+            self.last_turn = Some(turn);
+            self.check_stage();
+        }
+        if turn_out.is_ok() && self.puzzle.is_solved() {
+            self.timer.stop();
+            self.message = Some("solved!".to_string());
+            if let Some(blindfold) = self.blindfold.as_mut().filter(|b| b.hidden()) {
+                blindfold.execution = self
+                    .timer
+                    .final_time()
+                    .zip(blindfold.memo)
+                    .map(|(time, memo)| time.saturating_sub(memo));
+            }
+            // moves in a trial aren't the solve
+            if !self.scramble.is_solved() && self.trial.is_none() {
+                if !self.solve_recorded {
+                    self.record_solve();
+                }
+                self.mode = AppMode::Summary;
+            }
+        }
+
+        turn_out.map(|_| ())
+    }
+
+    // keep a copy of the puzzle, which has count moves, if it's time for one
+    fn take_snapshot(&mut self, count: usize) {
+        let every = self.prefs.snapshot_moves;
+        if let Some(node) = self.history.current_node().filter(|_| every > 0) {
+            if count.is_multiple_of(every) && !self.snapshots.contains_key(&node) {
+                self.snapshots.insert(node, self.puzzle.clone());
+            }
+        }
+    }
+
+    // add the solve to the records file, and keep the bests and means for the summary
+    fn record_solve(&mut self) {
+        let size = self.puzzle.size_name();
+        let record = SolveRecord {
+            stm: MoveCounts::new(&self.history.moves()).stm,
+            time: self.timer.final_time(),
+            solved: chrono::Local::now().to_rfc3339(),
+        };
+        self.solve_recorded = true;
+        self.record_lines = match Records::load() {
+            Ok(mut records) => {
+                records.add(&size, record);
+                let mut lines = records.lines(&size, &self.session_started);
+                if let Err(err) = records.store() {
+                    lines.push(format!("could not save the records: {err}"));
+                }
+                lines
+            }
+            Err(err) => vec![format!("could not read the records: {err}")],
+        };
+    }
+
+    fn reject_turn(&mut self, err: TurnError) {
+        self.alert = self.prefs.alert_frames * 4 - 1;
+        self.message = Some(format!("can't turn: {err}"));
+    }
+
+    fn animate(&mut self, moved: Option<Vec<usize>>) {
+        self.animation = moved
+            .filter(|_| self.prefs.animation_frames > 0)
+            .map(|moved| (moved.into_iter().collect(), self.prefs.animation_frames));
+    }
+
+    // the line under the message, with the timer and the last move
+    fn get_status(&self) -> String {
+        let mut parts = vec![];
+        if let Some((ind, count)) = self.tab {
+            parts.push(format!("tab {}/{count}", ind + 1));
+        }
+        if self.is_dirty() {
+            parts.push("unsaved".to_string());
+        }
+        if self.trial.is_some() {
+            parts.push("trial".to_string());
+        }
+        if let Some(sides) = &self.only_sides {
+            let names: String = sides
+                .iter()
+                .map(|&side| notation::side_name(&self.prefs, side))
+                .collect();
+            parts.push(format!("only {names}"));
+        }
+        match &self.blindfold {
+            Some(blindfold) if blindfold.memo.is_none() => parts.push("memo".to_string()),
+            Some(blindfold) if blindfold.hidden() => parts.push("blindfolded".to_string()),
+            _ => (),
+        }
+        if self.replaying {
+            let ind = self.history.move_count();
+            parts.push(format!(
+                "replay: move {ind} of {}, {} left",
+                self.replay_length,
+                self.replay_length.saturating_sub(ind)
+            ));
+            // the last move made was in a group
+            if let Some(group) = self
+                .groups
+                .iter()
+                .find(|group| group.start < ind && ind <= group.start + group.len)
+            {
+                parts.push(format!(
+                    "{} ({} of {})",
+                    group.notation,
+                    ind - group.start,
+                    group.len
+                ));
+            }
+            if let Some(marker) = self.markers.iter().rev().find(|marker| marker.moves <= ind) {
+                parts.push(format!("after {}", marker.name));
+            }
+        }
+        if let Some(name) = self
+            .filters
+            .get(self.filter_ind)
+            .and_then(|f| f.name.as_ref())
+        {
+            if !self.use_live_filter {
+                parts.push(format!("filter: {name}"));
+            }
+        }
+        if !self.prefs.status_metrics.is_empty() {
+            let counts = MoveCounts::new(&self.history.moves());
+            for &metric in &self.prefs.status_metrics {
+                parts.push(counts.show(metric));
+            }
+        }
+        if let Some(time) = self.timer.display() {
+            parts.push(time);
+        }
+        if let Some(turn) = &self.last_turn {
+            parts.push(format!(
+                "last move: {}",
+                turn.to_notation(&self.prefs, self.puzzle.shape())
+            ));
+        }
+        parts.join("  ")
+    }
+
+    // the help, the settings, the summary, and the scramble sequence replace the whole puzzle
+    // view
+    fn full_screen(&self) -> bool {
+        matches!(
+            self.mode,
+            AppMode::Help | AppMode::Settings | AppMode::Summary | AppMode::Sequence
+        )
+    }
+
+    // shown when the puzzle is solved
+    fn summary_lines(&self) -> Vec<String> {
+        let moves = self.history.moves();
+        let counts = MoveCounts::new(&moves);
+        let mut lines = vec![
+            format!("solved the {}!", self.puzzle.size_name()),
+            "".to_string(),
+            format!("  {}", counts.summary()),
+        ];
+        if let Some(time) = self.timer.final_time() {
+            lines.push(format!("  time {}", timer::format_duration(time)));
+            if !time.is_zero() {
+                lines.push(format!(
+                    "  {:.2} turns per second",
+                    counts.stm as f64 / time.as_secs_f64()
+                ));
+            }
+        }
+        if let Some(blindfold) = &self.blindfold {
+            lines.push(format!("  {}", blindfold.summary()));
+        }
+        if !self.record_lines.is_empty() {
+            lines.push("".to_string());
+            lines.extend(self.record_lines.iter().map(|line| format!("  {line}")));
+        }
+        let phases = metrics::phases(&moves, &self.markers);
+        if !phases.is_empty() {
+            lines.push("".to_string());
+            for phase in phases {
+                let mut line = format!("  {}: {} STM", phase.name, phase.stm);
+                if let Some(time) = phase.time {
+                    line.push_str(&format!(", {}", timer::format_duration(time)));
+                }
+                lines.push(line);
+            }
+        }
+        lines.push("".to_string());
+        lines.push(format!(
+            "{} saves to {}, and any other key goes back",
+            help::key_name(self.prefs.global_keys.save),
+            self.filename.display()
+        ));
+        lines
+    }
+
+    fn get_message(&self) -> String {
+        if let Some(message) = &self.message {
+            return message.to_string();
+        }
+        match self.mode {
+            AppMode::Turn
+                if self.keybind_set == KeybindSet::FixedKey
+                    && self.puzzle.d > 3
+                    && self.current_turn.side.is_some() =>
+            {
+                let remaining = self.puzzle.d as usize - 3 - self.current_turn.fixed.len();
+                format!("{} ({remaining} more)", self.current_keys)
+            }
+            AppMode::Turn if self.turn_repeat > 1 => {
+                format!("{} x{}", self.current_keys, self.turn_repeat)
+            }
+            AppMode::Turn => self.current_keys.clone(),
+            AppMode::LiveFilter => match self.editing_filter {
+                Some(ind) => format!("changing filter {}: {}", ind + 1, self.live_filter_string),
+                None => format!("live filter: {}", self.live_filter_string),
+            },
+            AppMode::MoveEntry => format!("moves: {}", self.text_entry),
+            AppMode::FilterName => format!("filter name: {}", self.text_entry),
+            AppMode::SaveAs => format!("save as: {}", self.text_entry),
+            AppMode::Marker => format!("marker name: {}", self.text_entry),
+            AppMode::Command => {
+                format!("{}{}", self.prefs.global_keys.command_mode, self.text_entry)
+            }
+            AppMode::Help | AppMode::Settings | AppMode::Summary | AppMode::Sequence => {
+                "".to_string()
+            }
+        }
+    }
+}
+
+/// Flat hypercube simulator
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None, args_conflicts_with_subcommands = true)]
+struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Number of layers of the puzzle
+    n: Option<i16>,
+    /// Dimension of the puzzle
+    d: Option<u16>,
+
+    /// Number of layers along each axis, for a hypercuboid like 2,3,3,3
+    #[arg(long, value_delimiter = ',', conflicts_with_all = ["n", "d"])]
+    size: Option<Vec<i16>>,
+
+    /// Puzzle definition file, for a puzzle with pieces taken out, axes that can't turn, or
+    /// sides that share colors
+    #[arg(long, conflicts_with_all = ["n", "d", "size"])]
+    puzzle: Option<PathBuf>,
+
+    /// Display in compact mode
+    #[arg(short, long)]
+    compact: bool,
+
+    /// File that contains the filters for the solve, one per line
+    #[arg(short, long)]
+    filters: Option<PathBuf>,
+
+    /// Log file to open
+    #[arg(short, long)]
+    log: Option<PathBuf>,
+
+    /// Open the log that was last saved or autosaved, with the same preferences, filters, and
+    /// keybinds
+    #[arg(long, conflicts_with_all = ["log", "n", "d"])]
+    resume: bool,
+
+    /// Start the log at the scramble, and step through its moves with undo and redo
+    #[arg(long, requires = "log")]
+    replay: bool,
+
+    /// File of moves to make at the start, written like typed moves
+    #[arg(long, conflicts_with = "replay")]
+    apply: Option<PathBuf>,
+
+    /// Join the outermost level of the layout vertically, unless only the other orientation fits
+    /// in the terminal. This is the default if d is even.
+    #[arg(long, conflicts_with = "horizontal")]
+    vertical: bool,
+
+    /// Join the outermost level of the layout horizontally, unless only the other orientation
+    /// fits in the terminal. This is the default if d is odd.
+    #[arg(long)]
+    horizontal: bool,
+
+    /// The axes to nest in the layout, from the innermost level out, like 3,0,1,2 to put the
+    /// fourth axis innermost. Axes left out follow in order.
+    #[arg(long, value_delimiter = ',')]
+    axis_order: Option<Vec<usize>>,
+
+    /// Draw a box around each three-dimensional block of the layout, and label the outermost
+    /// blocks with their sides
+    #[arg(long)]
+    borders: bool,
+
+    /// Display using colored boxes.
+    #[arg(long)]
+    boxes: bool,
+
+    /// Display two characters per sticker, adding a pattern for each axis.
+    #[arg(long)]
+    wide: bool,
+
+    /// Draw colors as they are, for terminals with 24-bit color, instead of the closest of 256
+    /// colors. Without this or --no-true-color, it's used when COLORTERM says the terminal has it.
+    #[arg(long, conflicts_with = "no_true_color")]
+    true_color: bool,
+
+    /// Draw colors as the closest of 256 colors, even if the terminal has 24-bit color
+    #[arg(long)]
+    no_true_color: bool,
+
+    /// Label each sticker with the side it belongs on, without its color, to memorize it for
+    /// a blindfolded solve. With boxes, the labels are drawn on the colors.
+    #[arg(long)]
+    labels: bool,
+
+    /// Draw each sticker as a shape for its side, like a filled circle for R and an empty one for
+    /// L, to tell the sides apart without their colors. With boxes, the shapes are drawn on the
+    /// colors.
+    #[arg(long, conflicts_with = "labels")]
+    glyphs: bool,
+
+    /// Preferences file
+    #[arg(short, long)]
+    prefs: Option<PathBuf>,
+
+    /// Move to the next filter when all the pieces in the current one are solved
+    #[arg(long)]
+    auto_advance: bool,
+
+    /// Only let these sides turn, like RUF, to practice with them. The whole puzzle can still
+    /// rotate.
+    #[arg(long)]
+    only: Option<String>,
+
+    /// Cancel a move with the one before it when it undoes it, and combine repeated moves
+    #[arg(long)]
+    merge_moves: bool,
+
+    /// Remove the center-type pieces from the puzzle
+    #[arg(long)]
+    void: bool,
+
+    /// Make the orientation of every sticker matter, and show it with arrows
+    #[arg(long = "super", conflicts_with = "log")]
+    super_cube: bool,
+
+    /// Start with the scramble of the day for the size of the puzzle, which is the same for
+    /// everyone who uses the same scramble length
+    #[arg(long, conflicts_with_all = ["seed", "log", "resume"])]
+    daily: bool,
+
+    /// Number of random turns in a scramble, instead of the one in the preferences
+    #[arg(long)]
+    scramble_length: Option<usize>,
+
+    /// Seed for the scrambles, to repeat a scramble from a log or share it
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Draw on the normal screen instead of the alternate screen, for debugging
+    #[arg(long)]
+    no_altscreen: bool,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Write a filter file with the stages of a solving method
+    Presets {
+        /// Method to make the stages for
+        #[arg(value_enum)]
+        method: presets::Method,
+        /// Number of layers of the puzzle
+        n: i16,
+        /// Dimension of the puzzle
+        d: u16,
+        /// File to write the filters to, instead of printing them
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Make moves without opening the puzzle, and print whether it ends up solved
+    Apply {
+        /// Log to start from, at its last move
+        #[arg(short, long)]
+        log: Option<PathBuf>,
+        /// Number of layers of a solved puzzle to start from instead of a log
+        #[arg(short)]
+        n: Option<i16>,
+        /// Dimension of a solved puzzle to start from instead of a log
+        #[arg(short)]
+        d: Option<u16>,
+        /// Layers along each axis of a solved hypercuboid to start from, like 2,3,3,3
+        #[arg(long, value_delimiter = ',', conflicts_with_all = ["n", "d"])]
+        size: Option<Vec<i16>>,
+        /// Puzzle definition file of a solved puzzle to start from
+        #[arg(long, conflicts_with_all = ["log", "n", "d", "size"])]
+        puzzle: Option<PathBuf>,
+        /// Start from a solved void puzzle, without center-type pieces
+        #[arg(long, conflicts_with = "log")]
+        void: bool,
+        /// Print the state of the puzzle after the moves, in the format logs use
+        #[arg(long)]
+        dump: bool,
+        /// Moves written like typed moves, or read from stdin if there are none
+        moves: Vec<String>,
+    },
+    /// Replay a log and report its length, whether it ends solved, and any turns that can't be made
+    Verify {
+        /// Log to check
+        log: PathBuf,
+    },
+    /// Print moves that solve a 2^3 or 3^3, or only the next few of them
+    Solve {
+        /// Log to start from, at its last move
+        #[arg(short, long)]
+        log: Option<PathBuf>,
+        /// Number of layers of a solved puzzle to start from instead of a log
+        #[arg(short)]
+        n: Option<i16>,
+        /// Dimension of a solved puzzle to start from instead of a log
+        #[arg(short)]
+        d: Option<u16>,
+        /// Only print this many of the moves
+        #[arg(long)]
+        next: Option<usize>,
+        /// Moves to make before solving, written like typed moves
+        moves: Vec<String>,
+    },
+    /// Count the solves in a directory of logs, with the best and average lengths and times
+    Stats {
+        /// Directory to read the logs from
+        #[arg(default_value = "logs")]
+        dir: PathBuf,
+    },
+}
+
+fn check_size(n: i16, d: u16, prefs: &Prefs) -> Result<(), Box<dyn std::error::Error>> {
+    if d > prefs.max_dim() {
+        return Err(format!(
+            "dimension should be less than or equal to {}",
+            prefs.max_dim()
+        )
+        .into());
+    }
+    if d < 1 {
+        return Err("dimension should be greater than 0".into());
+    }
+    if n > prefs.max_layers() {
+        return Err(format!(
+            "side should be less than or equal to {}",
+            prefs.max_layers()
+        )
+        .into());
+    }
+    if n < 1 {
+        return Err("side should be greater than 0".into());
+    }
+    Ok(())
+}
 
-        let previous_message = state.get_message();
-        let mut just_resized = false;
-        if event::poll(Duration::from_millis(0))? {
-            match event::read()? {
-                Event::Key(KeyEvent {
-                    code,
-                    kind: KeyEventKind::Press,
-                    modifiers,
-                    ..
-                }) => match code {
-                    KeyCode::Char('c') if modifiers.contains(KeyModifiers::CONTROL) => {
-                        break;
-                    }
-                    KeyCode::Char(c) => {
-                        state.process_key(c, modifiers);
-                    }
-                    KeyCode::Tab => {
-                        state.process_key('\t', modifiers);
-                    }
-                    KeyCode::Esc => {
-                        state.process_key(ESCAPE_CODE, modifiers);
-                    }
-                    KeyCode::Enter => {
-                        state.process_key('\n', modifiers);
-                    }
-                    KeyCode::Backspace => {
-                        state.process_key(BACKSPACE_CODE, modifiers);
-                    }
-                    _ => (),
-                },
-                Event::Resize(_, _) => {
-                    stdout.execute(terminal::Clear(terminal::ClearType::All))?;
-                    just_resized = true;
-                }
-                _ => (),
-            }
+// every axis of a hypercuboid has to be a size a hypercube could be
+fn check_shape(shape: &[i16], prefs: &Prefs) -> Result<(), Box<dyn std::error::Error>> {
+    let d = shape.len() as u16;
+    check_size(shape.iter().copied().min().unwrap_or(1), d, prefs)?;
+    check_size(shape.iter().copied().max().unwrap_or(1), d, prefs)
+}
+
+// the same for everyone on the same day, so it can't depend on the standard library's hasher.
+// this is FNV-1a.
+fn daily_seed(date: &str, puzzle: &Puzzle) -> u64 {
+    format!("{date} {}", puzzle.size_name())
+        .bytes()
+        .fold(0xcbf29ce484222325, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+        })
+}
+
+fn read_log(path: &Path) -> Result<AppLog, Box<dyn std::error::Error>> {
+    let file = File::open(path)?;
+    let reader = BufReader::new(file);
+    if compact::is_compact(path) {
+        return compact::read(reader);
+    }
+    Ok(serde_json::from_reader(reader).map_err(std::io::Error::other)?)
+}
+
+fn write_presets(
+    method: presets::Method,
+    n: i16,
+    d: u16,
+    output: Option<PathBuf>,
+    prefs: &Prefs,
+) -> Result<(), Box<dyn std::error::Error>> {
+    check_size(n, d, prefs)?;
+    let mut out = String::new();
+    for (name, filter) in presets::generate(method, n, d, prefs)? {
+        out.push_str(&format!("{name}: {filter}\n"));
+    }
+    match output {
+        Some(path) => std::fs::write(path, out)?,
+        None => print!("{out}"),
+    }
+    Ok(())
+}
+
+// a puzzle open in the session, with its own history, filters, and file
+struct Tab {
+    state: AppState,
+    layout: Layout,
+    arrows: HashMap<(i16, i16), char>,
+    autosave: Autosave,
+    // the zoomed out layouts, and how many levels out the puzzle is drawn, or 0 for the full
+    // layout
+    zoomed: Vec<Zoomed>,
+    zoom: usize,
+    // the layout with only the sides the filter shows, when focusing
+    focused: Option<Layout>,
+    // the compact layout the reference puzzle is drawn in
+    reference: Layout,
+    // one cell for each side, drawn in the corner when the puzzle doesn't fit in the terminal
+    minimap: Zoomed,
+}
+
+fn layout_order(shape: &[i16], prefs: &Prefs, args: &Args) -> Vec<usize> {
+    let listed = args
+        .axis_order
+        .as_deref()
+        .unwrap_or_else(|| prefs.axis_order(shape.len() as u16));
+    layout::axis_order(listed, shape.len())
+}
+
+fn make_layout(shape: &[i16], horizontal: bool, prefs: &Prefs, args: &Args) -> Layout {
+    let mut layout = Layout::make_layout(
+        shape,
+        &layout_order(shape, prefs, args),
+        prefs.gaps(args.compact),
+        horizontal,
+        args.borders,
+    );
+    if args.wide {
+        layout = layout.widen();
+    }
+    layout.move_right(1)
+}
+
+type Layouts = (
+    Layout,
+    HashMap<(i16, i16), char>,
+    Vec<Zoomed>,
+    Layout,
+    Zoomed,
+);
+
+// the layouts in the orientation from the arguments, unless only the other orientation fits in a
+// terminal of the given size
+fn make_layouts(shape: &[i16], prefs: &Prefs, args: &Args, (width, height): (u16, u16)) -> Layouts {
+    let d = shape.len();
+    let n = shape.iter().copied().max().unwrap_or(1);
+    // leave room for the two status lines
+    let fits = |layout: &Layout| layout.width <= width && layout.height + 2 <= height;
+    let mut horizontal = !args.vertical && (args.horizontal || d % 2 == 1);
+    let mut layout = make_layout(shape, horizontal, prefs, args);
+    if !fits(&layout) {
+        let other = make_layout(shape, !horizontal, prefs, args);
+        if fits(&other) {
+            horizontal = !horizontal;
+            layout = other;
+        }
+    }
+    let arrows = layout.arrows(shape);
+    let zoomed = layout::zoom_sizes(n)
+        .into_iter()
+        .map(|m| {
+            let zoomed_layout = make_layout(&Layout::zoom_shape(shape, m), horizontal, prefs, args);
+            layout.zoom_out(shape, m, zoomed_layout)
+        })
+        .collect();
+    let reference = Layout::make_layout(
+        shape,
+        &layout_order(shape, prefs, args),
+        prefs.gaps(true),
+        horizontal,
+        false,
+    );
+    let minimap = Layout::make_layout(
+        &Layout::zoom_shape(shape, 1),
+        &layout_order(shape, prefs, args),
+        prefs.gaps(true),
+        horizontal,
+        false,
+    );
+    let minimap = layout.zoom_out(shape, 1, minimap);
+    (layout, arrows, zoomed, reference, minimap)
+}
+
+impl Tab {
+    fn new(state: AppState, args: &Args, size: (u16, u16)) -> Self {
+        let (layout, arrows, zoomed, reference, minimap) =
+            make_layouts(state.puzzle.shape(), &state.prefs, args, size);
+        let autosave = Autosave::new(&state.prefs);
+        Tab {
+            state,
+            layout,
+            arrows,
+            autosave,
+            zoomed,
+            zoom: 0,
+            focused: None,
+            reference,
+            minimap,
         }
+    }
 
-        let message = state.get_message();
+    // lay the puzzle out again for a new terminal size
+    fn relayout(&mut self, args: &Args, size: (u16, u16)) {
+        (
+            self.layout,
+            self.arrows,
+            self.zoomed,
+            self.reference,
+            self.minimap,
+        ) = make_layouts(self.state.puzzle.shape(), &self.state.prefs, args, size);
+        // the cursor was on a place in the old layout
+        self.state.cursor = None;
+    }
+
+    fn zoomed(&self) -> Option<&Zoomed> {
+        self.zoom.checked_sub(1).map(|level| &self.zoomed[level])
+    }
 
-        if previous_message != message || just_resized {
-            stdout
-                .queue(cursor::MoveTo(0, layout.height))?
-                .queue(terminal::Clear(terminal::ClearType::CurrentLine))?
-                .flush()?;
+    // the layout the stickers are drawn and clicked in
+    fn shown_layout(&self) -> &Layout {
+        self.focused.as_ref().unwrap_or(&self.layout)
+    }
 
-            stdout
-                .queue(cursor::MoveTo(0, layout.height))?
-                .queue(style::Print(message))?;
+    // where the minimap goes in a terminal of the given size, in the bottom right corner of the
+    // part of the puzzle that fits, or None if the whole puzzle fits
+    fn minimap_corner(&self, (columns, rows): (u16, u16)) -> Option<(u16, u16)> {
+        let layout = self.shown_layout();
+        if self.zoom > 0 || (layout.width <= columns && layout.height + 2 <= rows) {
+            return None;
         }
+        let (width, height) = (self.minimap.layout.width, self.minimap.layout.height);
+        Some((
+            layout.width.min(columns).saturating_sub(width + 1),
+            layout.height.min(rows).saturating_sub(height + 1),
+        ))
+    }
 
-        for ((x, y), pos) in &layout.points {
-            // in this loop we are more efficient by not flushing the buffer.
-            let ch;
-            let color;
-            let filter = if matches!(state.mode, AppMode::LiveFilter) {
-                &state.live_filter_pending
-            } else if state.use_live_filter {
-                &state.live_filter
-            } else if let Some(filter) = state.filters.get(state.filter_ind) {
-                filter
-            } else {
-                &Default::default()
-            };
+    // lay out only the sides the filter shows again, saying whether that moved anything
+    fn refocus(&mut self) -> bool {
+        let state = &self.state;
+        let focused = (state.focus && self.zoom == 0).then(|| {
+            let no_filter = Filter::default();
+            let filter = state.shown_filter().unwrap_or(&no_filter);
+            let context = FilterContext::new(&state.puzzle);
+            self.layout.focus(state.puzzle.shape(), |pos| {
+                !state.puzzle.is_void(pos) && filter.matches(&state.puzzle.piece(pos), &context)
+            })
+        });
+        let moved = match (&focused, &self.focused) {
+            (Some(new), Some(old)) => new.points != old.points || new.height != old.height,
+            (None, None) => false,
+            _ => true,
+        };
+        if moved {
+            self.focused = focused;
+            // the cursor was on a place in the old layout
+            self.state.cursor = None;
+        }
+        moved
+    }
+}
+
+// a side in the minimap, named in a color between the unsolved color and the color most of its
+// stickers have, by how many of them have it
+fn draw_minimap_cell(
+    stdout: &mut io::Stdout,
+    state: &AppState,
+    minimap: &Zoomed,
+    (x, y): (u16, u16),
+    pos: &[i16],
+    on_screen: &HashSet<&Vec<i16>>,
+    screen: &mut Screen,
+) -> io::Result<()> {
+    let covered: Vec<&Vec<i16>> = minimap.covers[pos]
+        .iter()
+        .filter(|pos| !state.puzzle.is_void(pos) && state.puzzle.face_axis(pos).is_some())
+        .collect();
+    let Some(first) = covered.first() else {
+        return Ok(());
+    };
+    let name = notation::side_name(&state.prefs, state.puzzle.face(first));
+    let counts = covered.iter().map(|pos| state.puzzle.sticker(pos)).counts();
+    let (&most, &count) = counts
+        .iter()
+        .max_by_key(|&(_, count)| count)
+        .expect("a side has stickers");
+    let mut content = if state.colors_hidden() {
+        '□'.with(prefs::display_color(state.prefs.global_colors.piece))
+    } else {
+        let solved = (count * 100 / covered.len()) as u8;
+        let color = prefs::blend(
+            state.prefs.global_colors.unsolved,
+            state.prefs.color(most),
+            solved,
+        );
+        name.with(prefs::display_color(color))
+    };
+    if covered.iter().any(|pos| on_screen.contains(pos)) {
+        content = content.reverse();
+    }
+    screen.draw(stdout, x, y, content)
+}
+
+// whether the minimap in this corner, or the space around it, covers a place
+fn under_minimap(corner: Option<(u16, u16)>, (x, y): (i16, i16)) -> bool {
+    corner.is_some_and(|(x0, y0)| x + 1 >= x0 as i16 && y + 1 >= y0 as i16)
+}
+
+// in 4D, the side a slot of the xyz keys stands for when holding a side: the slots go to the
+// other three axes in order, keeping the sign
+fn xyz_axis(held: i16, slot: i16) -> i16 {
+    let slot_ax = ax(slot);
+    let axis = if slot_ax >= ax(held) {
+        slot_ax + 1
+    } else {
+        slot_ax
+    };
+    if slot >= 0 {
+        axis
+    } else {
+        !axis
+    }
+}
 
-            let in_filter = filter.matches_stickers(&state.puzzle.stickers(pos));
+// the turn of the held side clockwise around the around side, as from and to axes
+fn xyz_plane(held: i16, around: i16) -> (i16, i16) {
+    let fixed = [ax(held), ax(around)];
+    let plane: Vec<i16> = (0..4).filter(|a| !fixed.contains(a)).collect();
+    let order = [fixed[0], fixed[1], plane[0], plane[1]];
+    // parity of the order of the axes, flipped for each negative side
+    let inversions = (0..4)
+        .flat_map(|i| (0..i).map(move |j| (j, i)))
+        .filter(|&(j, i)| order[j] > order[i])
+        .count();
+    let even = (inversions % 2 == 0) == ((held < 0) == (around < 0));
+    if even {
+        (plane[1], plane[0])
+    } else {
+        (plane[0], plane[1])
+    }
+}
 
-            if pos.iter().any(|x| x.abs() == state.puzzle.n) {
-                let side = state.puzzle.stickers[pos];
-                ch = if args.boxes {
+// a cell of a zoomed out layout, which shows a color if every sticker it covers has that color
+fn draw_zoomed_cell(
+    stdout: &mut io::Stdout,
+    state: &AppState,
+    zoomed: &Zoomed,
+    (x, y): (i16, i16),
+    pos: &[i16],
+    args: &Args,
+    screen: &mut Screen,
+) -> io::Result<()> {
+    let covered: Vec<&Vec<i16>> = zoomed.covers[pos]
+        .iter()
+        .filter(|pos| !state.puzzle.is_void(pos))
+        .collect();
+    if covered.is_empty() {
+        return Ok(());
+    }
+    if pos.iter().zip(&zoomed.shape).any(|(x, n)| x.abs() == *n) {
+        let colors: HashSet<i16> = covered
+            .iter()
+            .map(|pos| state.puzzle.sticker(pos))
+            .collect();
+        let content = match colors.into_iter().exactly_one() {
+            _ if state.colors_hidden() => {
+                '□'.with(prefs::display_color(state.prefs.global_colors.piece))
+            }
+            Ok(side) => {
+                let ch = if args.glyphs {
+                    state.prefs.glyph(side)
+                } else if args.boxes {
                     '■'
-                } else if side >= 0 {
-                    state.prefs.axes[side as usize].pos.name
                 } else {
-                    state.prefs.axes[(!side) as usize].neg.name
+                    notation::side_name(&state.prefs, side)
                 };
-                color = if !in_filter {
-                    state.prefs.global_colors.filtered
-                } else if side >= 0 {
+                let color = if side >= 0 {
                     state.prefs.axes[side as usize].pos.color
                 } else {
                     state.prefs.axes[(!side) as usize].neg.color
                 };
-                stdout
-                    .queue(cursor::MoveTo(*x as u16, *y as u16))?
-                    .queue(style::PrintStyledContent(ch.with(color)))?;
-            } else if !matches!(layout.keybind_hints.get(&(*x, *y)), Some(Some(_))) {
-                if state.alert % (state.prefs.alert_frames * 2) >= state.prefs.alert_frames {
-                    ch = '+';
-                    color = state.prefs.global_colors.alert;
-                } else {
-                    ch = '·';
-                    color = if in_filter {
-                        state.prefs.global_colors.piece
-                    } else {
-                        state.prefs.global_colors.filtered
-                    };
+                ch.with(prefs::display_color(color))
+            }
+            Err(_) => '#'.with(prefs::display_color(state.prefs.global_colors.unsolved)),
+        };
+        screen.draw(stdout, x as u16, y as u16, content)?;
+        if args.wide {
+            screen.draw(stdout, x as u16 + 1, y as u16, content)?;
+        }
+    } else if !matches!(zoomed.layout.keybind_hints.get(&(x, y)), Some(Some(_))) {
+        let color = prefs::display_color(state.prefs.global_colors.piece);
+        screen.draw(stdout, x as u16, y as u16, '·'.with(color))?;
+    }
+    Ok(())
+}
+
+fn draw_frame(
+    stdout: &mut io::Stdout,
+    tab: &Tab,
+    args: &Args,
+    screen: &mut Screen,
+) -> io::Result<()> {
+    let state = &tab.state;
+    let arrows = &tab.arrows;
+    let zoomed = tab.zoomed();
+    let reference_layout = &tab.reference;
+    if matches!(state.mode, AppMode::Help) {
+        let lines = help::help_lines(
+            &state.prefs,
+            state.puzzle.n,
+            state.puzzle.d,
+            state.keybind_set,
+            state.keybind_axial,
+        );
+        for (y, line) in lines.into_iter().enumerate() {
+            screen.status(stdout, y as u16, line)?;
+        }
+        return stdout.flush();
+    }
+    if matches!(state.mode, AppMode::Summary) {
+        for (y, line) in state.summary_lines().into_iter().enumerate() {
+            screen.status(stdout, y as u16, line)?;
+        }
+        return stdout.flush();
+    }
+    if matches!(state.mode, AppMode::Sequence) {
+        let (columns, rows) = terminal::size()?;
+        let lines = state.sequence_lines(columns as usize, rows as usize);
+        for (y, line) in lines.into_iter().enumerate() {
+            screen.status(stdout, y as u16, line)?;
+        }
+        return stdout.flush();
+    }
+    if matches!(state.mode, AppMode::Settings) {
+        let (_, rows) = terminal::size()?;
+        for (y, line) in state.settings_lines(rows as usize).into_iter().enumerate() {
+            screen.status(stdout, y as u16, line)?;
+        }
+        return stdout.flush();
+    }
+
+    let layout = zoomed.map_or(tab.shown_layout(), |zoomed| &zoomed.layout);
+    // the reference goes to the right of the puzzle, or below it if there's no room, and the
+    // status lines below both
+    let reference = state.reference_puzzle();
+    let (columns, rows) = terminal::size()?;
+    let corner = tab.minimap_corner((columns, rows));
+    let (dx, dy) = if layout.width + 2 + reference_layout.width <= columns {
+        (layout.width + 2, 0)
+    } else {
+        (1, layout.height + 1)
+    };
+    let status_row = match reference {
+        Some(_) => layout.height.max(dy + reference_layout.height),
+        None => layout.height,
+    };
+    screen.status(stdout, status_row, state.get_message())?;
+    screen.status(stdout, status_row + 1, state.get_status())?;
+
+    if let Some(puzzle) = &reference {
+        for ((x, y), pos) in &reference_layout.points {
+            if puzzle.is_void(pos) {
+                continue;
+            }
+            let (ch, color) = if puzzle.face_axis(pos).is_none() {
+                ('·', state.prefs.global_colors.piece)
+            } else if state.colors_hidden() {
+                ('□', state.prefs.global_colors.piece)
+            } else {
+                let side = puzzle.sticker(pos);
+                (
+                    notation::side_name(&state.prefs, side),
+                    state.prefs.color(side),
+                )
+            };
+            let (x, y) = (*x as u16 + dx, *y as u16 + dy);
+            screen.draw(stdout, x, y, ch.with(prefs::display_color(color)))?;
+        }
+    }
+
+    let filter_context = FilterContext::new(&state.puzzle);
+    let no_filter = Filter::default();
+    let pending = state.pending_range();
+    for ((x, y), pos) in &layout.points {
+        // in this loop we are more efficient by not flushing the buffer.
+        if under_minimap(corner, (*x, *y)) {
+            continue;
+        }
+        if let Some(zoomed) = zoomed {
+            draw_zoomed_cell(stdout, state, zoomed, (*x, *y), pos, args, screen)?;
+            continue;
+        }
+        if state.puzzle.is_void(pos) {
+            continue;
+        }
+
+        let ch;
+        let color;
+        let filter = state.shown_filter().unwrap_or(&no_filter);
+
+        let piece = state.puzzle.piece(pos);
+        let mut style = filter.style(&piece, &filter_context);
+        let highlight = state
+            .current_highlight()
+            .filter(|highlight| highlight.pieces.contains(&piece.position))
+            .map(|_| prefs::display_color(state.prefs.global_colors.highlight));
+        // the sticker under the cursor or clicked to turn stays visible
+        if state.hide_filtered
+            && style == FilterStyle::Level(0)
+            && state.cursor != Some((*x, *y))
+            && state.clicked.as_ref() != Some(pos)
+        {
+            style = FilterStyle::Hidden;
+        }
+        // the color with the filter applied, or None if the filter hides it
+        let styled = |color| match style {
+            FilterStyle::Hidden => None,
+            FilterStyle::Level(level) => Some(prefs::blend(
+                state.prefs.global_colors.filtered,
+                color,
+                level,
+            )),
+            FilterStyle::Color(color) => Some(color),
+        };
+
+        if state.puzzle.face_axis(pos).is_some() {
+            let side = state.puzzle.sticker(pos);
+            ch = if let Some(pointer) = state.puzzle.pointer(pos) {
+                arrows
+                    .get(&(state.puzzle.face(pos), pointer))
+                    .copied()
+                    .unwrap_or_else(|| notation::side_name(&state.prefs, pointer))
+            } else if args.glyphs {
+                state.prefs.glyph(side)
+            } else if args.boxes && !args.labels {
+                '■'
+            } else if side >= 0 {
+                state.prefs.axes[side as usize].pos.name
+            } else {
+                state.prefs.axes[(!side) as usize].neg.name
+            };
+            let side_color = if args.labels && !args.boxes {
+                state.prefs.global_colors.piece
+            } else if side >= 0 {
+                state.prefs.axes[side as usize].pos.color
+            } else {
+                state.prefs.axes[(!side) as usize].neg.color
+            };
+            // filters could tell the colors apart, so they aren't used either
+            let (ch, mut sticker_color) = match styled(side_color) {
+                _ if state.colors_hidden() => ('□', state.prefs.global_colors.piece),
+                Some(color) => (ch, color),
+                None => (' ', side_color),
+            };
+            if let Some((moved, frames)) = &state.animation {
+                if moved.contains(&state.puzzle.index(pos)) {
+                    // fade from the animation color back to the sticker color
+                    sticker_color = prefs::blend(
+                        sticker_color,
+                        state.prefs.global_colors.animation,
+                        (*frames as u16 * 100 / (state.prefs.animation_frames as u16 + 1)) as u8,
+                    );
                 }
-                stdout
-                    .queue(cursor::MoveTo(*x as u16, *y as u16))?
-                    .queue(style::PrintStyledContent(ch.with(color)))?;
             }
+            color = prefs::display_color(sticker_color);
+            let changed = state
+                .diff_keyframe()
+                .filter(|keyframe| keyframe.sticker(pos) != side)
+                .map(|_| prefs::display_color(state.prefs.global_colors.changed));
+            let background = pending
+                .as_ref()
+                .filter(|(axis, range)| range.contains(&pos[*axis]))
+                .map(|_| prefs::display_color(state.prefs.global_colors.pending))
+                .or(highlight)
+                .or(changed)
+                .unwrap_or(Color::Reset);
+            let mut content = ch.with(color).on(background);
+            // a label or glyph is drawn over its color, and the cursor turns that back around
+            if ((args.labels || args.glyphs) && args.boxes) != (state.cursor == Some((*x, *y))) {
+                content = content.reverse();
+            }
+            screen.draw(stdout, *x as u16, *y as u16, content)?;
+            if args.wide {
+                let pattern = if ch == ' ' || state.colors_hidden() {
+                    ' '
+                } else {
+                    state.prefs.pattern(ax(side) as usize)
+                };
+                screen.draw(
+                    stdout,
+                    *x as u16 + 1,
+                    *y as u16,
+                    pattern.with(color).on(background),
+                )?;
+            }
+        } else if !matches!(layout.keybind_hints.get(&(*x, *y)), Some(Some(_))) {
+            if state.alert % (state.prefs.alert_frames * 2) >= state.prefs.alert_frames {
+                ch = '+';
+                color = prefs::display_color(state.prefs.global_colors.alert);
+            } else if let Some(piece_color) = styled(state.prefs.global_colors.piece) {
+                ch = '·';
+                color = prefs::display_color(piece_color);
+            } else {
+                ch = ' ';
+                color = prefs::display_color(state.prefs.global_colors.piece);
+            }
+            let background = highlight.unwrap_or(Color::Reset);
+            screen.draw(stdout, *x as u16, *y as u16, ch.with(color).on(background))?;
+        }
+    }
+
+    let frame_color = prefs::display_color(state.prefs.global_colors.piece);
+    for ((x, y), ch) in layout
+        .borders
+        .iter()
+        .filter(|(&xy, _)| !under_minimap(corner, xy))
+    {
+        screen.draw(stdout, *x as u16, *y as u16, ch.with(frame_color))?;
+    }
+    for ((x, y), side) in layout
+        .labels
+        .iter()
+        .filter(|(&xy, _)| !under_minimap(corner, xy))
+    {
+        let name = notation::side_name(&state.prefs, *side);
+        screen.draw(stdout, *x as u16, *y as u16, name.with(frame_color))?;
+    }
+
+    for ((x, y), side) in &layout.keybind_hints {
+        // in this loop we are more efficient by not flushing the buffer.
+        if under_minimap(corner, (*x, *y)) {
+            continue;
         }
+        let ch;
+        let color;
+        if let Some(side) = side {
+            // in fixed-key mode, mark the axes that are already part of the turn
+            let fixed_hints = state.keybind_set == KeybindSet::FixedKey
+                && state.puzzle.d > 3
+                && state.current_turn.side.is_some();
+            let pressed = fixed_hints && state.current_turn.fixed.contains(side);
+            let used = fixed_hints
+                && (state.current_turn.side.map(ax) == Some(ax(*side))
+                    || state.current_turn.fixed.iter().any(|&f| ax(f) == ax(*side)));
 
-        for ((x, y), side) in &layout.keybind_hints {
-            // in this loop we are more efficient by not flushing the buffer.
-            let ch;
-            let color;
-            if let Some(side) = side {
-                ch = if state.current_turn.side.is_none()
-                    || (state.keybind_set == KeybindSet::FixedKey && state.puzzle.d == 3)
-                {
+            ch = if let (KeybindSet::XyzKey, Some(held)) =
+                (state.keybind_set, state.current_turn.side)
+            {
+                // the key that turns the held side around this one
+                if ax(*side) == ax(held) {
+                    '·'
+                } else {
+                    let slot_ax = if ax(*side) > ax(held) {
+                        ax(*side) - 1
+                    } else {
+                        ax(*side)
+                    };
+                    let keys = &state.prefs.axes[slot_ax as usize];
                     if *side >= 0 {
-                        state.prefs.axes[*side as usize].pos.keys.select
+                        keys.pos.keys.side
                     } else {
-                        state.prefs.axes[(!side) as usize].neg.keys.select
+                        keys.neg.keys.side
                     }
+                }
+            } else if used && !pressed {
+                '·'
+            } else if state.current_turn.side.is_none()
+                || (state.keybind_set == KeybindSet::FixedKey && state.puzzle.d <= 3)
+            {
+                if *side >= 0 {
+                    state.prefs.axes[*side as usize].pos.keys.select
                 } else {
-                    match state.keybind_axial {
-                        KeybindAxial::Axial => {
-                            if *side >= 0 {
-                                state.prefs.axes[*side as usize].axis_key
-                            } else {
-                                '·'
+                    state.prefs.axes[(!side) as usize].neg.keys.select
+                }
+            } else {
+                match state.keybind_axial {
+                    KeybindAxial::Axial => {
+                        if *side >= 0 {
+                            state.prefs.axes[*side as usize].axis_key
+                        } else {
+                            '·'
+                        }
+                    }
+                    KeybindAxial::Side => {
+                        if *side >= 0 {
+                            state.prefs.axes[*side as usize].pos.keys.side
+                        } else {
+                            state.prefs.axes[(!side) as usize].neg.keys.side
+                        }
+                    }
+                }
+            };
+            color = prefs::display_color(state.prefs.global_colors.piece);
+
+            let content = if pressed {
+                ch.with(color).reverse()
+            } else {
+                ch.with(color)
+            };
+            screen.draw(stdout, *x as u16, *y as u16, content)?;
+        }
+        //state.message = format!("{:?}", (x, y, side)).into();
+    }
+
+    if let Some(corner) = corner {
+        // the sides with a sticker on the screen are marked
+        let on_screen: HashSet<&Vec<i16>> = (layout.points.iter())
+            .filter(|(&(x, y), _)| {
+                (x as u16) < columns && (y as u16) < rows && !under_minimap(Some(corner), (x, y))
+            })
+            .map(|(_, pos)| pos)
+            .collect();
+        for ((x, y), pos) in &tab.minimap.layout.points {
+            let (x, y) = (corner.0 + *x as u16, corner.1 + *y as u16);
+            draw_minimap_cell(stdout, state, &tab.minimap, (x, y), pos, &on_screen, screen)?;
+        }
+    }
+
+    stdout.queue(cursor::MoveTo(0, status_row))?.flush()?;
+    Ok(())
+}
+
+fn main_inner() -> Result<(), Box<dyn std::error::Error>> {
+    let args = Args::parse();
+    let session = if args.resume {
+        Some(Session::load()?)
+    } else {
+        None
+    };
+    let prefs_path = args
+        .prefs
+        .clone()
+        .or_else(|| session.as_ref().and_then(|s| s.prefs.clone()));
+    let log_path = args
+        .log
+        .clone()
+        .or_else(|| session.as_ref().map(|s| s.log.clone()));
+    let filters_path = args
+        .filters
+        .clone()
+        .or_else(|| session.as_ref().and_then(|s| s.filters.clone()));
+
+    let prefs_file = prefs_path.clone().or_else(prefs::find_prefs);
+    let mut prefs = Prefs::load(prefs_file.as_deref())?;
+
+    if let Some(command) = &args.command {
+        return match command {
+            Command::Presets {
+                method,
+                n,
+                d,
+                output,
+            } => {
+                prefs.extend_axes(*d)?;
+                prefs.validate(*d)?;
+                write_presets(*method, *n, *d, output.clone(), &prefs)
+            }
+            Command::Apply {
+                log,
+                n,
+                d,
+                size,
+                puzzle,
+                void,
+                dump,
+                moves,
+            } => {
+                let mut puzzle = match (size, puzzle) {
+                    (Some(size), _) => headless::start_shape(size, &mut prefs)?,
+                    (_, Some(path)) => headless::start_definition(path, &mut prefs)?,
+                    _ => headless::start_puzzle(log.as_deref(), *n, *d, &mut prefs)?,
+                };
+                puzzle.void |= *void;
+                headless::apply(puzzle, moves, *dump, &prefs)
+            }
+            Command::Solve {
+                log,
+                n,
+                d,
+                next,
+                moves,
+            } => {
+                let puzzle = headless::start_puzzle(log.as_deref(), *n, *d, &mut prefs)?;
+                headless::solution(puzzle, moves, *next, &prefs)
+            }
+            Command::Verify { log } => headless::verify(log, &mut prefs),
+            Command::Stats { dir } => headless::stats(dir),
+        };
+    }
+
+    let mut state;
+    if let Some(log_file) = &log_path {
+        let app_log = read_log(log_file)?;
+        prefs.extend_axes(app_log.scramble.d)?;
+        prefs.validate(app_log.scramble.d)?;
+        state = AppState::from_app_log(app_log, prefs);
+        if args.replay {
+            state.start_replay();
+        }
+    } else if let Some(path) = &args.puzzle {
+        let definition = definition::read_file(path)?;
+        let d = definition.d();
+        prefs.extend_axes(d)?;
+        prefs.validate(d)?;
+        check_shape(&definition.size, &prefs)?;
+        let puzzle = definition
+            .make(&prefs)
+            .map_err(|err| format!("in {}: {err}", path.display()))?;
+
+        state = AppState::new(puzzle.n, d, prefs);
+        state.puzzle = puzzle;
+        state.scramble = state.puzzle.clone();
+    } else if let Some(size) = &args.size {
+        let d = size.len() as u16;
+        prefs.extend_axes(d)?;
+        prefs.validate(d)?;
+        check_shape(size, &prefs)?;
+
+        let puzzle = Puzzle::make_solved_shape(size);
+        state = AppState::new(puzzle.n, d, prefs);
+        state.puzzle = puzzle;
+        state.scramble = state.puzzle.clone();
+    } else {
+        let Some(n) = args.n else {
+            return Err("n must be specified".into());
+        };
+        let Some(d) = args.d else {
+            return Err("d must be specified".into());
+        };
+        prefs.extend_axes(d)?;
+        prefs.validate(d)?;
+        check_size(n, d, &prefs)?;
+
+        state = AppState::new(n, d, prefs);
+    }
+
+    if let Some(path) = &filters_path {
+        state.filters = filters::read_file(path, &state.prefs)
+            .map_err(|errors| format!("in {}:\n{}", path.display(), errors.join("\n")))?;
+        state.filters_path = Some(path.clone());
+    }
+    if let Some(session) = session {
+        state.filename = session.filename;
+        state.filter_ind = session.filter_ind;
+        state.keybind_set = session.keybind_set;
+        state.keybind_axial = session.keybind_axial;
+    }
+    if let Some(order) = &args.axis_order {
+        let d = state.puzzle.d as usize;
+        if let Some(axis) = order.iter().find(|&&axis| axis >= d) {
+            return Err(format!("--axis-order has axis {axis}, but the puzzle has {d}").into());
+        }
+        if let Some(axis) = order.iter().duplicates().next() {
+            return Err(format!("--axis-order has axis {axis} twice").into());
+        }
+    }
+    state.auto_advance = args.auto_advance;
+    if !state.puzzle.is_solved() {
+        state.check_stage();
+    }
+    state.merge_moves = args.merge_moves;
+    if let Some(sides) = &args.only {
+        state.only_sides = Some(state.parse_sides(sides)?);
+    }
+    // with only the built-in prefs, rebinding keys makes a prefs file in the config directory
+    if let Some(path) = prefs_file.or_else(prefs::config_path) {
+        state.prefs_path = path;
+    }
+    if let Some(length) = args.scramble_length {
+        state.scramble_length = length;
+    }
+    state.seed = args.seed;
+    if args.void {
+        state.puzzle.void = true;
+        state.scramble.void = true;
+    }
+    if args.super_cube {
+        if state.puzzle.d < 2 {
+            return Err("supercubes need at least 2 dimensions".into());
+        }
+        state.puzzle.make_super();
+        state.scramble.make_super();
+    }
+    if args.daily {
+        if state.puzzle.d < 2 {
+            return Err("a 1D puzzle has no turns to scramble with".into());
+        }
+        let date = chrono::Utc::now().format("%Y-%m-%d").to_string();
+        let seed = daily_seed(&date, &state.puzzle);
+        state.scramble_from_seed(state.scramble_length, seed);
+        state.message = Some(format!("the daily scramble for {date} (seed {seed})"));
+    }
+    if let Some(path) = &args.apply {
+        let count = state.apply_file(path)?;
+        state.message = Some(format!("applied {count} moves from {}", path.display()));
+    }
+
+    prefs::set_true_color(if args.true_color {
+        Some(true)
+    } else if args.no_true_color {
+        Some(false)
+    } else {
+        state.prefs.true_color
+    });
+
+    let _stdout_manager = StdoutManager::enter(!args.no_altscreen)?;
+    let mut stdout = io::stdout();
+
+    let mut screen = Screen::new(FRAME_LENGTH);
+    let mut tabs = vec![Tab::new(state, &args, terminal::size()?)];
+    let mut current = 0;
+
+    'main: loop {
+        let frame_begin = Instant::now();
+
+        // handle all the pending input even if the terminal is behind on drawing
+        while event::poll(Duration::from_millis(0))? {
+            match event::read()? {
+                Event::Key(KeyEvent {
+                    code,
+                    kind: KeyEventKind::Press,
+                    modifiers,
+                    ..
+                }) => {
+                    let c = match code {
+                        KeyCode::Char('c') if modifiers.contains(KeyModifiers::CONTROL) => {
+                            let others_dirty = tabs
+                                .iter()
+                                .enumerate()
+                                .any(|(i, tab)| i != current && tab.state.is_dirty());
+                            let quit = tabs[current].state.request_quit(others_dirty);
+                            if quit {
+                                break 'main;
                             }
+                            continue;
                         }
-                        KeybindAxial::Side => {
-                            if *side >= 0 {
-                                state.prefs.axes[*side as usize].pos.keys.side
-                            } else {
-                                state.prefs.axes[(!side) as usize].neg.keys.side
+                        KeyCode::Left | KeyCode::Right | KeyCode::Up | KeyCode::Down => {
+                            let direction = match code {
+                                KeyCode::Left => (-1, 0),
+                                KeyCode::Right => (1, 0),
+                                KeyCode::Up => (0, -1),
+                                _ => (0, 1),
+                            };
+                            let tab = &mut tabs[current];
+                            if matches!(tab.state.mode, AppMode::Settings) {
+                                tab.state.move_setting(direction.1);
+                            } else if matches!(tab.state.mode, AppMode::Turn) && tab.zoom == 0 {
+                                let layout = tab.focused.as_ref().unwrap_or(&tab.layout);
+                                tab.state.move_cursor(layout, direction);
+                            }
+                            continue;
+                        }
+                        // enter clicks the sticker under the cursor
+                        KeyCode::Enter
+                            if matches!(tabs[current].state.mode, AppMode::Turn)
+                                && tabs[current].state.cursor.is_some()
+                                && tabs[current].zoom == 0 =>
+                        {
+                            let tab = &mut tabs[current];
+                            let layout = tab.shown_layout();
+                            let pos = tab.state.cursor.and_then(|xy| layout.points.get(&xy));
+                            tab.state.process_click(pos.cloned());
+                            continue;
+                        }
+                        KeyCode::Char(c) => c,
+                        KeyCode::Tab => '\t',
+                        KeyCode::Esc => ESCAPE_CODE,
+                        KeyCode::Enter => '\n',
+                        KeyCode::Backspace => BACKSPACE_CODE,
+                        _ => continue,
+                    };
+
+                    let count = tabs.len();
+                    let tab = &mut tabs[current];
+                    let state = &mut tab.state;
+                    let keys = &state.prefs.global_keys;
+                    let switch_tabs = matches!(state.mode, AppMode::Turn);
+                    if switch_tabs && (c == keys.zoom_in || c == keys.zoom_out) {
+                        let zoom = if c == keys.zoom_in {
+                            tab.zoom.saturating_sub(1)
+                        } else {
+                            (tab.zoom + 1).min(tab.zoomed.len())
+                        };
+                        if zoom != tab.zoom {
+                            tab.zoom = zoom;
+                            let message = match tab.zoomed() {
+                                Some(zoomed) => format!("zoomed out to size {}", zoomed.size),
+                                None => "zoomed in to every sticker".to_string(),
+                            };
+                            tab.state.message = Some(message);
+                            stdout.execute(terminal::Clear(terminal::ClearType::All))?;
+                            screen.invalidate();
+                        }
+                        continue;
+                    } else if switch_tabs && c == keys.next_tab {
+                        current = (current + 1) % count;
+                    } else if switch_tabs && c == keys.new_tab {
+                        let new_state = state.new_tab(count + 1);
+                        tabs.push(Tab::new(new_state, &args, terminal::size()?));
+                        current = tabs.len() - 1;
+                    } else {
+                        let shown = (state.full_screen(), state.reference);
+                        state.process_key(c, modifiers);
+                        if shown != (state.full_screen(), state.reference) {
+                            stdout.execute(terminal::Clear(terminal::ClearType::All))?;
+                            screen.invalidate();
+                        }
+                        if let Some(force) = state.quit_requested.take() {
+                            let dirty = tabs.iter().any(|tab| tab.state.is_dirty());
+                            if force || !dirty {
+                                break 'main;
                             }
+                            let command = tabs[current].state.prefs.global_keys.command_mode;
+                            tabs[current].state.message = Some(format!(
+                                "unsaved changes: {command}quit! quits anyway, or {command}save saves"
+                            ));
                         }
+                        continue;
                     }
-                };
-                color = state.prefs.global_colors.piece;
 
-                stdout
-                    .queue(cursor::MoveTo(*x as u16, *y as u16))?
-                    .queue(style::PrintStyledContent(ch.with(color)))?;
+                    // the new tab can have a different layout
+                    let count = tabs.len();
+                    tabs[current].state.message = Some(format!("tab {} of {count}", current + 1));
+                    for (i, tab) in tabs.iter_mut().enumerate() {
+                        tab.state.tab = (count > 1).then_some((i, count));
+                    }
+                    stdout.execute(terminal::Clear(terminal::ClearType::All))?;
+                    screen.invalidate();
+                }
+                Event::Mouse(MouseEvent {
+                    kind: MouseEventKind::Down(button),
+                    column,
+                    row,
+                    ..
+                }) => {
+                    let tab = &mut tabs[current];
+                    if button != MouseButton::Left || tab.zoom > 0 {
+                        tab.state.flush_modes();
+                        continue;
+                    }
+                    let (x, y) = (column as i16, row as i16);
+                    if under_minimap(tab.minimap_corner(terminal::size()?), (x, y)) {
+                        continue;
+                    }
+                    let layout = tab.shown_layout();
+                    let mut pos = layout.points.get(&(x, y));
+                    // the pattern next to a sticker in wide mode
+                    if args.wide && pos.is_none() {
+                        pos = layout.points.get(&(x - 1, y));
+                    }
+                    tab.state.process_click(pos.cloned());
+                }
+                Event::Resize(width, height) => {
+                    for tab in &mut tabs {
+                        tab.relayout(&args, (width, height));
+                    }
+                    stdout.execute(terminal::Clear(terminal::ClearType::All))?;
+                    screen.invalidate();
+                }
+                _ => (),
+            }
+        }
+
+        for tab in &mut tabs {
+            if tab.autosave.due(tab.state.changes) {
+                let path = tab.autosave.next_path();
+                match tab.state.write_log(&path) {
+                    Ok(()) => tab.state.last_written = Some(path),
+                    Err(err) => tab.state.message = Some(format!("could not autosave: {err}")),
+                }
+                tab.autosave.record(tab.state.changes);
             }
-            //state.message = format!("{:?}", (x, y, side)).into();
         }
 
-        stdout.queue(cursor::MoveTo(0, layout.height))?.flush()?;
+        let tab = &mut tabs[current];
+        if screen.should_draw() {
+            let render_begin = Instant::now();
+            if tab.refocus() {
+                stdout.execute(terminal::Clear(terminal::ClearType::All))?;
+                screen.invalidate();
+            }
+            draw_frame(&mut stdout, tab, &args, &mut screen)?;
+            screen.record(render_begin.elapsed());
+        }
 
+        let state = &mut tab.state;
+        state.rewind_step();
         if state.alert > 0 {
             state.alert -= 1;
         }
+        if let Some((_, frames)) = &mut state.animation {
+            *frames -= 1;
+            if *frames == 0 {
+                state.animation = None;
+            }
+        }
 
         let frame_end = Instant::now();
         let frame = frame_end - frame_begin;
@@ -914,8 +4166,20 @@ fn main_inner() -> Result<(), Box<dyn std::error::Error>> {
         //state.puzzle.turn(0, 2, 2, 1); // R
     }
 
-    stdout.execute(cursor::Show)?;
-    terminal::disable_raw_mode()?; // does this help?
+    let state = tabs.swap_remove(current).state;
+    if let Some(log) = state.last_written {
+        Session {
+            log,
+            filename: state.filename,
+            prefs: prefs_path,
+            filters: filters_path,
+            filter_ind: state.filter_ind,
+            keybind_set: state.keybind_set,
+            keybind_axial: state.keybind_axial,
+        }
+        .store()?;
+    }
+
     Ok(())
 }
 
@@ -0,0 +1,99 @@
+use crate::puzzle::Turn;
+use crate::Marker;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+// one way of counting moves, for the status line
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Metric {
+    Moves,
+    Rotations,
+    Stm,
+    Qstm,
+    Atm,
+}
+
+// the length of a sequence of moves in the usual ways of counting them. whole-puzzle rotations
+// are only counted in moves.
+#[derive(Default, Clone, Copy)]
+pub struct MoveCounts {
+    // every move as it was made
+    pub moves: usize,
+    pub rotations: usize,
+    // slice turn metric: a turn of any layers by any amount counts once
+    pub stm: usize,
+    // quarter slice turn metric: each quarter turn counts once
+    pub qstm: usize,
+    // axial turn metric: turns in a row along the same axis in the same plane count once
+    pub atm: usize,
+}
+
+impl MoveCounts {
+    pub fn new(turns: &[Turn]) -> Self {
+        let mut counts = MoveCounts::default();
+        let mut last: Option<&Turn> = None;
+        for turn in turns {
+            counts.moves += 1;
+            if turn.is_rotation() {
+                counts.rotations += 1;
+                last = None;
+                continue;
+            }
+            let quarters = turn.quarter_turns() as usize;
+            if quarters == 0 {
+                continue;
+            }
+            counts.stm += 1;
+            counts.qstm += quarters;
+            if !last.is_some_and(|last| last.same_axis(turn)) {
+                counts.atm += 1;
+            }
+            last = Some(turn);
+        }
+        counts
+    }
+
+    // like "12 STM"
+    pub fn show(&self, metric: Metric) -> String {
+        match metric {
+            Metric::Moves => format!("{} moves", self.moves),
+            Metric::Rotations => format!("{} rotations", self.rotations),
+            Metric::Stm => format!("{} STM", self.stm),
+            Metric::Qstm => format!("{} QSTM", self.qstm),
+            Metric::Atm => format!("{} ATM", self.atm),
+        }
+    }
+
+    pub fn summary(&self) -> String {
+        format!(
+            "{} moves with {} rotations, {} STM, {} QSTM, {} ATM",
+            self.moves, self.rotations, self.stm, self.qstm, self.atm
+        )
+    }
+}
+
+// the moves from one marker to the next, or from the scramble to the first
+pub struct Phase {
+    pub name: String,
+    pub stm: usize,
+    pub time: Option<Duration>,
+}
+
+pub fn phases(moves: &[Turn], markers: &[Marker]) -> Vec<Phase> {
+    let mut phases = vec![];
+    let (mut start, mut start_time) = (0, Some(Duration::ZERO));
+    for marker in markers {
+        let end = marker.moves.clamp(start, moves.len());
+        phases.push(Phase {
+            name: marker.name.clone(),
+            stm: MoveCounts::new(&moves[start..end]).stm,
+            time: marker
+                .time
+                .zip(start_time)
+                .map(|(time, start_time)| time.saturating_sub(start_time)),
+        });
+        (start, start_time) = (end, marker.time);
+    }
+    phases
+}
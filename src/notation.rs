@@ -0,0 +1,409 @@
+use crate::puzzle::{ax, PuzzleTurn, SideTurn, Turn};
+use crate::Prefs;
+
+// turns are written as [layers]<side><from><to>[times], like 2RUF for the second layer of R
+// turning U to F, 1-2RUF for the outer two layers, or RUF2 for turning R twice. whole-puzzle
+// rotations use * as the side.
+pub const WHOLE_PUZZLE: char = '*';
+
+pub fn side_name(prefs: &Prefs, side: i16) -> char {
+    if side >= 0 {
+        prefs.axes[side as usize].pos.name
+    } else {
+        prefs.axes[(!side) as usize].neg.name
+    }
+}
+
+impl SideTurn {
+    // the layers turned, counted from the outside of the side starting at 1
+    pub fn layers(&self, n: i16) -> (i16, i16) {
+        let (min, max) = if self.side >= 0 {
+            ((n - 1 - self.layer_max) / 2, (n - 1 - self.layer_min) / 2)
+        } else {
+            ((self.layer_min + n - 1) / 2, (self.layer_max + n - 1) / 2)
+        };
+        (min + 1, max + 1)
+    }
+
+    fn to_notation_with(&self, n: i16, name: &dyn Fn(i16) -> char) -> String {
+        let layers = match self.layers(n) {
+            (1, 1) => "".to_string(),
+            (min, max) if min == max => format!("{min}"),
+            (min, max) => format!("{min}-{max}"),
+        };
+        format!(
+            "{layers}{}{}{}",
+            name(self.side),
+            name(self.from),
+            name(self.to)
+        )
+    }
+}
+
+// the number of layers of the side's axis in a puzzle of this shape. sides the puzzle doesn't
+// have are left for turning to reject, as if they had the most layers.
+fn side_size(shape: &[i16], side: i16) -> i16 {
+    shape
+        .get(ax(side) as usize)
+        .or(shape.iter().max())
+        .copied()
+        .unwrap_or(1)
+}
+
+pub fn parse_side(prefs: &Prefs, ch: char) -> Option<i16> {
+    prefs.axes.iter().enumerate().find_map(|(s, ax)| {
+        if ax.pos.name == ch {
+            Some(s as i16)
+        } else if ax.neg.name == ch {
+            Some(!(s as i16))
+        } else {
+            None
+        }
+    })
+}
+
+impl Turn {
+    // written for a puzzle with shape as its number of layers along each axis
+    pub fn to_notation(&self, prefs: &Prefs, shape: &[i16]) -> String {
+        self.to_notation_with(shape, &|side| side_name(prefs, side))
+    }
+
+    pub fn parse(st: &str, prefs: &Prefs, shape: &[i16]) -> Result<Self, String> {
+        Turn::parse_with(st, shape, &|ch| parse_side(prefs, ch))
+    }
+
+    // the same as to_notation, with the sides named by name instead of by the prefs
+    pub fn to_notation_with(&self, shape: &[i16], name: &dyn Fn(i16) -> char) -> String {
+        match self {
+            Turn::Side(t) => t.to_notation_with(side_size(shape, t.side), name),
+            Turn::Puzzle(t) => format!("{WHOLE_PUZZLE}{}{}", name(t.from), name(t.to)),
+            Turn::Repeat(t, times) => format!("{}{times}", t.to_notation_with(shape, name)),
+        }
+    }
+
+    pub fn parse_with(
+        st: &str,
+        shape: &[i16],
+        side: &dyn Fn(char) -> Option<i16>,
+    ) -> Result<Self, String> {
+        let body = st.trim_end_matches(|c: char| c.is_ascii_digit());
+        if body.len() < st.len() && !body.is_empty() {
+            let times_st = &st[body.len()..];
+            let times = times_st
+                .parse::<u8>()
+                .ok()
+                .filter(|&times| times >= 2)
+                .ok_or_else(|| format!("invalid repeat count {times_st} in {st}"))?;
+            return Ok(Turn::Repeat(
+                Box::new(Turn::parse_with(body, shape, side)?),
+                times,
+            ));
+        }
+
+        let digits_end = st
+            .find(|c: char| !c.is_ascii_digit() && c != '-')
+            .ok_or_else(|| format!("missing side in {st}"))?;
+        let (layers_st, rest) = st.split_at(digits_end);
+        let mut chars = rest.chars();
+        let (Some(side_ch), Some(from_ch), Some(to_ch), None) =
+            (chars.next(), chars.next(), chars.next(), chars.next())
+        else {
+            return Err(format!("{st} should be a side and two axes"));
+        };
+
+        let axis = |ch: char| side(ch).ok_or_else(|| format!("invalid side {ch}"));
+        let from = axis(from_ch)?;
+        let to = axis(to_ch)?;
+
+        if side_ch == WHOLE_PUZZLE {
+            if !layers_st.is_empty() {
+                return Err(format!("whole-puzzle rotation {st} can't have layers"));
+            }
+            return Ok(Turn::Puzzle(PuzzleTurn { from, to }));
+        }
+        let side = axis(side_ch)?;
+        let n = side_size(shape, side);
+
+        let layer = |l_st: &str| -> Result<i16, String> {
+            let l = l_st
+                .parse::<i16>()
+                .map_err(|_| format!("invalid layer {l_st}"))?;
+            if l < 1 || l > n {
+                return Err(format!("layer {l} should be between 1 and {n}"));
+            }
+            Ok(l)
+        };
+        let (min, max) = match layers_st.split_once('-') {
+            _ if layers_st.is_empty() => (1, 1),
+            None => (layer(layers_st)?, layer(layers_st)?),
+            Some(("", _)) => return Err(format!("missing first layer before - in {st}")),
+            Some((_, "")) => return Err(format!("missing last layer after - in {st}")),
+            Some((min_st, max_st)) => (layer(min_st)?, layer(max_st)?),
+        };
+        if min > max {
+            return Err(format!("layers {min}-{max} are backwards"));
+        }
+
+        // coordinate of a layer counted from the outside of the positive side
+        let coord = |l: i16| n + 1 - 2 * l;
+        let (layer_min, layer_max) = if side >= 0 {
+            (coord(max), coord(min))
+        } else {
+            (-coord(min), -coord(max))
+        };
+        Ok(Turn::Side(SideTurn {
+            side,
+            layer_min,
+            layer_max,
+            from,
+            to,
+        }))
+    }
+}
+
+// a sequence of turns separated by whitespace, which can be grouped into commutators like
+// [A, B], meaning A B A' B', and conjugates like A: B or [A: B], meaning A B A'
+pub enum Alg {
+    Turn(Turn),
+    Sequence(Vec<Alg>),
+    Commutator(Box<Alg>, Box<Alg>),
+    Conjugate(Box<Alg>, Box<Alg>),
+}
+
+fn inverse(turns: &[Turn]) -> Vec<Turn> {
+    turns.iter().rev().map(Turn::inverse).collect()
+}
+
+impl Alg {
+    pub fn parse(st: &str, prefs: &Prefs, shape: &[i16]) -> Result<Self, String> {
+        let mut tokens = vec![];
+        let mut word = String::new();
+        for c in st.chars() {
+            if c.is_whitespace() || "[],:".contains(c) {
+                if !word.is_empty() {
+                    tokens.push(std::mem::take(&mut word));
+                }
+                if !c.is_whitespace() {
+                    tokens.push(c.to_string());
+                }
+            } else {
+                word.push(c);
+            }
+        }
+        if !word.is_empty() {
+            tokens.push(word);
+        }
+
+        let mut parser = AlgParser {
+            tokens,
+            pos: 0,
+            prefs,
+            shape,
+        };
+        let alg = parser.conjugate()?;
+        match parser.peek() {
+            None => Ok(alg),
+            Some(token) => Err(format!("unexpected {token} in {st}")),
+        }
+    }
+
+    // the turns it stands for, in order
+    pub fn expand(&self) -> Vec<Turn> {
+        match self {
+            Alg::Turn(turn) => vec![turn.clone()],
+            Alg::Sequence(algs) => algs.iter().flat_map(Alg::expand).collect(),
+            Alg::Commutator(a, b) => {
+                let (a, b) = (a.expand(), b.expand());
+                [a.clone(), b.clone(), inverse(&a), inverse(&b)].concat()
+            }
+            Alg::Conjugate(a, b) => {
+                let a = a.expand();
+                [a.clone(), b.expand(), inverse(&a)].concat()
+            }
+        }
+    }
+
+    // whether it has any commutators or conjugates
+    pub fn is_grouped(&self) -> bool {
+        match self {
+            Alg::Turn(_) => false,
+            Alg::Sequence(algs) => algs.iter().any(Alg::is_grouped),
+            Alg::Commutator(..) | Alg::Conjugate(..) => true,
+        }
+    }
+}
+
+//   conjugate = sequence (':' conjugate)?
+//   sequence  = (turn | '[' conjugate (',' conjugate)? ']')*
+struct AlgParser<'a> {
+    tokens: Vec<String>,
+    pos: usize,
+    prefs: &'a Prefs,
+    shape: &'a [i16],
+}
+
+impl AlgParser<'_> {
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.pos).map(|token| token.as_str())
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        let found = self.peek() == Some(token);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn conjugate(&mut self) -> Result<Alg, String> {
+        let setup = self.sequence()?;
+        if !self.eat(":") {
+            return Ok(setup);
+        }
+        let setup = operand(setup, "before :")?;
+        let body = operand(self.conjugate()?, "after :")?;
+        Ok(Alg::Conjugate(Box::new(setup), Box::new(body)))
+    }
+
+    fn sequence(&mut self) -> Result<Alg, String> {
+        let mut algs = vec![];
+        while let Some(token) = self.peek() {
+            if token == "[" {
+                self.pos += 1;
+                let a = self.conjugate()?;
+                let alg = if self.eat(",") {
+                    let a = operand(a, "before ,")?;
+                    let b = operand(self.conjugate()?, "after ,")?;
+                    Alg::Commutator(Box::new(a), Box::new(b))
+                } else {
+                    a
+                };
+                if !self.eat("]") {
+                    return Err(match self.peek() {
+                        Some(token) => format!("expected ] instead of {token}"),
+                        None => "missing ]".to_string(),
+                    });
+                }
+                algs.push(alg);
+            } else if "],:".contains(token) {
+                break;
+            } else {
+                algs.push(Alg::Turn(Turn::parse(token, self.prefs, self.shape)?));
+                self.pos += 1;
+            }
+        }
+        Ok(Alg::Sequence(algs))
+    }
+}
+
+// the alg on one side of a : or ,, which has to have moves in it
+fn operand(alg: Alg, place: &str) -> Result<Alg, String> {
+    match alg {
+        Alg::Sequence(algs) if algs.is_empty() => Err(format!("missing moves {place}")),
+        alg => Ok(alg),
+    }
+}
+
+pub fn to_sequence(turns: &[Turn], prefs: &Prefs, shape: &[i16]) -> String {
+    turns
+        .iter()
+        .map(|turn| turn.to_notation(prefs, shape))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::puzzle::Puzzle;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn prefs() -> Prefs {
+        Prefs::load(None).expect("default prefs load")
+    }
+
+    fn turn(st: &str, shape: &[i16]) -> Turn {
+        Turn::parse(st, &prefs(), shape).unwrap()
+    }
+
+    fn alg_error(st: &str) -> String {
+        match Alg::parse(st, &prefs(), &[3, 3, 3]) {
+            Ok(alg) => panic!("{st} parsed to {} turns", alg.expand().len()),
+            Err(err) => err,
+        }
+    }
+
+    #[test]
+    fn turns_round_trip() {
+        let prefs = prefs();
+        for shape in [vec![3, 3, 3], vec![4, 4, 4, 4], vec![2, 5, 3], vec![6, 6]] {
+            let puzzle = Puzzle::make_solved_shape(&shape);
+            let mut turns = puzzle.scramble_turns(200, &mut StdRng::seed_from_u64(1));
+            let single = match &turns[0] {
+                Turn::Repeat(t, _) => (**t).clone(),
+                t => t.clone(),
+            };
+            turns.push(Turn::Repeat(Box::new(single), 3));
+            turns.push(Turn::Puzzle(PuzzleTurn { from: 0, to: !1 }));
+            for t in turns {
+                let st = t.to_notation(&prefs, &shape);
+                assert_eq!(Turn::parse(&st, &prefs, &shape), Ok(t), "{st}");
+            }
+        }
+    }
+
+    #[test]
+    fn notation_round_trips() {
+        let shape = [4, 4, 4];
+        for st in [
+            "RUF", "2RUF", "1-2RUF", "2-4LUB", "4DFR", "RUF2", "3-4BRU3", "*UF",
+        ] {
+            assert_eq!(turn(st, &shape).to_notation(&prefs(), &shape), st);
+        }
+        // layers that are written out anyway are left out again
+        assert_eq!(turn("1RUF", &shape).to_notation(&prefs(), &shape), "RUF");
+        assert_eq!(turn("3-3RUF", &shape).to_notation(&prefs(), &shape), "3RUF");
+    }
+
+    #[test]
+    fn layer_errors() {
+        let shape = [3, 3, 3];
+        let error = |st| Turn::parse(st, &prefs(), &shape).unwrap_err();
+        assert_eq!(error("2-RUF"), "missing last layer after - in 2-RUF");
+        assert_eq!(error("1-RUF"), "missing last layer after - in 1-RUF");
+        assert_eq!(error("-2RUF"), "missing first layer before - in -2RUF");
+        assert_eq!(error("4RUF"), "layer 4 should be between 1 and 3");
+        assert_eq!(error("3-2RUF"), "layers 3-2 are backwards");
+        assert_eq!(
+            error("2*UF"),
+            "whole-puzzle rotation 2*UF can't have layers"
+        );
+    }
+
+    #[test]
+    fn groups_expand() {
+        let shape = [3, 3, 3];
+        let expand = |st| Alg::parse(st, &prefs(), &shape).unwrap().expand();
+        let (a, b) = (turn("RUF", &shape), turn("UFR", &shape));
+        assert_eq!(
+            expand("[RUF, UFR]"),
+            [a.clone(), b.clone(), a.inverse(), b.inverse()]
+        );
+        assert_eq!(expand("RUF: UFR"), [a.clone(), b.clone(), a.inverse()]);
+        assert_eq!(expand("[RUF: UFR] RUF"), [a.clone(), b, a.inverse(), a]);
+        assert_eq!(expand(""), []);
+    }
+
+    #[test]
+    fn empty_operands_are_rejected() {
+        assert_eq!(alg_error(":"), "missing moves before :");
+        assert_eq!(alg_error(": RUF"), "missing moves before :");
+        assert_eq!(alg_error("RUF:"), "missing moves after :");
+        assert_eq!(alg_error("[RUF: ]"), "missing moves after :");
+        assert_eq!(alg_error("[,]"), "missing moves before ,");
+        assert_eq!(alg_error("[RUF,]"), "missing moves after ,");
+        assert_eq!(alg_error("[, RUF]"), "missing moves before ,");
+        assert_eq!(alg_error("[RUF, UFR"), "missing ]");
+    }
+}
@@ -1,33 +1,162 @@
 #![allow(dead_code)]
-use crate::BufReader;
 use crossterm::style::Color;
 use serde::de::Error;
 use serde::Deserializer;
-use std::fs::File;
+use std::collections::BTreeMap;
 use std::num::ParseIntError;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
 
 use rgb2ansi256::rgb_to_ansi256;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize, Serializer};
+use serde_json::Value;
+
+use crate::metrics::Metric;
+use crate::{KeybindAxial, KeybindSet};
 
 pub const ESCAPE_CODE: char = '⎋';
 pub const BACKSPACE_CODE: char = '⌫';
-pub const DEFAULT_FILE_PATH_STR: &'static str = "default_prefs.json";
+pub const DEFAULT_FILE_PATH_STR: &str = "default_prefs.json";
+const DEFAULT_PREFS_STR: &str = include_str!("../default_prefs.json");
+pub const DEFAULT_PATTERNS: &[char] = &['/', '\\', '-', '|', '+', 'x', 'o', '=', '~', '^'];
+// the shape of each axis for --glyphs, filled on the positive side and hollow on the negative
+pub const DEFAULT_GLYPHS: &[(char, char)] = &[
+    ('●', '○'),
+    ('▲', '△'),
+    ('◆', '◇'),
+    ('▼', '▽'),
+    ('♥', '♡'),
+    ('♠', '♤'),
+    ('♣', '♧'),
+    ('▶', '▷'),
+    ('◀', '◁'),
+    ('★', '☆'),
+];
+pub const NO_KEY: char = '∅';
+// whether colors are drawn as they are instead of squashed to 256 colors, set once at the start
+static TRUE_COLOR: AtomicBool = AtomicBool::new(false);
+// names for generated axes, skipping letters that look like the greek ones already in use
+const SPARE_NAMES: &str = "CEGHMNQTVWXYЖЗИЙЦЧШЩЫЭЮЯ";
+// characters that mean something in filters, so they can't be generated keys
+const RESERVED_KEYS: &str = "0123456789&+!%#*:()|";
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Prefs {
     pub axes: Vec<Axis>,
     pub global_keys: GlobalKeys,
     pub global_colors: GlobalColors,
     pub damage_repeat: u8,
     pub alert_frames: u8,
+    #[serde(default)]
+    pub inspection_seconds: u64,
+    // how many frames the stickers that just changed stay highlighted
+    pub animation_frames: u8,
+    pub scramble_length: usize,
+    // write a backup after this many moves or seconds, with 0 to turn either off
+    pub autosave_moves: usize,
+    pub autosave_seconds: u64,
+    // number of backup files to rotate through, with 0 to turn autosave off
+    pub autosave_count: usize,
+    // keep a copy of the puzzle every this many moves, so seeking far back doesn't make every
+    // move again, with 0 to turn it off
+    pub snapshot_moves: usize,
+    #[serde(default)]
+    pub algs: Vec<AlgKey>,
+    // the name saved in logs as who solved them
+    #[serde(default)]
+    pub solver: Option<String>,
+    // the move counts shown in the status line
+    #[serde(default)]
+    pub status_metrics: Vec<Metric>,
+    // draw colors as they are instead of the closest of 256 colors, or null to do it when the
+    // terminal says it can
+    #[serde(default)]
+    pub true_color: Option<bool>,
+    // how many moves of a solution the hint key shows
+    pub hint_moves: usize,
+    // show the moves that make each scramble after scrambling
+    pub show_scramble: bool,
+    // how many moves a second the rewind command undoes
+    pub rewind_speed: u32,
+    // the space between the blocks of each level of the layout, by dimension, and with --compact
+    pub gaps: Vec<u16>,
+    pub gaps_compact: Vec<u16>,
+    // the keybinds a puzzle starts with, by dimension like "3", or "5+" for 5 and up
+    #[serde(default)]
+    pub keybinds: BTreeMap<String, StartKeybinds>,
+    // the axes the layout nests, from the innermost level out, by dimension like the keybinds.
+    // axes left out follow in order.
+    #[serde(default)]
+    pub axis_orders: BTreeMap<String, Vec<usize>>,
+    // named sets of colors to switch between, each changing only the colors it lists
+    #[serde(default)]
+    pub themes: Vec<Theme>,
+    // the colors are put back to these when the prefs are written
+    #[serde(skip)]
+    themed: Option<Themed>,
+}
+
+// the theme in use, with the colors of the sides and the global colors from before it
+#[derive(Debug, Clone)]
+struct Themed {
+    name: String,
+    sides: Vec<(Color, Color)>,
+    global_colors: GlobalColors,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Theme {
+    pub name: String,
+    // colors by side name, as six hex digits
+    #[serde(default)]
+    pub sides: BTreeMap<char, String>,
+    // like global_colors, with only the ones the theme changes
+    #[serde(default)]
+    pub global_colors: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StartKeybinds {
+    pub set: KeybindSet,
+    pub axial: KeybindAxial,
+}
+
+// a key that makes a sequence of moves, written like typed moves
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AlgKey {
+    pub key: char,
+    pub name: String,
+    pub moves: String,
 }
 
 impl Prefs {
-    pub fn load_default() -> Result<Self, Box<dyn std::error::Error>> {
-        let file = File::open(Path::new(DEFAULT_FILE_PATH_STR))?;
-        let reader = BufReader::new(file);
-        Ok(serde_json::from_reader(reader)?)
+    // with no file, the defaults built into the program are used. files ending in .toml are read
+    // as toml and anything else as json, with the same fields in both.
+    pub fn load(path: Option<&Path>) -> Result<Self, Box<dyn std::error::Error>> {
+        let Some(path) = path else {
+            return Self::from_value(Value::Object(Default::default()));
+        };
+        let st = std::fs::read_to_string(path)
+            .map_err(|err| format!("could not open prefs {}: {err}", path.display()))?;
+        let over = if is_toml(path) {
+            toml::from_str(&st).map_err(|err| format!("{}: {err}", path.display()))?
+        } else {
+            serde_json::from_str(&st).map_err(|err| format!("{}: {err}", path.display()))?
+        };
+        Self::from_value(over)
+    }
+
+    // anything missing from the file is taken from the default prefs, so that prefs files from
+    // before a setting was added still load
+    fn from_value(over: Value) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut prefs: Value = serde_json::from_str(DEFAULT_PREFS_STR)?;
+        merge(&mut prefs, over);
+        let mut problems = vec![];
+        color_problems(&prefs, "", &mut problems);
+        if !problems.is_empty() {
+            return Err(format!("invalid prefs:\n{}", problems.join("\n")).into());
+        }
+        Ok(serde_json::from_value(prefs)?)
     }
 
     pub fn pos_keys(&self) -> impl Iterator<Item = char> + '_ {
@@ -38,43 +167,429 @@ impl Prefs {
         self.axes.len() as u16
     }
 
+    // the keybinds to start a puzzle of dimension d with
+    pub fn start_keybinds(&self, d: u16) -> Option<&StartKeybinds> {
+        by_dim(&self.keybinds, d)
+    }
+
+    // the axes to lay a puzzle of dimension d out with, from the innermost level out
+    pub fn axis_order(&self, d: u16) -> &[usize] {
+        by_dim(&self.axis_orders, d).map_or(&[], |order| order)
+    }
+
     pub fn max_layers(&self) -> i16 {
         (self.global_keys.layers.len() * 2 + 1) as i16
     }
+
+    // every key bound to something, with repeats if a key is bound more than once
+    pub fn used_keys(&self) -> Vec<char> {
+        let g = &self.global_keys;
+        let mut keys = g.layers.clone();
+        keys.extend([
+            g.rotate,
+            g.scramble,
+            g.reset,
+            g.keybind_mode,
+            g.axis_mode,
+            g.undo,
+            g.redo,
+            g.next_filter,
+            g.prev_filter,
+            g.live_filter_mode,
+            g.reset_mode,
+            g.save,
+            g.next_branch,
+            g.prev_branch,
+            g.move_entry_mode,
+            g.save_as_mode,
+            g.next_tab,
+            g.new_tab,
+            g.zoom_in,
+            g.zoom_out,
+            g.help,
+            g.settings_mode,
+            g.filter_name_mode,
+            g.save_live_filter,
+            g.reload_filters,
+            g.hide_filtered,
+            g.focus,
+            g.repeat_turn,
+            g.slice,
+            g.command_mode,
+            g.marker_mode,
+            g.trial_mode,
+            g.hint,
+            g.theme,
+        ]);
+        keys.extend(self.algs.iter().map(|alg| alg.key));
+        for axis in &self.axes {
+            keys.extend([
+                axis.axis_key,
+                axis.pos.keys.select,
+                axis.pos.keys.side,
+                axis.neg.keys.select,
+                axis.neg.keys.side,
+            ]);
+        }
+        keys.retain(|&k| k != NO_KEY);
+        keys
+    }
+
+    // add generated axes until there are d of them, taking names and keys that aren't in use
+    pub fn extend_axes(&mut self, d: u16) -> Result<(), String> {
+        let count = (d as usize).saturating_sub(self.axes.len());
+        if count == 0 {
+            return Ok(());
+        }
+
+        let used_keys = self.used_keys();
+        let used_names: Vec<char> = self
+            .axes
+            .iter()
+            .flat_map(|ax| [ax.pos.name, ax.neg.name])
+            .collect();
+        let mut names = SPARE_NAMES
+            .chars()
+            .filter(|c| !used_names.contains(c) && !used_keys.contains(c));
+        let mut keys =
+            ('!'..='~').filter(|c| !used_keys.contains(c) && !RESERVED_KEYS.contains(*c));
+
+        for i in 0..count {
+            let mut next = || -> Option<(char, char)> { Some((names.next()?, keys.next()?)) };
+            let (Some((pos_name, pos_key)), Some((neg_name, neg_key)), Some(axis_key)) =
+                (next(), next(), keys.next())
+            else {
+                return Err(format!(
+                    "not enough spare names and keys for dimension {d}, the most is {}",
+                    self.axes.len()
+                ));
+            };
+            let hue = i as f32 / count as f32;
+            self.axes.push(Axis {
+                pos: Side {
+                    name: pos_name,
+                    color: hsv(hue, 0.6, 0.95),
+                    keys: Keys {
+                        select: pos_key,
+                        side: NO_KEY,
+                    },
+                    glyph: None,
+                },
+                neg: Side {
+                    name: neg_name,
+                    color: hsv(hue + 0.5 / count as f32, 0.8, 0.7),
+                    keys: Keys {
+                        select: neg_key,
+                        side: NO_KEY,
+                    },
+                    glyph: None,
+                },
+                axis_key,
+                pattern: None,
+            });
+        }
+        Ok(())
+    }
+
+    // every key that can be rebound, as a name and a json pointer to it in the prefs. only the
+    // first d axes are included.
+    pub fn bindings(&self, d: u16) -> Vec<(String, String)> {
+        let mut bindings = vec![];
+        let global_keys = serde_json::to_value(&self.global_keys).expect("prefs serialize");
+        for (name, value) in global_keys.as_object().expect("keys are an object") {
+            match value {
+                Value::Array(layers) => bindings.extend((0..layers.len()).map(|i| {
+                    (
+                        format!("layer {}", i + 1),
+                        format!("/global_keys/{name}/{i}"),
+                    )
+                })),
+                _ => bindings.push((name.replace('_', " "), format!("/global_keys/{name}"))),
+            }
+        }
+        for (i, alg) in self.algs.iter().enumerate() {
+            bindings.push((alg.name.clone(), format!("/algs/{i}/key")));
+        }
+        for (i, axis) in self.axes.iter().enumerate().take(d as usize) {
+            for (side, dir) in [(&axis.pos, "pos"), (&axis.neg, "neg")] {
+                for key in ["select", "side"] {
+                    bindings.push((
+                        format!("{} {key}", side.name),
+                        format!("/axes/{i}/{dir}/keys/{key}"),
+                    ));
+                }
+            }
+            bindings.push((
+                format!("{}{} axis", axis.pos.name, axis.neg.name),
+                format!("/axes/{i}/axis_key"),
+            ));
+        }
+        bindings
+    }
+
+    // keys bound more than once where they could be confused, and sides with the same name, in a
+    // puzzle of dimension d. global keys and alg keys are checked before anything else, so they
+    // have to be different from every other key, but the keys for turning only have to be
+    // different from the others used at the same step of a turn.
+    pub fn problems(&self, d: u16) -> Vec<String> {
+        let step = |field: &str| {
+            if field.starts_with("global_keys") || field.starts_with("algs") {
+                0
+            } else if field.ends_with("select") {
+                1
+            } else if field.ends_with("side") {
+                2
+            } else {
+                3
+            }
+        };
+        let keys: Vec<(String, char)> = self
+            .bindings(d)
+            .into_iter()
+            .filter_map(|(_, pointer)| {
+                let key = self.key_at(&pointer)?;
+                Some((pointer.trim_start_matches('/').replace('/', "."), key))
+            })
+            .filter(|&(_, key)| key != NO_KEY)
+            .collect();
+        let names: Vec<(String, char)> = self
+            .axes
+            .iter()
+            .enumerate()
+            .take(d as usize)
+            .flat_map(|(i, axis)| {
+                [
+                    (format!("axes.{i}.pos.name"), axis.pos.name),
+                    (format!("axes.{i}.neg.name"), axis.neg.name),
+                ]
+            })
+            .collect();
+
+        let mut problems = vec![];
+        let dims = (self.keybinds.keys().map(|dims| ("keybinds", dims)))
+            .chain(self.axis_orders.keys().map(|dims| ("axis_orders", dims)));
+        for (field, dims) in dims {
+            if dims.trim_end_matches('+').parse::<u16>().is_err() || dims.matches('+').count() > 1 {
+                problems.push(format!("{field}.{dims} should be a dimension like 3 or 5+"));
+            }
+        }
+        for (i, (field_a, key_a)) in keys.iter().enumerate() {
+            for (field_b, key_b) in &keys[i + 1..] {
+                let (step_a, step_b) = (step(field_a), step(field_b));
+                if key_a == key_b && (step_a == step_b || step_a == 0 || step_b == 0) {
+                    problems.push(format!("{field_a} and {field_b} are both {key_a}"));
+                }
+            }
+        }
+        let global_colors = serde_json::to_value(&self.global_colors).expect("prefs serialize");
+        for (i, theme) in self.themes.iter().enumerate() {
+            for name in theme.sides.keys() {
+                if !self
+                    .axes
+                    .iter()
+                    .any(|axis| [axis.pos.name, axis.neg.name].contains(name))
+                {
+                    problems.push(format!("themes.{i}.sides.{name} isn't a side"));
+                }
+            }
+            for field in theme.global_colors.keys() {
+                if global_colors.get(field).is_none() {
+                    problems.push(format!(
+                        "themes.{i}.global_colors.{field} isn't a global color"
+                    ));
+                }
+            }
+        }
+        for (i, (field_a, name_a)) in names.iter().enumerate() {
+            for (field_b, name_b) in &names[i + 1..] {
+                if name_a == name_b {
+                    problems.push(format!("{field_a} and {field_b} are both {name_a}"));
+                }
+            }
+        }
+        problems
+    }
+
+    pub fn gaps(&self, compact: bool) -> &[u16] {
+        if compact {
+            &self.gaps_compact
+        } else {
+            &self.gaps
+        }
+    }
+
+    // the theme with this name, over the colors from before any theme, or those colors again
+    // with None
+    pub fn set_theme(&mut self, name: Option<&str>) -> Result<(), String> {
+        if let Some(themed) = self.themed.take() {
+            for (axis, (pos, neg)) in self.axes.iter_mut().zip(themed.sides) {
+                axis.pos.color = pos;
+                axis.neg.color = neg;
+            }
+            self.global_colors = themed.global_colors;
+        }
+        let Some(name) = name else {
+            return Ok(());
+        };
+        let theme = self
+            .themes
+            .iter()
+            .find(|theme| theme.name == name)
+            .cloned()
+            .ok_or_else(|| format!("no theme named {name}"))?;
+        let sides = self
+            .axes
+            .iter()
+            .map(|axis| (axis.pos.color, axis.neg.color))
+            .collect();
+        let unthemed = self.global_colors.clone();
+
+        let mut global_colors =
+            serde_json::to_value(&self.global_colors).map_err(|err| err.to_string())?;
+        for (field, color) in &theme.global_colors {
+            global_colors[field] = Value::String(color.clone());
+        }
+        self.global_colors =
+            serde_json::from_value(global_colors).map_err(|err| err.to_string())?;
+        for axis in &mut self.axes {
+            for side in [&mut axis.pos, &mut axis.neg] {
+                if let Some(color) = theme.sides.get(&side.name) {
+                    side.color = hex(color).map_err(|err| format!("{name}: {err}"))?;
+                }
+            }
+        }
+        self.themed = Some(Themed {
+            name: name.to_string(),
+            sides,
+            global_colors: unthemed,
+        });
+        Ok(())
+    }
+
+    pub fn theme(&self) -> Option<&str> {
+        self.themed.as_ref().map(|themed| themed.name.as_str())
+    }
+
+    pub fn validate(&self, d: u16) -> Result<(), String> {
+        let problems = self.problems(d);
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(format!("invalid prefs:\n{}", problems.join("\n")))
+        }
+    }
+
+    pub fn key_at(&self, pointer: &str) -> Option<char> {
+        let prefs = serde_json::to_value(self).ok()?;
+        prefs.pointer(pointer)?.as_str()?.chars().next()
+    }
+
+    pub fn rebind(&mut self, pointer: &str, key: char) -> Result<(), String> {
+        let mut unthemed = self.clone();
+        unthemed.set_theme(None)?;
+        let mut prefs = serde_json::to_value(&unthemed).map_err(|err| err.to_string())?;
+        let value = prefs
+            .pointer_mut(pointer)
+            .ok_or_else(|| format!("no key at {pointer}"))?;
+        *value = Value::String(key.to_string());
+        let mut rebound: Prefs = serde_json::from_value(prefs).map_err(|err| err.to_string())?;
+        rebound.set_theme(self.theme())?;
+        *self = rebound;
+        Ok(())
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)?;
+        }
+        let mut unthemed = self.clone();
+        unthemed.set_theme(None)?;
+        let st = if is_toml(path) {
+            toml::to_string_pretty(&unthemed)?
+        } else {
+            serde_json::to_string_pretty(&unthemed)?
+        };
+        std::fs::write(path, st)?;
+        Ok(())
+    }
+
+    // the glyph drawn next to the stickers of an axis in wide mode
+    pub fn pattern(&self, axis: usize) -> char {
+        self.axes[axis]
+            .pattern
+            .unwrap_or(DEFAULT_PATTERNS[axis % DEFAULT_PATTERNS.len()])
+    }
+
+    // the shape drawn for the stickers of a side with --glyphs
+    pub fn glyph(&self, side: i16) -> char {
+        let axis = side.max(!side) as usize;
+        let (pos, neg) = DEFAULT_GLYPHS[axis % DEFAULT_GLYPHS.len()];
+        if side >= 0 {
+            self.axes[axis].pos.glyph.unwrap_or(pos)
+        } else {
+            self.axes[axis].neg.glyph.unwrap_or(neg)
+        }
+    }
+
+    pub fn color(&self, side: i16) -> Color {
+        if side >= 0 {
+            self.axes[side as usize].pos.color
+        } else {
+            self.axes[(!side) as usize].neg.color
+        }
+    }
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Axis {
     pub pos: Side,
     pub neg: Side,
     pub axis_key: char,
+    #[serde(default)]
+    pub pattern: Option<char>,
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Side {
     pub name: char,
-    #[serde(deserialize_with = "de_color")]
+    #[serde(serialize_with = "se_color", deserialize_with = "de_color")]
     pub color: Color,
     pub keys: Keys,
+    // drawn instead of the name with --glyphs, to tell the sides apart without their colors
+    #[serde(default)]
+    pub glyph: Option<char>,
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Keys {
     pub select: char,
     pub side: char,
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GlobalColors {
-    #[serde(deserialize_with = "de_color")]
+    #[serde(serialize_with = "se_color", deserialize_with = "de_color")]
     pub piece: Color,
-    #[serde(deserialize_with = "de_color")]
+    #[serde(serialize_with = "se_color", deserialize_with = "de_color")]
     pub filtered: Color,
-    #[serde(deserialize_with = "de_color")]
+    #[serde(serialize_with = "se_color", deserialize_with = "de_color")]
     pub alert: Color,
+    #[serde(serialize_with = "se_color", deserialize_with = "de_color")]
+    pub animation: Color,
+    #[serde(serialize_with = "se_color", deserialize_with = "de_color")]
+    pub unsolved: Color,
+    // background of the stickers the turn being entered would move
+    #[serde(serialize_with = "se_color", deserialize_with = "de_color")]
+    pub pending: Color,
+    // background of the pieces picked out by a command, like a cycle
+    #[serde(serialize_with = "se_color", deserialize_with = "de_color")]
+    pub highlight: Color,
+    // background of the stickers that changed since the keyframe
+    #[serde(serialize_with = "se_color", deserialize_with = "de_color")]
+    pub changed: Color,
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GlobalKeys {
     pub layers: Vec<char>,
     pub rotate: char,
@@ -89,15 +604,205 @@ pub struct GlobalKeys {
     pub live_filter_mode: char,
     pub reset_mode: char,
     pub save: char,
+    pub next_branch: char,
+    pub prev_branch: char,
+    pub move_entry_mode: char,
+    pub save_as_mode: char,
+    pub next_tab: char,
+    pub new_tab: char,
+    pub zoom_in: char,
+    pub zoom_out: char,
+    pub help: char,
+    pub settings_mode: char,
+    pub filter_name_mode: char,
+    pub save_live_filter: char,
+    pub reload_filters: char,
+    pub hide_filtered: char,
+    pub focus: char,
+    pub repeat_turn: char,
+    pub slice: char,
+    pub command_mode: char,
+    pub marker_mode: char,
+    pub trial_mode: char,
+    pub hint: char,
+    pub theme: char,
+}
+
+// colors that aren't six hex digits, found before deserializing so the field can be named
+fn color_problems(value: &Value, field: &str, problems: &mut Vec<String>) {
+    let Value::Object(map) = value else {
+        if let Value::Array(items) = value {
+            for (i, item) in items.iter().enumerate() {
+                color_problems(item, &format!("{field}.{i}"), problems);
+            }
+        }
+        return;
+    };
+    for (key, item) in map {
+        let item_field = if field.is_empty() {
+            key.clone()
+        } else {
+            format!("{field}.{key}")
+        };
+        let is_color = key == "color"
+            || field == "global_colors"
+            || field.ends_with(".global_colors")
+            || field.ends_with(".sides");
+        match item {
+            Value::String(st) if is_color => {
+                if st.len() != 6 || hex(st).is_err() {
+                    problems.push(format!("{item_field} should be six hex digits, not {st:?}"));
+                }
+            }
+            _ if is_color => {
+                problems.push(format!("{item_field} should be a string of hex digits"))
+            }
+            _ => color_problems(item, &item_field, problems),
+        }
+    }
+}
+
+// the prefs file in the config directory, like ~/.config/flat-hypercube/prefs.json
+pub fn config_path() -> Option<PathBuf> {
+    let config = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+    Some(config.join("flat-hypercube").join("prefs.json"))
+}
+
+fn is_toml(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "toml")
+}
+
+// the prefs file to use when none is given: the one in the config directory, either json or
+// toml, or else default_prefs.json in the working directory, if they exist
+pub fn find_prefs() -> Option<PathBuf> {
+    config_path()
+        .into_iter()
+        .flat_map(|path| [path.with_extension("toml"), path])
+        .chain([PathBuf::from(DEFAULT_FILE_PATH_STR)])
+        .find(|path| path.exists())
+}
+
+// the entry for dimension d in a map by dimension like "3", or "5+" for 5 and up, preferring an
+// exact match over the closest "k+" below it
+fn by_dim<T>(map: &BTreeMap<String, T>, d: u16) -> Option<&T> {
+    map.get(&d.to_string()).or_else(|| {
+        map.iter()
+            .filter_map(|(dims, value)| {
+                let min = dims.strip_suffix('+')?.parse::<u16>().ok()?;
+                Some((min, value))
+            })
+            .filter(|&(min, _)| min <= d)
+            .max_by_key(|&(min, _)| min)
+            .map(|(_, value)| value)
+    })
 }
 
-fn hex(st: &str) -> Result<Color, ParseIntError> {
-    let hex = u32::from_str_radix(&st, 16)?;
-    Ok(Color::AnsiValue(rgb_to_ansi256(
-        ((hex >> 16) & 0xff) as u8,
-        ((hex >> 8) & 0xff) as u8,
-        ((hex >> 0) & 0xff) as u8,
-    )))
+// objects are merged key by key, and anything else in over replaces base
+fn merge(base: &mut Value, over: Value) {
+    match (base, over) {
+        (Value::Object(base), Value::Object(over)) => {
+            for (key, value) in over {
+                match base.get_mut(&key) {
+                    Some(base_value) => merge(base_value, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, over) => *base = over,
+    }
+}
+
+pub fn hex(st: &str) -> Result<Color, ParseIntError> {
+    let hex = u32::from_str_radix(st, 16)?;
+    Ok(Color::Rgb {
+        r: ((hex >> 16) & 0xff) as u8,
+        g: ((hex >> 8) & 0xff) as u8,
+        b: (hex & 0xff) as u8,
+    })
+}
+
+// hue goes around once from 0 to 1
+fn hsv(hue: f32, saturation: f32, value: f32) -> Color {
+    let h = hue.rem_euclid(1.0) * 6.0;
+    let c = value * saturation;
+    let x = c * (1.0 - (h % 2.0 - 1.0).abs());
+    let (r, g, b) = match h as u8 {
+        0 => (c, x, 0.0),
+        1 => (x, c, 0.0),
+        2 => (0.0, c, x),
+        3 => (0.0, x, c),
+        4 => (x, 0.0, c),
+        _ => (c, 0.0, x),
+    };
+    let m = value - c;
+    let byte = |v: f32| ((v + m) * 255.0).round() as u8;
+    Color::Rgb {
+        r: byte(r),
+        g: byte(g),
+        b: byte(b),
+    }
+}
+
+// colors are kept as rgb so they can be blended, and only squashed to 256 colors when drawn,
+// unless the terminal takes them as they are
+pub fn display_color(color: Color) -> Color {
+    match color {
+        Color::Rgb { .. } if TRUE_COLOR.load(Ordering::Relaxed) => color,
+        Color::Rgb { r, g, b } => Color::AnsiValue(rgb_to_ansi256(r, g, b)),
+        _ => color,
+    }
+}
+
+// with None, use true color if COLORTERM says the terminal has it
+pub fn set_true_color(true_color: Option<bool>) {
+    let detected = || {
+        std::env::var("COLORTERM")
+            .is_ok_and(|colorterm| colorterm == "truecolor" || colorterm == "24bit")
+    };
+    TRUE_COLOR.store(true_color.unwrap_or_else(detected), Ordering::Relaxed);
+}
+
+// level 0 gives `from`, level 100 gives `to`
+pub fn blend(from: Color, to: Color, level: u8) -> Color {
+    match (from, to) {
+        (
+            Color::Rgb {
+                r: r0,
+                g: g0,
+                b: b0,
+            },
+            Color::Rgb {
+                r: r1,
+                g: g1,
+                b: b1,
+            },
+        ) => {
+            let mix = |x: u8, y: u8| {
+                ((x as u16 * (100 - level as u16) + y as u16 * level as u16) / 100) as u8
+            };
+            Color::Rgb {
+                r: mix(r0, r1),
+                g: mix(g0, g1),
+                b: mix(b0, b1),
+            }
+        }
+        _ if level == 0 => from,
+        _ => to,
+    }
+}
+
+fn se_color<S>(color: &Color, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    match color {
+        Color::Rgb { r, g, b } => serializer.serialize_str(&format!("{r:02x}{g:02x}{b:02x}")),
+        _ => Err(serde::ser::Error::custom("colors should be rgb")),
+    }
 }
 
 fn de_color<'de, D>(deserializer: D) -> Result<Color, D::Error>
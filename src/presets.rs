@@ -0,0 +1,94 @@
+use crate::notation::side_name;
+use crate::Prefs;
+use crate::Puzzle;
+use clap::ValueEnum;
+use std::collections::BTreeSet;
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum Method {
+    /// Solve one cell at a time, ending with the cell opposite the first
+    CellByCell,
+    /// Solve pieces in order of how many stickers they have
+    Reduction,
+    /// Solve the first cell, then everything but the last cell, then the last cell (4D only)
+    Rkt,
+}
+
+// numbers of stickers that pieces of the puzzle have, from fewest to most
+fn piece_types(n: i16, d: u16) -> Vec<usize> {
+    let puzzle = Puzzle::make_solved(n, d);
+    let types: BTreeSet<usize> = puzzle
+        .pieces()
+        .iter()
+        .map(|piece| piece.orientation.len())
+        .collect();
+    types.into_iter().collect()
+}
+
+fn type_char(t: usize) -> Result<char, String> {
+    crate::filters::DIGITS.chars().nth(t).ok_or_else(|| {
+        let max = crate::filters::DIGITS.len() - 1;
+        format!("filters can only select piece types up to {max} stickers")
+    })
+}
+
+// a list of (stage name, filter) pairs
+pub fn generate(
+    method: Method,
+    n: i16,
+    d: u16,
+    prefs: &Prefs,
+) -> Result<Vec<(String, String)>, String> {
+    let last = (d - 1) as i16;
+    match method {
+        Method::CellByCell => {
+            let mut sides = vec![!last];
+            for axis in 0..last {
+                sides.push(axis);
+                sides.push(!axis);
+            }
+            sides.push(last);
+
+            let mut stages = vec![];
+            let mut filter = String::new();
+            for side in sides {
+                if !filter.is_empty() {
+                    filter.push('+');
+                }
+                filter.push(side_name(prefs, side));
+                stages.push((format!("{} cell", side_name(prefs, side)), filter.clone()));
+            }
+            Ok(stages)
+        }
+        Method::Reduction => {
+            let mut stages = vec![];
+            let mut filter = String::new();
+            for t in piece_types(n, d) {
+                if !filter.is_empty() {
+                    filter.push('+');
+                }
+                filter.push(type_char(t)?);
+                stages.push((format!("{t}-color pieces"), filter.clone()));
+            }
+            Ok(stages)
+        }
+        Method::Rkt => {
+            if d != 4 {
+                return Err("rkt is only for 4D puzzles".to_string());
+            }
+            let first = side_name(prefs, !last);
+            let final_cell = side_name(prefs, last);
+
+            let mut stages = vec![
+                ("first cell".to_string(), first.to_string()),
+                ("first two layers".to_string(), format!("!{final_cell}")),
+            ];
+            let mut filter = format!("!{final_cell}");
+            for t in piece_types(n, d) {
+                filter.push_str(&format!("+{final_cell}{}", type_char(t)?));
+                stages.push((format!("last cell {t}-color pieces"), filter.clone()));
+            }
+            Ok(stages)
+        }
+    }
+}
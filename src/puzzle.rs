@@ -1,10 +1,10 @@
 use itertools::Itertools;
 use rand::prelude::*;
-use rand::rngs::ThreadRng;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
 
-#[derive(Serialize, Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
 pub struct SideTurn {
     pub side: i16,
     pub layer_min: i16,
@@ -25,7 +25,7 @@ impl SideTurn {
     }
 }
 
-#[derive(Serialize, Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
 pub struct PuzzleTurn {
     pub from: i16,
     pub to: i16,
@@ -40,10 +40,48 @@ impl PuzzleTurn {
     }
 }
 
-#[derive(Serialize, Deserialize, Clone)]
+// why a turn can't be made
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TurnError {
+    // the side or an axis of the turn isn't in the puzzle
+    AxisOutOfRange,
+    // the side is on an axis of the rotation, or the rotation's axes are the same or opposite
+    SameAxis,
+    // the layers aren't in the puzzle, or are backwards
+    LayerOutOfRange,
+    // a quarter turn between axes with different numbers of layers
+    NotSquare,
+    // the side is on an axis that the puzzle's definition doesn't let turn
+    FixedAxis,
+    // the side isn't one of the sides being practiced with. puzzles never give this, only the
+    // app does.
+    NotPracticed,
+}
+
+impl fmt::Display for TurnError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::AxisOutOfRange => write!(f, "the turn uses an axis the puzzle doesn't have"),
+            Self::SameAxis => write!(f, "the side and the two axes of a turn need different axes"),
+            Self::LayerOutOfRange => write!(f, "the turn uses layers the puzzle doesn't have"),
+            Self::NotSquare => write!(
+                f,
+                "the axes of the turn have different numbers of layers, so it has to be made twice"
+            ),
+            Self::FixedAxis => write!(f, "the puzzle's definition doesn't let that side turn"),
+            Self::NotPracticed => write!(f, "that side isn't one of the sides being practiced"),
+        }
+    }
+}
+
+impl std::error::Error for TurnError {}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
 pub enum Turn {
     Side(SideTurn),
     Puzzle(PuzzleTurn),
+    // a turn made several times in a row, kept as one move
+    Repeat(Box<Turn>, u8),
 }
 
 impl Turn {
@@ -51,6 +89,132 @@ impl Turn {
         match self {
             Self::Side(t) => Self::Side(t.inverse()),
             Self::Puzzle(t) => Self::Puzzle(t.inverse()),
+            Self::Repeat(t, times) => Self::Repeat(Box::new(t.inverse()), *times),
+        }
+    }
+
+    // whether the turn only rotates the whole puzzle
+    pub fn is_rotation(&self) -> bool {
+        match self {
+            Self::Side(_) => false,
+            Self::Puzzle(_) => true,
+            Self::Repeat(t, _) => t.is_rotation(),
+        }
+    }
+
+    // the quarter turns it makes going the shorter way around, with a flip in 2D counting as one
+    pub fn quarter_turns(&self) -> u8 {
+        let (key, quarters) = self.canonical();
+        quarters.min(key.order() - quarters)
+    }
+
+    // whether both are turns of sides along the same axis in the same plane, which could be made
+    // at the same time
+    pub fn same_axis(&self, other: &Turn) -> bool {
+        let ((a, _), (b, _)) = (self.canonical(), other.canonical());
+        match (a.layers, b.layers) {
+            (Some((axis_a, ..)), Some((axis_b, ..))) => axis_a == axis_b && a.plane == b.plane,
+            _ => false,
+        }
+    }
+
+    // the axes of the rotation, for a turn that isn't repeated
+    fn plane(&self) -> Option<(i16, i16)> {
+        match self {
+            Self::Side(t) => Some((t.from, t.to)),
+            Self::Puzzle(t) => Some((t.from, t.to)),
+            Self::Repeat(..) => None,
+        }
+    }
+
+    // the turn that is repeated
+    fn base(&self) -> &Turn {
+        match self {
+            Self::Repeat(t, _) => t.base(),
+            t => t,
+        }
+    }
+
+    // the side that turns, or None for a whole-puzzle rotation
+    pub fn side(&self) -> Option<i16> {
+        match self.base() {
+            Self::Side(t) => Some(t.side),
+            _ => None,
+        }
+    }
+
+    // what the turn moves, and how many quarter turns it makes from the lower axis of the plane
+    // to the higher one. turns written differently that do the same thing give the same values.
+    fn canonical(&self) -> (TurnKey, u8) {
+        match self {
+            Self::Side(t) => {
+                TurnKey::new(Some((ax(t.side), t.layer_min, t.layer_max)), t.from, t.to)
+            }
+            Self::Puzzle(t) => TurnKey::new(None, t.from, t.to),
+            Self::Repeat(t, times) => {
+                let (key, quarters) = t.canonical();
+                let order = key.order();
+                (key, quarters * (times % order) % order)
+            }
+        }
+    }
+
+    // the turn doing both this and next, if they turn the same layers in the same plane, which is
+    // Some(None) if they cancel out
+    pub fn merge(&self, next: &Turn) -> Option<Option<Turn>> {
+        let (key, first) = self.canonical();
+        let (next_key, second) = next.canonical();
+        if key != next_key {
+            return None;
+        }
+        let order = key.order();
+        let total = (first + second) % order;
+        if total == 0 {
+            return Some(None);
+        }
+        // a quarter turn is 1 or 3 of the lower-to-higher ones, and either one undoes itself
+        let base = self.base();
+        let (_, base_quarters) = base.canonical();
+        let times = total * base_quarters % order;
+        Some(Some(if times == 1 {
+            base.clone()
+        } else {
+            Turn::Repeat(Box::new(base.clone()), times)
+        }))
+    }
+}
+
+// the layers and plane of a turn, without its direction
+#[derive(PartialEq)]
+struct TurnKey {
+    // the axis and coordinates turned, or None for the whole puzzle
+    layers: Option<(i16, i16, i16)>,
+    plane: (i16, i16),
+}
+
+impl TurnKey {
+    fn new(layers: Option<(i16, i16, i16)>, from: i16, to: i16) -> (Self, u8) {
+        let (a, b) = (ax(from), ax(to));
+        // turning from a negative side to a positive one goes the other way
+        let mut forward = (from >= 0) == (to >= 0);
+        if a > b {
+            forward = !forward;
+        }
+        let key = TurnKey {
+            layers,
+            plane: (a.min(b), a.max(b)),
+        };
+        let quarters = if forward { 1 } else { key.order() - 1 };
+        (key, quarters)
+    }
+
+    // how many times the turn can be made before it gets back to the start
+    fn order(&self) -> u8 {
+        // flips in 2D
+        if self.plane.0 == self.plane.1 {
+            2
+        } else {
+            4
         }
     }
 }
@@ -83,79 +247,441 @@ mod serde_map {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct Puzzle {
-    pub n: i16,
-    pub d: u16,
+// a piece and the colors of its stickers
+#[derive(Clone, Debug)]
+pub struct Piece {
+    // coordinates in -n+1..=n-1
+    pub position: Vec<i16>,
+    // (side the sticker faces, color of the sticker) for each sticker of the piece
+    pub orientation: Vec<(i16, i16)>,
+}
+
+impl Piece {
+    pub fn colors(&self) -> Vec<i16> {
+        self.orientation.iter().map(|&(_, color)| color).collect()
+    }
+
+    // whether every sticker is on the side its color is being solved to
+    pub fn is_solved(&self, face_colors: &HashMap<i16, i16>) -> bool {
+        self.orientation
+            .iter()
+            .all(|(side, color)| face_colors.get(side) == Some(color))
+    }
+}
+
+// the format puzzles are saved in
+#[derive(Serialize, Deserialize)]
+struct PuzzleRepr {
+    n: i16,
+    d: u16,
     // map from coordinate vector (only contains -n+1, n-1 every other, and ±n)
     // to side (sides related by ! are opposite)
     #[serde(with = "serde_map")]
-    pub stickers: HashMap<Vec<i16>, i16>,
+    stickers: HashMap<Vec<i16>, i16>,
+    #[serde(default)]
+    void: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pointers: Option<Vec<(Vec<i16>, i16)>>,
+    // the number of layers along each axis, if they aren't all n
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    shape: Option<Vec<i16>>,
+    #[serde(default, skip_serializing_if = "Custom::is_empty")]
+    custom: Custom,
+}
+
+// the parts of a puzzle that only come from a definition file
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct Custom {
+    // the numbers of stickers of the piece types taken out, which are drawn as holes and don't
+    // need to be solved, like void puzzles without their center-type pieces
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub removed: Vec<usize>,
+    // the axes whose sides can turn, or None for all of them. the whole puzzle can always rotate.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub turn_axes: Option<Vec<u16>>,
+    // sides whose stickers start with the color of another side, as (side, color)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub colors: Vec<(i16, i16)>,
+}
+
+impl Custom {
+    pub fn is_empty(&self) -> bool {
+        self == &Custom::default()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(try_from = "PuzzleRepr", into = "PuzzleRepr")]
+pub struct Puzzle {
+    // the most layers along any axis
+    pub n: i16,
+    pub d: u16,
+    // the number of layers along each axis, which are all n except in a hypercuboid. the
+    // coordinates along axis i are in -shape[i]..=shape[i].
+    shape: Vec<i16>,
+    // side of each sticker (sides related by ! are opposite), indexed by face and then by the
+    // coordinates along the other axes. see index.
+    stickers: Vec<i16>,
+    // the index of the first sticker of each face, and the number of stickers at the end
+    face_starts: Vec<usize>,
+    // whether the center-type pieces are removed
+    pub void: bool,
+    // in a supercube, the side that an arrow on each sticker points to, indexed like stickers.
+    // this only tracks one direction along each sticker, which is a full orientation in 3D.
+    pointers: Option<Vec<i16>>,
+    custom: Custom,
+}
+
+impl From<Puzzle> for PuzzleRepr {
+    fn from(puzzle: Puzzle) -> Self {
+        PuzzleRepr {
+            n: puzzle.n,
+            d: puzzle.d,
+            stickers: (0..puzzle.stickers.len())
+                .map(|ind| (puzzle.position(ind), puzzle.stickers[ind]))
+                .collect(),
+            void: puzzle.void,
+            pointers: puzzle.pointers.as_ref().map(|pointers| {
+                (0..pointers.len())
+                    .map(|ind| (puzzle.position(ind), pointers[ind]))
+                    .collect()
+            }),
+            shape: (!puzzle.is_cube()).then_some(puzzle.shape),
+            custom: puzzle.custom,
+        }
+    }
+}
+
+impl TryFrom<PuzzleRepr> for Puzzle {
+    type Error = String;
+
+    fn try_from(repr: PuzzleRepr) -> Result<Self, String> {
+        let mut puzzle = match repr.shape {
+            Some(shape) if shape.len() != repr.d as usize || shape.iter().any(|&n| n < 1) => {
+                return Err(format!("{shape:?} is not a shape of a {}D puzzle", repr.d))
+            }
+            Some(shape) => Puzzle::make_solved_shape(&shape),
+            None => Puzzle::make_solved(repr.n, repr.d),
+        };
+        puzzle.void = repr.void;
+        puzzle.custom = repr.custom;
+        if repr.stickers.len() != puzzle.stickers.len() {
+            return Err(format!(
+                "puzzle should have {} stickers, not {}",
+                puzzle.stickers.len(),
+                repr.stickers.len()
+            ));
+        }
+        for (pos, side) in repr.stickers {
+            if !puzzle.is_sticker(&pos) {
+                return Err(format!("{pos:?} is not a sticker position"));
+            }
+            let ind = puzzle.index(&pos);
+            puzzle.stickers[ind] = side;
+        }
+        if let Some(repr_pointers) = repr.pointers {
+            let mut pointers = vec![0; puzzle.stickers.len()];
+            for (pos, pointer) in repr_pointers {
+                if !puzzle.is_sticker(&pos) {
+                    return Err(format!("{pos:?} is not a sticker position"));
+                }
+                pointers[puzzle.index(&pos)] = pointer;
+            }
+            puzzle.pointers = Some(pointers);
+        }
+        Ok(puzzle)
+    }
 }
 
 pub fn ax(s: i16) -> i16 {
     s.max(!s)
 }
 
+// 1 for an even permutation, -1 for an odd one
+fn permutation_sign(perm: &[usize]) -> i16 {
+    let inversions = (0..perm.len())
+        .tuple_combinations()
+        .filter(|&(i, j)| perm[i] > perm[j])
+        .count();
+    if inversions % 2 == 1 {
+        -1
+    } else {
+        1
+    }
+}
+
+// the rotations that take the piece at home to slot, each given as the axis of slot that each
+// axis of home goes to
+fn placements(home: &[i16], slot: &[i16]) -> Vec<Vec<usize>> {
+    (0..home.len())
+        .permutations(home.len())
+        .filter(|perm| {
+            if perm
+                .iter()
+                .enumerate()
+                .any(|(i, &j)| home[i].abs() != slot[j].abs())
+            {
+                return false;
+            }
+            // reflecting along an axis with coordinate 0 fixes the handedness without moving it
+            if home.contains(&0) {
+                return true;
+            }
+            let signs: i16 = perm
+                .iter()
+                .enumerate()
+                .map(|(i, &j)| home[i].signum() * slot[j].signum())
+                .product();
+            permutation_sign(perm) * signs == 1
+        })
+        .collect()
+}
+
+// how far around a 3D corner the color from axis 0 has been twisted
+fn twist(slot: &[i16], perm: &[usize]) -> usize {
+    let around = if slot.iter().map(|x| x.signum()).product::<i16>() > 0 {
+        [0, 1, 2]
+    } else {
+        [0, 2, 1]
+    };
+    around
+        .iter()
+        .position(|&axis| axis == perm[0])
+        .expect("perm is a permutation of the axes")
+}
+
+// whether the color from the lower axis of a 3D edge has moved off the lower axis
+fn flip(home: &[i16], slot: &[i16], perm: &[usize]) -> usize {
+    let lower = |pos: &[i16]| {
+        pos.iter()
+            .position(|&x| x != 0)
+            .expect("edges have two nonzero coordinates")
+    };
+    (perm[lower(home)] != lower(slot)) as usize
+}
+
 impl Puzzle {
     pub fn make_solved(n: i16, d: u16) -> Puzzle {
-        if d == 1 {
-            // i think multi_cartesian_product returns empty iterator for the empty product
+        Self::make_solved_with(n, vec![n; d as usize])
+    }
 
-            return Puzzle {
-                n,
-                d,
-                stickers: HashMap::from([(vec![-n], !0), (vec![n], 0)]),
-            };
+    // a hypercuboid, with the number of layers along each axis
+    pub fn make_solved_shape(shape: &[i16]) -> Puzzle {
+        let n = shape.iter().copied().max().unwrap_or(1);
+        Self::make_solved_with(n, shape.to_vec())
+    }
+
+    fn make_solved_with(n: i16, shape: Vec<i16>) -> Puzzle {
+        let d = shape.len() as u16;
+        let mut face_starts = vec![0];
+        for face in 0..2 * d as usize {
+            let face_size: usize = (0..d as usize)
+                .filter(|&i| i != face / 2)
+                .map(|i| shape[i] as usize)
+                .product();
+            face_starts.push(face_starts[face] + face_size);
         }
+        let stickers = (0..2 * d as usize)
+            .flat_map(|face| {
+                let side = if face % 2 == 1 {
+                    !(face as i16 / 2)
+                } else {
+                    face as i16 / 2
+                };
+                std::iter::repeat_n(side, face_starts[face + 1] - face_starts[face])
+            })
+            .collect();
+        Puzzle {
+            n,
+            d,
+            shape,
+            stickers,
+            face_starts,
+            void: false,
+            pointers: None,
+            custom: Custom::default(),
+        }
+    }
+
+    // the number of layers along an axis
+    pub fn size(&self, axis: usize) -> i16 {
+        self.shape[axis]
+    }
+
+    pub fn shape(&self) -> &[i16] {
+        &self.shape
+    }
+
+    // whether every axis has the same number of layers
+    pub fn is_cube(&self) -> bool {
+        self.shape.iter().all(|&size| size == self.n)
+    }
+
+    // like 3^4, or 2x3x3x3 for a hypercuboid
+    pub fn size_name(&self) -> String {
+        if self.is_cube() {
+            format!("{}^{}", self.n, self.d)
+        } else {
+            self.shape.iter().join("x")
+        }
+    }
+
+    // track the orientation of every sticker, starting from the solved orientation. the arrows
+    // on a face all point along the next axis.
+    pub fn make_super(&mut self) {
+        self.pointers = Some(
+            (0..self.stickers.len())
+                .map(|ind| (ax(self.index_face(ind)) + 1) % self.d as i16)
+                .collect(),
+        );
+    }
 
-        let mut stickers = HashMap::new();
-        for (side, coords) in [n, -n].into_iter().cartesian_product(
-            (0..d - 1)
-                .map(|_| (-n + 1..n).step_by(2))
-                .multi_cartesian_product(),
-        ) {
-            let mut pos = vec![side];
-            pos.extend(&coords);
-            for f in 0..(d as i16) {
-                stickers.insert(pos.clone(), if side >= 0 { f } else { !f });
-                pos.rotate_right(1);
+    pub fn is_super(&self) -> bool {
+        self.pointers.is_some()
+    }
+
+    // a solved puzzle of the same kind
+    pub fn make_solved_like(&self) -> Puzzle {
+        let mut puzzle = Self::make_solved_with(self.n, self.shape.clone());
+        puzzle.void = self.void;
+        if self.is_super() {
+            puzzle.make_super();
+        }
+        puzzle.customize(self.custom.clone());
+        puzzle
+    }
+
+    // make a solved puzzle into the one a definition file describes, recoloring its sides
+    pub fn customize(&mut self, custom: Custom) {
+        for &(side, color) in &custom.colors {
+            for ind in 0..self.stickers.len() {
+                if self.index_face(ind) == side {
+                    self.stickers[ind] = color;
+                }
             }
         }
-        Puzzle { n, d, stickers }
+        self.custom = custom;
+    }
+
+    pub fn custom(&self) -> &Custom {
+        &self.custom
+    }
+
+    // the side the arrow on a sticker points to, in a supercube
+    pub fn pointer(&self, pos: &[i16]) -> Option<i16> {
+        self.pointers
+            .as_ref()
+            .map(|pointers| pointers[self.index(pos)])
+    }
+
+    // the face a sticker at an index is on, in the order 0, !0, 1, !1, ...
+    fn index_face_number(&self, ind: usize) -> usize {
+        self.face_starts.partition_point(|&start| start <= ind) - 1
+    }
+
+    // the axis a position is on the outside of, if it is on exactly one
+    pub fn face_axis(&self, pos: &[i16]) -> Option<usize> {
+        let mut axes = (0..pos.len()).filter(|&i| pos[i].abs() == self.shape[i]);
+        match (axes.next(), axes.next()) {
+            (Some(axis), None) => Some(axis),
+            _ => None,
+        }
+    }
+
+    fn is_sticker(&self, pos: &[i16]) -> bool {
+        pos.len() == self.d as usize
+            && self.face_axis(pos).is_some()
+            && pos
+                .iter()
+                .zip(&self.shape)
+                .all(|(&x, &n)| x.abs() == n || (x.abs() < n && (x + n - 1) % 2 == 0))
+    }
+
+    // index of a sticker position into stickers. the faces are in the order 0, !0, 1, !1, ...
+    pub fn index(&self, pos: &[i16]) -> usize {
+        let axis = self.face_axis(pos).expect("should be on a face");
+        let face = 2 * axis + (pos[axis] < 0) as usize;
+        self.face_starts[face]
+            + pos
+                .iter()
+                .zip(&self.shape)
+                .enumerate()
+                .filter(|&(i, _)| i != axis)
+                .fold(0, |ind, (_, (&x, &n))| {
+                    ind * n as usize + ((x + n - 1) / 2) as usize
+                })
+    }
+
+    // the sticker position at an index into stickers
+    pub fn position(&self, ind: usize) -> Vec<i16> {
+        let mut pos = vec![0; self.d as usize];
+        self.position_into(ind, &mut pos);
+        pos
+    }
+
+    fn position_into(&self, ind: usize, pos: &mut [i16]) {
+        let face = self.index_face_number(ind);
+        let axis = face / 2;
+        let mut rest = ind - self.face_starts[face];
+        for i in (0..self.d as usize).rev() {
+            let n = self.shape[i];
+            if i == axis {
+                pos[i] = if face % 2 == 1 { -n } else { n };
+            } else {
+                pos[i] = 2 * (rest % n as usize) as i16 - n + 1;
+                rest /= n as usize;
+            }
+        }
+    }
+
+    // the side a sticker at an index lies on
+    fn index_face(&self, ind: usize) -> i16 {
+        let face = self.index_face_number(ind) as i16;
+        if face % 2 == 1 {
+            !(face / 2)
+        } else {
+            face / 2
+        }
+    }
+
+    // the side a sticker position lies on
+    pub fn face(&self, pos: &[i16]) -> i16 {
+        self.index_face(self.index(pos))
+    }
+
+    pub fn sticker(&self, pos: &[i16]) -> i16 {
+        self.stickers[self.index(pos)]
+    }
+
+    // how many stickers have a different color than on another puzzle of the same shape
+    pub fn changed_stickers(&self, other: &Puzzle) -> usize {
+        (self.stickers.iter().zip(&other.stickers))
+            .filter(|(a, b)| a != b)
+            .count()
     }
 
     pub fn is_solved(&self) -> bool {
         let mut side_colors = HashMap::new();
-        for (pos, &color) in &self.stickers {
-            let side = pos
-                .iter()
-                .position(|x| x.abs() == self.n)
-                .expect("should be on a face");
-            let side = if pos[side] < 0 { !side } else { side };
-            let old_color = side_colors.insert(side, color);
+        for (ind, &color) in self.stickers.iter().enumerate() {
+            if self.is_void(&self.position(ind)) {
+                continue;
+            }
+            // in a supercube, the arrows on a side also have to line up
+            let pointer = self.pointers.as_ref().map(|pointers| pointers[ind]);
+            let old_color = side_colors.insert(self.index_face(ind), (color, pointer));
             match old_color {
-                Some(c) if c != color => return false,
+                Some(c) if c != (color, pointer) => return false,
                 _ => (),
             }
         }
         true
     }
 
-    fn side_turn(&mut self, turn: SideTurn) -> Option<()> {
-        let SideTurn {
-            side,
-            layer_min,
-            layer_max,
-            mut from,
-            mut to,
-        } = turn;
-        if side == from || side == !from || side == to || side == !to || from == to || from == !to {
-            return None;
-        }
-
-        let layer_range = layer_min - 1..=layer_max + 1;
-
+    // rotates the stickers at the positions where moving(pos) is true from the from axis to the
+    // to axis. returns the indices of the stickers that changed, leaving out the ones that were
+    // replaced by the same color.
+    fn permute(&mut self, moving: impl Fn(&[i16]) -> bool, from: i16, to: i16) -> Vec<usize> {
+        let (mut from, mut to) = (from, to);
         let to_swap = (from < 0) != (to < 0);
         if from < 0 {
             from = !from
@@ -166,96 +692,516 @@ impl Puzzle {
         if to_swap {
             std::mem::swap(&mut from, &mut to)
         }
+        // turning from an axis to itself reflects it
+        let rotate_pointer = |p: i16| {
+            if ax(p) == from && from == to {
+                !p
+            } else if ax(p) == from {
+                if p < 0 {
+                    !to
+                } else {
+                    to
+                }
+            } else if ax(p) == to {
+                if p < 0 {
+                    from
+                } else {
+                    !from
+                }
+            } else {
+                p
+            }
+        };
+        let (from, to) = (from as usize, to as usize);
+        self.remap(
+            moving,
+            |pos, source| {
+                source[from] = pos[to];
+                source[to] = -pos[from];
+            },
+            rotate_pointer,
+        )
+    }
 
-        let mut new_stickers = HashMap::new();
-        for pos in self.stickers.keys() {
-            if (side >= 0 && layer_range.contains(&pos[side as usize]))
-                || (side < 0 && layer_range.contains(&pos[(!side) as usize]))
-            {
-                let mut from_pos = pos.clone();
-                from_pos[from as usize] = pos[to as usize];
-                from_pos[to as usize] = -pos[from as usize];
-                new_stickers.insert(pos.clone(), self.stickers[&from_pos]);
+    // turns the stickers at the positions where moving(pos) is true halfway around in the plane
+    // of two axes, which works even if they have different numbers of layers
+    fn half_turn(&mut self, moving: impl Fn(&[i16]) -> bool, a: i16, b: i16) -> Vec<usize> {
+        let (a, b) = (ax(a), ax(b));
+        self.remap(
+            moving,
+            |pos, source| {
+                source[a as usize] = -pos[a as usize];
+                source[b as usize] = -pos[b as usize];
+            },
+            |p| if ax(p) == a || ax(p) == b { !p } else { p },
+        )
+    }
+
+    // moves the stickers at the positions where moving(pos) is true to where source puts them
+    // from, with pointer turning their arrows
+    fn remap(
+        &mut self,
+        moving: impl Fn(&[i16]) -> bool,
+        source_of: impl Fn(&[i16], &mut [i16]),
+        rotate_pointer: impl Fn(i16) -> i16,
+    ) -> Vec<usize> {
+        let mut new_stickers = self.stickers.clone();
+        let mut new_pointers = self.pointers.clone();
+        let mut moved = vec![];
+        let mut pos = vec![0; self.d as usize];
+        let mut source = vec![0; self.d as usize];
+        for (ind, new_sticker) in new_stickers.iter_mut().enumerate() {
+            self.position_into(ind, &mut pos);
+            if moving(&pos) {
+                source.copy_from_slice(&pos);
+                source_of(&pos, &mut source);
+                let source_ind = self.index(&source);
+                *new_sticker = self.stickers[source_ind];
+                let mut changed = *new_sticker != self.stickers[ind];
+                if let (Some(new_pointers), Some(pointers)) = (&mut new_pointers, &self.pointers) {
+                    new_pointers[ind] = rotate_pointer(pointers[source_ind]);
+                    changed |= new_pointers[ind] != pointers[ind];
+                }
+                if changed {
+                    moved.push(ind);
+                }
             }
         }
-        self.stickers.extend(new_stickers);
-        Some(())
+        self.stickers = new_stickers;
+        self.pointers = new_pointers;
+        moved
     }
 
-    fn puzzle_rotate(&mut self, turn: PuzzleTurn) -> Option<()> {
+    // whether a quarter turn from one axis to another keeps the puzzle's shape
+    fn is_square(&self, from: i16, to: i16) -> bool {
+        self.size(ax(from) as usize) == self.size(ax(to) as usize)
+    }
+
+    // returns the indices of the stickers that changed. a half turn turns twice at once, which
+    // is the only way to turn axes with different numbers of layers.
+    fn side_turn(&mut self, turn: SideTurn, half: bool) -> Result<Vec<usize>, TurnError> {
+        let SideTurn {
+            side,
+            layer_min,
+            layer_max,
+            from,
+            to,
+        } = turn;
+        if side == from || side == !from || side == to || side == !to || from == !to {
+            return Err(TurnError::SameAxis);
+        }
+        // turning from an axis to itself flips the layer over, which is only a real move in 2D
+        if from == to && self.d != 2 {
+            return Err(TurnError::SameAxis);
+        }
+        let side_ax = ax(side) as usize;
+        let n = self.size(side_ax);
+        if layer_min > layer_max || layer_min < 1 - n || layer_max > n - 1 {
+            return Err(TurnError::LayerOutOfRange);
+        }
+        if let Some(turn_axes) = &self.custom.turn_axes {
+            if !turn_axes.contains(&(side_ax as u16)) {
+                return Err(TurnError::FixedAxis);
+            }
+        }
+
+        let layer_range = layer_min - 1..=layer_max + 1;
+        let moving = |pos: &[i16]| layer_range.contains(&pos[side_ax]);
+        if half && from != to {
+            Ok(self.half_turn(moving, from, to))
+        } else if self.is_square(from, to) {
+            Ok(self.permute(moving, from, to))
+        } else {
+            Err(TurnError::NotSquare)
+        }
+    }
+
+    fn puzzle_rotate(&mut self, turn: PuzzleTurn, half: bool) -> Result<Vec<usize>, TurnError> {
         let PuzzleTurn { from, to } = turn;
         if from == to || from == !to {
-            return None;
+            return Err(TurnError::SameAxis);
         }
 
-        let mut new_stickers = HashMap::new();
-        for pos in self.stickers.keys() {
-            let mut from_pos = pos.clone();
-            from_pos[from as usize] = pos[to as usize];
-            from_pos[to as usize] = -pos[from as usize];
-            new_stickers.insert(pos.clone(), self.stickers[&from_pos]);
+        if half {
+            Ok(self.half_turn(|_| true, from, to))
+        } else if self.is_square(from, to) {
+            Ok(self.permute(|_| true, from, to))
+        } else {
+            Err(TurnError::NotSquare)
         }
-        self.stickers = new_stickers;
-        Some(())
     }
 
-    pub fn turn(&mut self, turn: Turn) -> Option<()> {
+    fn check_axes(&self, turn: &Turn) -> Result<(), TurnError> {
+        let axes = match turn {
+            Turn::Side(t) => vec![t.side, t.from, t.to],
+            Turn::Puzzle(t) => vec![t.from, t.to],
+            Turn::Repeat(t, _) => return self.check_axes(t),
+        };
+        if axes.into_iter().any(|s| ax(s) as u16 >= self.d) {
+            return Err(TurnError::AxisOutOfRange);
+        }
+        Ok(())
+    }
+
+    // returns the indices of the stickers that changed, and leaves the puzzle alone if the turn
+    // can't be made
+    pub fn turn(&mut self, turn: Turn) -> Result<Vec<usize>, TurnError> {
+        self.check_axes(&turn)?;
+        self.turn_half(turn, false)
+    }
+
+    fn turn_half(&mut self, turn: Turn, half: bool) -> Result<Vec<usize>, TurnError> {
         match turn {
-            Turn::Side(t) => self.side_turn(t),
-            Turn::Puzzle(t) => self.puzzle_rotate(t),
+            Turn::Side(t) => self.side_turn(t, half),
+            Turn::Puzzle(t) => self.puzzle_rotate(t, half),
+            Turn::Repeat(t, times) => self.repeat_turn(&t, times),
         }
     }
 
+    // the stickers that changed are the ones that are different after all the turns
+    fn repeat_turn(&mut self, turn: &Turn, times: u8) -> Result<Vec<usize>, TurnError> {
+        let before = self.clone();
+        match turn.plane() {
+            // axes with different numbers of layers can only be turned halfway around
+            Some((from, to)) if from != to && !self.is_square(from, to) => {
+                if times % 2 == 1 {
+                    return Err(TurnError::NotSquare);
+                }
+                if times % 4 == 2 {
+                    self.turn_half(turn.clone(), true)?;
+                }
+            }
+            _ => {
+                for _ in 0..times {
+                    self.turn_half(turn.clone(), false)?;
+                }
+            }
+        }
+        Ok((0..self.stickers.len())
+            .filter(|&ind| {
+                self.stickers[ind] != before.stickers[ind]
+                    || self.pointers.as_ref().map(|p| p[ind])
+                        != before.pointers.as_ref().map(|p| p[ind])
+            })
+            .collect())
+    }
+
     fn piece_body(&self, piece: &[i16]) -> Vec<i16> {
-        if let Some(ind) = piece.iter().position(|x| x.abs() == self.n) {
-            let mut piece_body = piece.to_vec();
-            if piece[ind] == self.n {
-                piece_body[ind] -= 1;
-            } else {
-                piece_body[ind] += 1;
+        let mut piece_body = piece.to_vec();
+        if let Some(ind) = (0..piece.len()).find(|&i| piece[i].abs() == self.shape[i]) {
+            piece_body[ind] -= piece[ind].signum();
+        }
+        piece_body
+    }
+
+    // number of stickers on the piece at a position. along an axis with one layer, a piece has
+    // a sticker on both sides.
+    fn sticker_count(&self, piece: &[i16]) -> usize {
+        self.piece_body(piece)
+            .iter()
+            .zip(&self.shape)
+            .map(|(&x, &n)| match n {
+                1 => 2,
+                n if x.abs() == n - 1 => 1,
+                _ => 0,
+            })
+            .sum()
+    }
+
+    // whether the piece at a position has been removed, from a void puzzle or by the puzzle's
+    // definition
+    pub fn is_void(&self, piece: &[i16]) -> bool {
+        if !self.void && self.custom.removed.is_empty() {
+            return false;
+        }
+        let count = self.sticker_count(piece);
+        (self.void && count <= 1) || self.custom.removed.contains(&count)
+    }
+
+    // the piece at a position, which can be the position of the piece or one of its stickers
+    pub fn piece(&self, pos: &[i16]) -> Piece {
+        let position = self.piece_body(pos);
+        let mut orientation = vec![];
+        for (ind, &x) in position.iter().enumerate() {
+            let n = self.shape[ind];
+            // the piece of a 1^d has two stickers per axis
+            for (layer, face_coord, side) in [(n - 1, n, ind as i16), (-(n - 1), -n, !(ind as i16))]
+            {
+                if x == layer {
+                    let mut sticker = position.clone();
+                    sticker[ind] = face_coord;
+                    orientation.push((side, self.sticker(&sticker)));
+                }
             }
-            piece_body
-        } else {
-            piece.to_vec()
+        }
+        Piece {
+            position,
+            orientation,
         }
     }
 
-    fn piece_body_stickers(&self, piece: &[i16]) -> Vec<i16> {
-        let mut colors = vec![];
-        for (ind, x) in piece.iter().enumerate() {
-            let mut piece = piece.to_vec();
-            if *x == self.n - 1 {
-                piece[ind] += 1;
-            } else if *x == -(self.n - 1) {
-                piece[ind] -= 1;
-            } else {
+    // all the pieces that have at least one sticker
+    pub fn pieces(&self) -> Vec<Piece> {
+        self.shape
+            .iter()
+            .map(|&n| (-n + 1..n).step_by(2))
+            .multi_cartesian_product()
+            .filter(|pos| pos.iter().zip(&self.shape).any(|(x, &n)| x.abs() == n - 1))
+            .map(|pos| self.piece(&pos))
+            .collect()
+    }
+
+    // the color each side is being solved to, taken to be the most common color on it
+    pub fn face_colors(&self) -> HashMap<i16, i16> {
+        let mut counts: HashMap<i16, HashMap<i16, usize>> = HashMap::new();
+        for (ind, &color) in self.stickers.iter().enumerate() {
+            if self.is_void(&self.position(ind)) {
+                continue;
+            }
+            *counts
+                .entry(self.index_face(ind))
+                .or_default()
+                .entry(color)
+                .or_default() += 1;
+        }
+        counts
+            .into_iter()
+            .map(|(side, colors)| {
+                let (color, _count) = colors
+                    .into_iter()
+                    .max_by_key(|&(color, count)| (count, color))
+                    .expect("every side has stickers");
+                (side, color)
+            })
+            .collect()
+    }
+
+    // for each number of stickers a piece can have, how many of those pieces are solved and how
+    // many there are, leaving out removed pieces
+    pub fn progress(&self) -> BTreeMap<usize, (usize, usize)> {
+        let face_colors = self.face_colors();
+        let mut progress: BTreeMap<usize, (usize, usize)> = BTreeMap::new();
+        for piece in self.pieces() {
+            if self.is_void(&piece.position) {
                 continue;
             }
-            colors.push(self.stickers[&piece]);
-            if self.n == 1 {
-                // the piece of a 1^d has two stickers per axis
-                colors.push(!self.stickers[&piece]);
+            let (solved, total) = progress.entry(piece.orientation.len()).or_default();
+            *solved += piece.is_solved(&face_colors) as usize;
+            *total += 1;
+        }
+        progress
+    }
+
+    // whether scramble_random_state works for this puzzle
+    pub fn has_random_state(&self) -> bool {
+        self.d == 3
+            && (self.n == 2 || self.n == 3)
+            && self.is_cube()
+            && !self.is_super()
+            && self.custom.is_empty()
+    }
+
+    // move the piece that is at home in the solved puzzle to slot, rotated by perm
+    fn place(&mut self, home: &[i16], slot: &[i16], perm: &[usize]) {
+        for (i, &j) in perm.iter().enumerate() {
+            if home[i].abs() != self.n - 1 {
+                continue;
             }
+            let color = if home[i] > 0 { i as i16 } else { !(i as i16) };
+            let mut sticker = slot.to_vec();
+            sticker[j] = slot[j].signum() * self.n;
+            let ind = self.index(&sticker);
+            self.stickers[ind] = color;
         }
-        colors
     }
 
-    pub fn stickers(&self, piece: &[i16]) -> Vec<i16> {
-        self.piece_body_stickers(&self.piece_body(piece))
+    // pick the rotation of each piece at random, except for the last one, which has to bring the
+    // total of measure over all the pieces to a multiple of modulus
+    fn place_all(
+        &mut self,
+        slots: &[Vec<i16>],
+        perm: &[usize],
+        modulus: usize,
+        measure: impl Fn(&[i16], &[i16], &[usize]) -> usize,
+        rng: &mut impl Rng,
+    ) {
+        let mut total = 0;
+        for (k, slot) in slots.iter().enumerate() {
+            let home = &slots[perm[k]];
+            let options = placements(home, slot);
+            let rotation = if k == slots.len() - 1 {
+                options
+                    .iter()
+                    .find(|rotation| {
+                        (total + measure(home, slot, rotation)).is_multiple_of(modulus)
+                    })
+                    .expect("one rotation makes the total work")
+            } else {
+                options
+                    .choose(rng)
+                    .expect("pieces can be placed in any slot")
+            };
+            total += measure(home, slot, rotation);
+            self.place(home, slot, rotation);
+        }
+    }
+
+    // set the solved puzzle to a uniformly random state that can be reached by turning, instead
+    // of applying random turns. the centers of a 3^3 stay where they are.
+    pub fn scramble_random_state(&mut self, rng: &mut impl Rng) {
+        let positions: Vec<Vec<i16>> = self.pieces().into_iter().map(|p| p.position).collect();
+        self.scramble_random_pieces(&positions, rng);
+    }
+
+    // like scramble_random_state, but only the pieces in these slots move, among themselves
+    pub fn scramble_random_pieces(&mut self, positions: &[Vec<i16>], rng: &mut impl Rng) {
+        let corners: Vec<Vec<i16>> = positions
+            .iter()
+            .filter(|pos| pos.iter().all(|x| x.abs() == self.n - 1))
+            .cloned()
+            .collect();
+        let edges: Vec<Vec<i16>> = positions
+            .iter()
+            .filter(|pos| pos.iter().filter(|&&x| x == 0).count() == 1)
+            .cloned()
+            .collect();
+
+        let mut corner_perm: Vec<usize> = (0..corners.len()).collect();
+        corner_perm.shuffle(rng);
+        let mut edge_perm: Vec<usize> = (0..edges.len()).collect();
+        edge_perm.shuffle(rng);
+        // the corners and edges of a 3^3 are permuted with the same parity
+        if self.n == 3 && permutation_sign(&corner_perm) != permutation_sign(&edge_perm) {
+            if edges.len() > 1 {
+                edge_perm.swap(0, 1);
+            } else {
+                corner_perm.swap(0, 1);
+            }
+        }
+
+        self.place_all(
+            &corners,
+            &corner_perm,
+            3,
+            |_, slot, perm| twist(slot, perm),
+            rng,
+        );
+        self.place_all(&edges, &edge_perm, 2, flip, rng);
+    }
+
+    // the random turns that scramble makes with the same rng. turns between axes with different
+    // numbers of layers are made twice, and turns of a side with one layer move the whole puzzle,
+    // so they are made as rotations.
+    pub fn scramble_turns(&self, length: usize, rng: &mut impl Rng) -> Vec<Turn> {
+        (0..length)
+            .map(|_| {
+                let mut axes: Vec<i16> = (0..self.d as i16).collect();
+                axes.shuffle(rng);
+                // the turned side has to be on an axis that can turn
+                if let Some(turn_axes) = &self.custom.turn_axes {
+                    let side = *turn_axes.choose(rng).expect("some axis can turn") as i16;
+                    axes.retain(|&axis| axis != side);
+                    axes.insert(0, side);
+                }
+                let n = self.size(axes[0] as usize);
+                let layer = n - 1 - 2 * rng.gen_range(0..n);
+                // in 2D, the only moves are flips
+                let to = if self.d == 2 { axes[1] } else { axes[2] };
+                let turn = if n == 1 && self.d > 2 {
+                    Turn::Puzzle(PuzzleTurn { from: axes[1], to })
+                } else {
+                    Turn::Side(SideTurn {
+                        side: axes[0],
+                        layer_min: layer,
+                        layer_max: layer,
+                        from: axes[1],
+                        to,
+                    })
+                };
+                if self.is_square(axes[1], to) {
+                    turn
+                } else {
+                    Turn::Repeat(Box::new(turn), 2)
+                }
+            })
+            .collect()
+    }
+
+    pub fn scramble(&mut self, length: usize, rng: &mut impl Rng) {
+        for turn in self.scramble_turns(length, rng) {
+            self.turn(turn).expect("scramble turns are valid");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn side_turn(side: i16, from: i16, to: i16) -> Turn {
+        Turn::Side(SideTurn {
+            side,
+            layer_min: 1,
+            layer_max: 1,
+            from,
+            to,
+        })
+    }
+
+    fn repeat(turn: &Turn, times: u8) -> Turn {
+        Turn::Repeat(Box::new(turn.clone()), times)
+    }
+
+    #[test]
+    fn merge_inverse_cancels() {
+        let x = side_turn(0, 1, 2);
+        assert_eq!(x.merge(&x.inverse()), Some(None));
+        assert_eq!(x.inverse().merge(&x), Some(None));
+    }
+
+    #[test]
+    fn merge_same_turn_repeats() {
+        let x = side_turn(0, 1, 2);
+        assert_eq!(x.merge(&x), Some(Some(repeat(&x, 2))));
+        // the same turn written from the other side of the plane
+        assert_eq!(x.merge(&side_turn(0, 2, !1)), Some(Some(repeat(&x, 2))));
+        assert_eq!(repeat(&x, 2).merge(&repeat(&x, 2)), Some(None));
+    }
+
+    #[test]
+    fn merge_half_turn_and_quarter_turn() {
+        let x = side_turn(0, 1, 2);
+        // X2 X is X', kept as the turn it started with made 3 times
+        let merged = repeat(&x, 2).merge(&x).unwrap().unwrap();
+        assert_eq!(merged, repeat(&x, 3));
+        assert_eq!(merged.merge(&x), Some(None));
+        assert_eq!(merged.quarter_turns(), 1);
+        assert_eq!(repeat(&x, 2).merge(&x.inverse()), Some(Some(x.clone())));
+
+        // a base turn going from the higher axis to the lower one
+        let y = side_turn(0, 2, 1);
+        assert_eq!(repeat(&y, 2).merge(&y), Some(Some(repeat(&y, 3))));
+        assert_eq!(repeat(&y, 2).merge(&x), Some(Some(y.clone())));
+    }
+
+    #[test]
+    fn merge_flips() {
+        let flip = side_turn(1, 0, !0);
+        assert_eq!(flip.merge(&flip), Some(None));
+        assert_eq!(flip.merge(&side_turn(1, !0, 0)), Some(None));
+        assert_eq!(flip.quarter_turns(), 1);
+        assert_eq!(repeat(&flip, 3).merge(&flip), Some(None));
     }
 
-    pub fn scramble(&mut self, rng: &mut ThreadRng) {
-        for _ in 0..5000 {
-            let mut axes: Vec<i16> = (0..self.d as i16).collect();
-            axes.shuffle(rng);
-            let layer = self.n - 1 - 2 * rng.gen_range(0..self.n);
-            self.turn(Turn::Side(SideTurn {
-                side: axes[0],
-                layer_min: layer,
-                layer_max: layer,
-                from: axes[1],
-                to: axes[2],
-            }));
+    #[test]
+    fn merge_different_turns() {
+        let x = side_turn(0, 1, 2);
+        assert_eq!(x.merge(&side_turn(0, 1, 3)), None);
+        let mut wide = side_turn(0, 1, 2);
+        if let Turn::Side(t) = &mut wide {
+            t.layer_min = 0;
         }
+        assert_eq!(x.merge(&wide), None);
+        assert_eq!(x.merge(&Turn::Puzzle(PuzzleTurn { from: 1, to: 2 })), None);
     }
 }
@@ -0,0 +1,91 @@
+use crate::timer::format_duration;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::Path;
+use std::time::Duration;
+
+pub const RECORDS_PATH: &str = "logs/records.json";
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SolveRecord {
+    pub stm: usize,
+    pub time: Option<Duration>,
+    // when it was solved, in RFC 3339
+    pub solved: String,
+}
+
+// every solve finished in the program, by size like "3^4" or "2x3x3", for personal bests
+#[derive(Serialize, Deserialize, Default)]
+pub struct Records {
+    sizes: BTreeMap<String, Vec<SolveRecord>>,
+}
+
+fn mean<T>(items: &[T], value: impl Fn(&T) -> f64) -> f64 {
+    items.iter().map(value).sum::<f64>() / items.len() as f64
+}
+
+impl Records {
+    // no file yet means no solves yet
+    pub fn load() -> Result<Self, Box<dyn std::error::Error>> {
+        match File::open(RECORDS_PATH) {
+            Ok(file) => Ok(serde_json::from_reader(BufReader::new(file))?),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Records::default()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    pub fn store(&self) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(parent) = Path::new(RECORDS_PATH).parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut writer = BufWriter::new(File::create(RECORDS_PATH)?);
+        serde_json::to_writer_pretty(&mut writer, self)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    pub fn add(&mut self, size: &str, record: SolveRecord) {
+        self.sizes.entry(size.to_string()).or_default().push(record);
+    }
+
+    // the bests of every solve of the size, and the means of the ones solved since the session
+    // started, which is in RFC 3339 like the solve times
+    pub fn lines(&self, size: &str, session_started: &str) -> Vec<String> {
+        let solves = self.sizes.get(size).map(Vec::as_slice).unwrap_or_default();
+        let mut lines = vec![];
+        if let Some(best) = solves.iter().map(|solve| solve.stm).min() {
+            let mut line = format!("best of {} solves: {best} STM", solves.len());
+            if let Some(time) = solves.iter().filter_map(|solve| solve.time).min() {
+                line.push_str(&format!(", {}", format_duration(time)));
+            }
+            lines.push(line);
+        }
+
+        let since = chrono::DateTime::parse_from_rfc3339(session_started).ok();
+        let session: Vec<&SolveRecord> = solves
+            .iter()
+            .filter(|solve| {
+                chrono::DateTime::parse_from_rfc3339(&solve.solved)
+                    .ok()
+                    .zip(since)
+                    .is_some_and(|(solved, since)| solved >= since)
+            })
+            .collect();
+        if !session.is_empty() {
+            let mut line = format!(
+                "mean of {} this session: {:.1} STM",
+                session.len(),
+                mean(&session, |solve| solve.stm as f64)
+            );
+            let times: Vec<Duration> = session.iter().filter_map(|solve| solve.time).collect();
+            if !times.is_empty() {
+                let time = Duration::from_secs_f64(mean(&times, Duration::as_secs_f64));
+                line.push_str(&format!(", {}", format_duration(time)));
+            }
+            lines.push(line);
+        }
+        lines
+    }
+}
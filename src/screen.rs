@@ -0,0 +1,91 @@
+use crossterm::{
+    cursor,
+    style::{self, StyledContent},
+    terminal, QueueableCommand,
+};
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::time::Duration;
+
+// the weight of the newest render time in the moving average, out of 8
+const NEW_RENDER_WEIGHT: u32 = 1;
+
+// draws cells of the puzzle, keeping track of how long rendering takes. once the terminal can't
+// keep up, only cells that changed since the last draw are sent, and frames are skipped until
+// rendering catches up again. input is still handled every frame.
+pub struct Screen {
+    frame_length: Duration,
+    drawn: HashMap<(u16, u16), StyledContent<char>>,
+    lines: HashMap<u16, String>,
+    render_time: Duration,
+    dirty_only: bool,
+    frames_since_draw: u32,
+}
+
+impl Screen {
+    pub fn new(frame_length: Duration) -> Self {
+        Screen {
+            frame_length,
+            drawn: HashMap::new(),
+            lines: HashMap::new(),
+            render_time: Duration::ZERO,
+            dirty_only: false,
+            frames_since_draw: 0,
+        }
+    }
+
+    // whether to render this frame
+    pub fn should_draw(&mut self) -> bool {
+        let interval = (self.render_time.as_micros() / self.frame_length.as_micros()) as u32 + 1;
+        self.frames_since_draw += 1;
+        if self.frames_since_draw >= interval {
+            self.frames_since_draw = 0;
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn draw(
+        &mut self,
+        out: &mut impl Write,
+        x: u16,
+        y: u16,
+        content: StyledContent<char>,
+    ) -> io::Result<()> {
+        if self.dirty_only && self.drawn.get(&(x, y)) == Some(&content) {
+            return Ok(());
+        }
+        self.drawn.insert((x, y), content);
+        out.queue(cursor::MoveTo(x, y))?
+            .queue(style::PrintStyledContent(content))?;
+        Ok(())
+    }
+
+    // replace a whole line of text, if it changed
+    pub fn status(&mut self, out: &mut impl Write, y: u16, text: String) -> io::Result<()> {
+        if self.lines.get(&y) == Some(&text) {
+            return Ok(());
+        }
+        out.queue(cursor::MoveTo(0, y))?
+            .queue(terminal::Clear(terminal::ClearType::CurrentLine))?
+            .queue(style::Print(&text))?;
+        self.lines.insert(y, text);
+        Ok(())
+    }
+
+    // record how long the last render took, including flushing it to the terminal
+    pub fn record(&mut self, render: Duration) {
+        self.render_time =
+            (self.render_time * (8 - NEW_RENDER_WEIGHT) + render * NEW_RENDER_WEIGHT) / 8;
+        if self.render_time > self.frame_length / 2 {
+            self.dirty_only = true;
+        }
+    }
+
+    // the terminal was cleared, so everything needs to be drawn again
+    pub fn invalidate(&mut self) {
+        self.drawn.clear();
+        self.lines.clear();
+    }
+}
@@ -0,0 +1,39 @@
+use crate::{KeybindAxial, KeybindSet};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+pub const SESSION_PATH: &str = "logs/session.json";
+
+// what --resume needs to open the last session where it was left off
+#[derive(Serialize, Deserialize)]
+pub struct Session {
+    // the last log written, by saving or autosaving
+    pub log: PathBuf,
+    // where the session was being saved to
+    pub filename: PathBuf,
+    pub prefs: Option<PathBuf>,
+    pub filters: Option<PathBuf>,
+    pub filter_ind: usize,
+    pub keybind_set: KeybindSet,
+    pub keybind_axial: KeybindAxial,
+}
+
+impl Session {
+    pub fn load() -> Result<Self, Box<dyn std::error::Error>> {
+        let file =
+            File::open(SESSION_PATH).map_err(|err| format!("no session to resume: {err}"))?;
+        Ok(serde_json::from_reader(BufReader::new(file))?)
+    }
+
+    pub fn store(&self) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(parent) = Path::new(SESSION_PATH).parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut writer = BufWriter::new(File::create(SESSION_PATH)?);
+        serde_json::to_writer(&mut writer, self)?;
+        writer.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,571 @@
+use crate::puzzle::{ax, Puzzle, SideTurn, Turn};
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+// solves the puzzles that scramble to a random state: a 3^3 in two phases like Kociemba's
+// algorithm, and a 2^3 by searching the turns of R, U, and F, which leave the DBL corner alone.
+// the pieces are read into the usual model of the 3^3, where each slot says which piece is in
+// it and how it is turned.
+
+// the sides in the order the moves are numbered, with three moves for each: clockwise, half,
+// and counterclockwise
+const SIDES: [i16; 6] = [1, !1, 0, !0, 2, !2];
+// the moves that keep the edges between U and D there and every piece unflipped
+const PHASE_2: [usize; 10] = [0, 1, 2, 3, 4, 5, 7, 10, 13, 16];
+// the moves of R, U, and F
+const CORNER_MOVES: [usize; 9] = [0, 1, 2, 6, 7, 8, 12, 13, 14];
+
+const MAX_PHASE_1: usize = 12;
+const MAX_PHASE_2: usize = 18;
+const MAX_CORNERS: usize = 14;
+
+// the piece in each slot and how far it is turned from its home orientation. the corners are
+// turned 0, 1, or 2 times around, and the edges are flipped or not.
+#[derive(Clone, Copy, PartialEq)]
+struct Cube {
+    cp: [u8; 8],
+    co: [u8; 8],
+    ep: [u8; 12],
+    eo: [u8; 12],
+}
+
+impl Cube {
+    fn solved() -> Self {
+        Cube {
+            cp: std::array::from_fn(|i| i as u8),
+            co: [0; 8],
+            ep: std::array::from_fn(|i| i as u8),
+            eo: [0; 12],
+        }
+    }
+
+    // this cube, then the move
+    fn then(&self, m: &Cube) -> Cube {
+        Cube {
+            cp: std::array::from_fn(|i| self.cp[m.cp[i] as usize]),
+            co: std::array::from_fn(|i| (self.co[m.cp[i] as usize] + m.co[i]) % 3),
+            ep: std::array::from_fn(|i| self.ep[m.ep[i] as usize]),
+            eo: std::array::from_fn(|i| (self.eo[m.ep[i] as usize] + m.eo[i]) % 2),
+        }
+    }
+}
+
+// the slots as the signs of their coordinates, with the edges between U and D last
+struct Slots {
+    corners: Vec<[i16; 3]>,
+    edges: Vec<[i16; 3]>,
+}
+
+impl Slots {
+    fn new() -> Self {
+        let signs = [-1, 0, 1];
+        let all: Vec<[i16; 3]> = signs
+            .iter()
+            .flat_map(|&x| signs.iter().flat_map(move |&y| signs.map(|z| [x, y, z])))
+            .collect();
+        let corners = all
+            .iter()
+            .copied()
+            .filter(|slot| !slot.contains(&0))
+            .collect();
+        let mut edges: Vec<[i16; 3]> = all
+            .iter()
+            .copied()
+            .filter(|slot| slot.iter().filter(|&&x| x == 0).count() == 1)
+            .collect();
+        edges.sort_by_key(|slot| slot[1] == 0);
+        Slots { corners, edges }
+    }
+}
+
+// the axes of a corner's stickers going around it the same way for every corner, starting from
+// the one on the U or D side
+fn corner_axes(slot: &[i16; 3]) -> [usize; 3] {
+    if slot.iter().product::<i16>() > 0 {
+        [1, 2, 0]
+    } else {
+        [1, 0, 2]
+    }
+}
+
+// the axes of an edge's stickers, starting from the one that says whether it is flipped: U or
+// D if it has one, or else F or B
+fn edge_axes(slot: &[i16; 3]) -> [usize; 2] {
+    let axes: Vec<usize> = [1, 2, 0].into_iter().filter(|&a| slot[a] != 0).collect();
+    [axes[0], axes[1]]
+}
+
+fn slot_side(slot: &[i16; 3], axis: usize) -> i16 {
+    if slot[axis] > 0 {
+        axis as i16
+    } else {
+        !(axis as i16)
+    }
+}
+
+// the sides a piece's colors belong on, going around its stickers in order
+fn sticker_sides(
+    puzzle: &Puzzle,
+    side_of: &HashMap<i16, i16>,
+    slot: &[i16; 3],
+    axes: &[usize],
+) -> Vec<i16> {
+    let n = puzzle.n;
+    axes.iter()
+        .map(|&axis| {
+            let mut pos: Vec<i16> = slot.iter().map(|&x| x * (n - 1)).collect();
+            pos[axis] = slot[axis] * n;
+            side_of[&puzzle.sticker(&pos)]
+        })
+        .collect()
+}
+
+fn sorted(mut sides: Vec<i16>) -> Vec<i16> {
+    sides.sort();
+    sides
+}
+
+fn parity(perm: &[u8]) -> usize {
+    (0..perm.len())
+        .flat_map(|i| (i + 1..perm.len()).map(move |j| (i, j)))
+        .filter(|&(i, j)| perm[i] > perm[j])
+        .count()
+        % 2
+}
+
+const UNSOLVABLE: &str = "the pieces are in a state that turns can't solve";
+
+// the cube that the puzzle's pieces make, with the colors that the sides are being solved to.
+// a 2^3 only has corners, and its edges are left solved.
+fn read(puzzle: &Puzzle, face_colors: &HashMap<i16, i16>) -> Result<Cube, String> {
+    let side_of: HashMap<i16, i16> = face_colors
+        .iter()
+        .map(|(&side, &color)| (color, side))
+        .collect();
+    if side_of.len() != 6 {
+        return Err("two sides are being solved to the same color".to_string());
+    }
+    let slots = Slots::new();
+    let mut cube = Cube::solved();
+    for (i, slot) in slots.corners.iter().enumerate() {
+        let axes = corner_axes(slot);
+        let sides = sticker_sides(puzzle, &side_of, slot, &axes);
+        let home = slots
+            .corners
+            .iter()
+            .position(|home| {
+                sorted(sides.clone()) == sorted(axes.map(|a| slot_side(home, a)).to_vec())
+            })
+            .ok_or(UNSOLVABLE)?;
+        cube.cp[i] = home as u8;
+        cube.co[i] = sides
+            .iter()
+            .position(|&side| ax(side) == 1)
+            .ok_or(UNSOLVABLE)? as u8;
+    }
+    if puzzle.n == 3 {
+        for (i, slot) in slots.edges.iter().enumerate() {
+            let sides = sticker_sides(puzzle, &side_of, slot, &edge_axes(slot));
+            let home = slots
+                .edges
+                .iter()
+                .position(|home| {
+                    let mut home_sides: Vec<i16> =
+                        edge_axes(home).map(|a| slot_side(home, a)).to_vec();
+                    home_sides.sort();
+                    home_sides == sorted(sides.clone())
+                })
+                .ok_or(UNSOLVABLE)?;
+            let home_slot = &slots.edges[home];
+            cube.ep[i] = home as u8;
+            cube.eo[i] = (sides[0] != slot_side(home_slot, edge_axes(home_slot)[0])) as u8;
+        }
+    }
+
+    let mut corners = cube.cp.to_vec();
+    corners.sort();
+    let mut edges = cube.ep.to_vec();
+    edges.sort();
+    if corners != Cube::solved().cp
+        || edges != Cube::solved().ep
+        || cube.co.iter().sum::<u8>() % 3 != 0
+        || cube.eo.iter().sum::<u8>() % 2 != 0
+        || (puzzle.n == 3 && parity(&cube.cp) != parity(&cube.ep))
+    {
+        return Err(UNSOLVABLE.to_string());
+    }
+    Ok(cube)
+}
+
+// a move by number, turning the outer layer of a side
+fn make_turn(n: i16, m: usize) -> Turn {
+    let side = SIDES[m / 3];
+    let a = ax(side);
+    let (layer, from, to) = if side >= 0 {
+        (n - 1, (a + 2) % 3, (a + 1) % 3)
+    } else {
+        (1 - n, (a + 1) % 3, (a + 2) % 3)
+    };
+    let turn = Turn::Side(SideTurn {
+        side,
+        layer_min: layer,
+        layer_max: layer,
+        from,
+        to,
+    });
+    match m % 3 {
+        0 => turn,
+        1 => Turn::Repeat(Box::new(turn), 2),
+        _ => turn.inverse(),
+    }
+}
+
+// what each move does, found by making it on a solved puzzle
+fn move_cubes(n: i16) -> Vec<Cube> {
+    let face_colors: HashMap<i16, i16> = SIDES.iter().map(|&side| (side, side)).collect();
+    (0..18)
+        .map(|m| {
+            let mut puzzle = Puzzle::make_solved(n, 3);
+            puzzle
+                .turn(make_turn(n, m))
+                .expect("outer turns can be made");
+            read(&puzzle, &face_colors).expect("a turned puzzle can be read")
+        })
+        .collect()
+}
+
+fn twist(cube: &Cube) -> usize {
+    cube.co[..7]
+        .iter()
+        .rev()
+        .fold(0, |t, &o| t * 3 + o as usize)
+}
+
+fn flip(cube: &Cube) -> usize {
+    cube.eo[..11]
+        .iter()
+        .rev()
+        .fold(0, |f, &o| f * 2 + o as usize)
+}
+
+fn choose(n: usize, k: usize) -> usize {
+    (0..k).fold(1, |c, i| c * (n - i) / (i + 1))
+}
+
+// which four slots the edges between U and D are in
+fn slice(cube: &Cube) -> usize {
+    let mut k = 0;
+    let mut rank = 0;
+    for (i, &piece) in cube.ep.iter().enumerate() {
+        if piece >= 8 {
+            k += 1;
+            rank += choose(i, k);
+        }
+    }
+    rank
+}
+
+// the position of the permutation in order, which is 0 for the pieces in order
+fn rank(perm: &[u8]) -> usize {
+    (0..perm.len()).fold(0, |rank, i| {
+        rank * (perm.len() - i) + perm[i + 1..].iter().filter(|&&x| x < perm[i]).count()
+    })
+}
+
+fn corner_perm(cube: &Cube) -> usize {
+    rank(&cube.cp)
+}
+
+fn edge_perm(cube: &Cube) -> usize {
+    rank(&cube.ep[..8])
+}
+
+fn slice_perm(cube: &Cube) -> usize {
+    rank(&cube.ep[8..])
+}
+
+// the value of a coordinate that each move takes each value to, found by making the moves from
+// the solved cube until no new values turn up. values that can't be reached are left empty.
+fn move_table(moves: &[Cube], size: usize, coord: impl Fn(&Cube) -> usize) -> Vec<Vec<u16>> {
+    let mut table = vec![vec![]; size];
+    let mut queued = vec![false; size];
+    let solved = Cube::solved();
+    queued[coord(&solved)] = true;
+    let mut queue = std::collections::VecDeque::from([solved]);
+    while let Some(cube) = queue.pop_front() {
+        let value = coord(&cube);
+        table[value] = moves
+            .iter()
+            .map(|m| {
+                let next = cube.then(m);
+                let next_value = coord(&next);
+                if !queued[next_value] {
+                    queued[next_value] = true;
+                    queue.push_back(next);
+                }
+                next_value as u16
+            })
+            .collect();
+    }
+    table
+}
+
+// the fewest moves from each pair of values of two coordinates to the pair that the solved cube
+// has, which is never more than the moves to solve a cube with those values
+fn prune_table(a: &[Vec<u16>], b: &[Vec<u16>], goal: (usize, usize)) -> Vec<u8> {
+    let mut dist = vec![u8::MAX; a.len() * b.len()];
+    dist[goal.0 * b.len() + goal.1] = 0;
+    let mut frontier = vec![goal];
+    let mut depth = 0;
+    while !frontier.is_empty() {
+        depth += 1;
+        let mut next = vec![];
+        for (x, y) in frontier {
+            for (&nx, &ny) in a[x].iter().zip(&b[y]) {
+                let (nx, ny) = (nx as usize, ny as usize);
+                if dist[nx * b.len() + ny] == u8::MAX {
+                    dist[nx * b.len() + ny] = depth;
+                    next.push((nx, ny));
+                }
+            }
+        }
+        frontier = next;
+    }
+    dist
+}
+
+// whether a move can follow the last one: turning the same side twice in a row is one move, and
+// turns of opposite sides are only tried in one order
+fn follows(last: Option<usize>, m: usize) -> bool {
+    let Some(last) = last else {
+        return true;
+    };
+    let (side, last_side) = (m / 3, last / 3);
+    side != last_side && !(side / 2 == last_side / 2 && side < last_side)
+}
+
+struct CubeTables {
+    moves: Vec<Cube>,
+    twist: Vec<Vec<u16>>,
+    flip: Vec<Vec<u16>>,
+    slice: Vec<Vec<u16>>,
+    corner_perm: Vec<Vec<u16>>,
+    edge_perm: Vec<Vec<u16>>,
+    slice_perm: Vec<Vec<u16>>,
+    twist_slice: Vec<u8>,
+    flip_slice: Vec<u8>,
+    corner_slice: Vec<u8>,
+    edge_slice: Vec<u8>,
+}
+
+impl CubeTables {
+    fn new() -> Self {
+        let moves = move_cubes(3);
+        let phase_2: Vec<Cube> = PHASE_2.iter().map(|&m| moves[m]).collect();
+        let twist = move_table(&moves, 2187, twist);
+        let flip = move_table(&moves, 2048, flip);
+        let slice = move_table(&moves, 495, slice);
+        let corner_perm = move_table(&phase_2, 40320, corner_perm);
+        let edge_perm = move_table(&phase_2, 40320, edge_perm);
+        let slice_perm = move_table(&phase_2, 24, slice_perm);
+        let solved_slice = self::slice(&Cube::solved());
+        CubeTables {
+            twist_slice: prune_table(&twist, &slice, (0, solved_slice)),
+            flip_slice: prune_table(&flip, &slice, (0, solved_slice)),
+            corner_slice: prune_table(&corner_perm, &slice_perm, (0, 0)),
+            edge_slice: prune_table(&edge_perm, &slice_perm, (0, 0)),
+            moves,
+            twist,
+            flip,
+            slice,
+            corner_perm,
+            edge_perm,
+            slice_perm,
+        }
+    }
+
+    fn solve(&self, cube: &Cube) -> Option<Vec<usize>> {
+        let start = (twist(cube), flip(cube), slice(cube));
+        (0..=MAX_PHASE_1).find_map(|depth| self.phase_1(cube, start, depth, &mut vec![]))
+    }
+
+    // turn every corner and edge to its home orientation, and the edges between U and D into
+    // their layer, in exactly depth moves, and then solve the rest without undoing that
+    fn phase_1(
+        &self,
+        cube: &Cube,
+        (tw, fl, sl): (usize, usize, usize),
+        depth: usize,
+        path: &mut Vec<usize>,
+    ) -> Option<Vec<usize>> {
+        let bound = self.twist_slice[tw * 495 + sl].max(self.flip_slice[fl * 495 + sl]) as usize;
+        if bound > depth {
+            return None;
+        }
+        if depth == 0 {
+            // ending with a move of the second phase would have been found with fewer moves
+            if path.last().is_some_and(|m| PHASE_2.contains(m)) {
+                return None;
+            }
+            let mut cube = *cube;
+            for &m in path.iter() {
+                cube = cube.then(&self.moves[m]);
+            }
+            let start = (corner_perm(&cube), edge_perm(&cube), slice_perm(&cube));
+            let rest = (0..=MAX_PHASE_2)
+                .find_map(|depth| self.phase_2(start, depth, path.last().copied(), &mut vec![]))?;
+            return Some([path.clone(), rest].concat());
+        }
+        for m in 0..18 {
+            if !follows(path.last().copied(), m) {
+                continue;
+            }
+            path.push(m);
+            let next = (
+                self.twist[tw][m] as usize,
+                self.flip[fl][m] as usize,
+                self.slice[sl][m] as usize,
+            );
+            let found = self.phase_1(cube, next, depth - 1, path);
+            path.pop();
+            if found.is_some() {
+                return found;
+            }
+        }
+        None
+    }
+
+    // solve a cube that only needs the moves of the second phase, in exactly depth moves
+    fn phase_2(
+        &self,
+        (cp, ep, sp): (usize, usize, usize),
+        depth: usize,
+        last: Option<usize>,
+        path: &mut Vec<usize>,
+    ) -> Option<Vec<usize>> {
+        let bound = self.corner_slice[cp * 24 + sp].max(self.edge_slice[ep * 24 + sp]) as usize;
+        if bound > depth {
+            return None;
+        }
+        if depth == 0 {
+            return Some(path.clone());
+        }
+        for (j, &m) in PHASE_2.iter().enumerate() {
+            if !follows(path.last().copied().or(last), m) {
+                continue;
+            }
+            path.push(m);
+            let next = (
+                self.corner_perm[cp][j] as usize,
+                self.edge_perm[ep][j] as usize,
+                self.slice_perm[sp][j] as usize,
+            );
+            let found = self.phase_2(next, depth - 1, last, path);
+            path.pop();
+            if found.is_some() {
+                return found;
+            }
+        }
+        None
+    }
+}
+
+struct CornerTables {
+    twist: Vec<Vec<u16>>,
+    corner_perm: Vec<Vec<u16>>,
+    // how far each value of one coordinate is from solved, paired with a coordinate that never
+    // changes
+    twist_dist: Vec<u8>,
+    corner_dist: Vec<u8>,
+}
+
+impl CornerTables {
+    fn new() -> Self {
+        let all = move_cubes(2);
+        let moves: Vec<Cube> = CORNER_MOVES.iter().map(|&m| all[m]).collect();
+        let twist = move_table(&moves, 2187, twist);
+        let corner_perm = move_table(&moves, 40320, corner_perm);
+        let unchanged = vec![vec![0; moves.len()]];
+        CornerTables {
+            twist_dist: prune_table(&twist, &unchanged, (0, 0)),
+            corner_dist: prune_table(&corner_perm, &unchanged, (0, 0)),
+            twist,
+            corner_perm,
+        }
+    }
+
+    fn solve(&self, cube: &Cube) -> Option<Vec<usize>> {
+        let start = (twist(cube), corner_perm(cube));
+        (0..=MAX_CORNERS).find_map(|depth| self.search(start, depth, &mut vec![]))
+    }
+
+    fn search(
+        &self,
+        (tw, cp): (usize, usize),
+        depth: usize,
+        path: &mut Vec<usize>,
+    ) -> Option<Vec<usize>> {
+        if self.twist_dist[tw].max(self.corner_dist[cp]) as usize > depth {
+            return None;
+        }
+        if depth == 0 {
+            return Some(path.clone());
+        }
+        for (j, &m) in CORNER_MOVES.iter().enumerate() {
+            if !follows(path.last().copied(), m) {
+                continue;
+            }
+            path.push(m);
+            let next = (self.twist[tw][j] as usize, self.corner_perm[cp][j] as usize);
+            let found = self.search(next, depth - 1, path);
+            path.pop();
+            if found.is_some() {
+                return found;
+            }
+        }
+        None
+    }
+}
+
+// the tables take a moment to make, so they are only made the first time they are needed
+static CUBE_TABLES: OnceLock<CubeTables> = OnceLock::new();
+static CORNER_TABLES: OnceLock<CornerTables> = OnceLock::new();
+
+// the colors each side is being solved to: the centers of a 3^3, or on a 2^3, the DBL corner
+// and the colors opposite it, since only R, U, and F turn
+fn face_colors(puzzle: &Puzzle) -> HashMap<i16, i16> {
+    let n = puzzle.n;
+    let mut face_colors = HashMap::new();
+    for axis in 0..3 {
+        let mut pos = if n == 3 { vec![0; 3] } else { vec![1 - n; 3] };
+        pos[axis] = -n;
+        let color = puzzle.sticker(&pos);
+        if n == 3 {
+            pos[axis] = n;
+            face_colors.insert(axis as i16, puzzle.sticker(&pos));
+        } else {
+            face_colors.insert(axis as i16, !color);
+        }
+        face_colors.insert(!(axis as i16), color);
+    }
+    face_colors
+}
+
+// the turns of outer layers that solve the puzzle, for a 2^3 or 3^3 that isn't a supercube or
+// from a definition file
+pub fn solve(puzzle: &Puzzle) -> Result<Vec<Turn>, String> {
+    if !puzzle.has_random_state() {
+        return Err("only 2^3 and 3^3 puzzles can be solved".to_string());
+    }
+    if puzzle.is_solved() {
+        return Ok(vec![]);
+    }
+    let cube = read(puzzle, &face_colors(puzzle))?;
+    let moves = if puzzle.n == 3 {
+        CUBE_TABLES.get_or_init(CubeTables::new).solve(&cube)
+    } else {
+        CORNER_TABLES.get_or_init(CornerTables::new).solve(&cube)
+    };
+    let moves = moves.ok_or("no solution was found")?;
+    Ok(moves.into_iter().map(|m| make_turn(puzzle.n, m)).collect())
+}
@@ -0,0 +1,47 @@
+use crossterm::{cursor, event, terminal, ExecutableCommand};
+use std::io;
+
+// puts the terminal into the state the app draws in, and puts it back when dropped or when the
+// program panics, so that messages printed afterwards end up on the normal screen
+pub struct StdoutManager {
+    alt_screen: bool,
+}
+
+fn restore(alt_screen: bool) -> io::Result<()> {
+    let mut stdout = io::stdout();
+    stdout.execute(event::DisableMouseCapture)?;
+    stdout.execute(cursor::Show)?;
+    if alt_screen {
+        stdout.execute(terminal::LeaveAlternateScreen)?;
+    }
+    terminal::disable_raw_mode()
+}
+
+impl StdoutManager {
+    // with alt_screen false, the app draws over the normal screen, which is kept after exiting
+    pub fn enter(alt_screen: bool) -> io::Result<Self> {
+        let default_hook = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            let _ = restore(alt_screen);
+            default_hook(info);
+        }));
+
+        let mut stdout = io::stdout();
+        terminal::enable_raw_mode()?;
+        if alt_screen {
+            stdout.execute(terminal::EnterAlternateScreen)?;
+        } else {
+            stdout.execute(terminal::Clear(terminal::ClearType::All))?;
+        }
+        stdout.execute(cursor::Hide)?;
+        stdout.execute(event::EnableMouseCapture)?;
+        Ok(StdoutManager { alt_screen })
+    }
+}
+
+impl Drop for StdoutManager {
+    fn drop(&mut self) {
+        let _ = restore(self.alt_screen);
+        let _ = std::panic::take_hook();
+    }
+}
@@ -0,0 +1,108 @@
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Copy)]
+enum TimerState {
+    // there is no scramble to time
+    Off,
+    // scrambled, waiting for the first turn
+    Ready,
+    Inspecting(Instant),
+    Running(Instant),
+    Stopped(Duration),
+}
+
+#[derive(Clone)]
+pub struct Timer {
+    inspection: Duration,
+    state: TimerState,
+}
+
+pub fn format_duration(duration: Duration) -> String {
+    let centis = duration.as_millis() / 10;
+    let (minutes, seconds, centis) = (centis / 6000, centis / 100 % 60, centis % 100);
+    if minutes > 0 {
+        format!("{minutes}:{seconds:02}.{centis:02}")
+    } else {
+        format!("{seconds}.{centis:02}")
+    }
+}
+
+impl Timer {
+    pub fn new(inspection: Duration) -> Self {
+        Timer {
+            inspection,
+            state: TimerState::Off,
+        }
+    }
+
+    // inspection that runs out starts the solve
+    fn current(&self) -> TimerState {
+        match self.state {
+            TimerState::Inspecting(start) if start.elapsed() >= self.inspection => {
+                TimerState::Running(start + self.inspection)
+            }
+            state => state,
+        }
+    }
+
+    // the puzzle was just scrambled
+    pub fn arm(&mut self) {
+        self.state = if self.inspection.is_zero() {
+            TimerState::Ready
+        } else {
+            TimerState::Inspecting(Instant::now())
+        };
+    }
+
+    pub fn disarm(&mut self) {
+        self.state = TimerState::Off;
+    }
+
+    // a turn was made
+    pub fn start(&mut self) {
+        self.state = match self.current() {
+            TimerState::Ready | TimerState::Inspecting(_) => TimerState::Running(Instant::now()),
+            state => state,
+        };
+    }
+
+    // the puzzle was solved
+    pub fn stop(&mut self) {
+        if let TimerState::Running(start) = self.current() {
+            self.state = TimerState::Stopped(start.elapsed());
+        }
+    }
+
+    pub fn final_time(&self) -> Option<Duration> {
+        match self.state {
+            TimerState::Stopped(time) => Some(time),
+            _ => None,
+        }
+    }
+
+    // the time so far, or the final time
+    pub fn elapsed(&self) -> Option<Duration> {
+        match self.current() {
+            TimerState::Running(start) => Some(start.elapsed()),
+            TimerState::Stopped(time) => Some(time),
+            _ => None,
+        }
+    }
+
+    pub fn set_final_time(&mut self, time: Duration) {
+        self.state = TimerState::Stopped(time);
+    }
+
+    pub fn display(&self) -> Option<String> {
+        match self.current() {
+            TimerState::Off => None,
+            TimerState::Ready => Some(format_duration(Duration::ZERO)),
+            TimerState::Inspecting(start) => Some(format!(
+                "inspection {}",
+                self.inspection.saturating_sub(start.elapsed()).as_secs() + 1
+            )),
+            TimerState::Running(start) => Some(format_duration(start.elapsed())),
+            TimerState::Stopped(time) => Some(format_duration(time)),
+        }
+    }
+}